@@ -0,0 +1,64 @@
+/* Compare matching with and without the dense ASCII transition table of the DFA */
+
+use lexviz::dfa::DFA;
+use lexviz::{construct_dfa, construct_minimal_dfa, construct_nfa, parse_microsyntax_list};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ROUNDS: usize = 20;
+
+fn build_dfa() -> DFA {
+    let regex_list = vec![
+        (
+            "[a-zA-Z_][a-zA-Z0-9_]*".to_string(),
+            "IDENTIFIER".to_string(),
+        ),
+        ("[0-9]+(\\.[0-9]+)?".to_string(), "NUMBER".to_string()),
+        ("( |\\n|\\t)+".to_string(), "WHITESPACE".to_string()),
+        (
+            "\\+|-|\\*|/|=|==|!=|<|<=|>|>=".to_string(),
+            "OPERATOR".to_string(),
+        ),
+    ];
+    let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+    let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+    construct_minimal_dfa(&construct_dfa(&nfa, false).unwrap(), false)
+}
+
+// Match every token of the input, returning how long the fastest round took
+fn time_scan(dfa: &DFA, input: &str) -> Duration {
+    let mut fastest = Duration::MAX;
+
+    for _ in 0..ROUNDS {
+        let begin = Instant::now();
+        let mut offset = 0;
+        while let Some(span) = dfa.longest_match(input, offset) {
+            if span.is_empty() {
+                break;
+            }
+            offset = span.end;
+        }
+        black_box(offset);
+        fastest = fastest.min(begin.elapsed());
+    }
+
+    fastest
+}
+
+fn main() {
+    let input = "count = count + 1\tvalue_2 >= 3.14\n".repeat(20_000);
+
+    let mut dfa = build_dfa();
+    let hashed = time_scan(&dfa, &input);
+
+    dfa.build_ascii_table();
+    let dense = time_scan(&dfa, &input);
+
+    println!("{} bytes, fastest of {} rounds", input.len(), ROUNDS);
+    println!("transition maps: {:?}", hashed);
+    println!("ascii table:     {:?}", dense);
+    println!(
+        "speedup:         {:.2}x",
+        hashed.as_secs_f64() / dense.as_secs_f64()
+    );
+}
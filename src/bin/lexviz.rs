@@ -1,8 +1,9 @@
 use clap::{Arg, Command};
 use color_eyre::eyre::{Report, Result};
+use lexviz::regex::RuleFlags;
 use lexviz::{
-    construct_dfa, construct_minimal_dfa, construct_nfa, construct_scanner, parse_microsyntax_list,
-    read_microsyntax_file, visualize, LexerError,
+    analyze_conflicts, construct_dfa, construct_minimal_dfa, construct_nfa, construct_scanner,
+    parse_microsyntax_list, read_microsyntax_files, visualize, LexerError,
 };
 
 fn main() -> Result<()> {
@@ -43,9 +44,10 @@ fn main() -> Result<()> {
                             Arg::new("microsyntax-file")
                                 .short('f')
                                 .long("microsyntax-file")
-                                .help("Provide a file with a list of regular expressions and the corresponsing syntactic category name. The order of the list determines the priority of the regular expressions during token scanning")
+                                .help("Provide a file with a list of regular expressions and the corresponsing syntactic category name. The order of the list determines the priority of the regular expressions during token scanning. Can be repeated, earlier files have a higher priority")
                                 .value_name("MICROSYNTAX FILE")
                                 .value_parser(clap::value_parser!(String))
+                                .action(clap::ArgAction::Append)
                         )
                         .arg(
                             Arg::new("input")
@@ -82,6 +84,13 @@ fn main() -> Result<()> {
                             .value_parser(clap::value_parser!(String))
                             .num_args(1)
                         )
+                        .arg(
+                            Arg::new("check-conflicts")
+                            .short('c')
+                            .long("check-conflicts")
+                            .help("Warn about microsyntaxes which can never produce a token because an earlier microsyntax accepts the same strings")
+                            .action(clap::ArgAction::SetTrue)
+                        )
                         .arg(
                             Arg::new("skip-categories")
                             .short('s')
@@ -95,16 +104,21 @@ fn main() -> Result<()> {
                         )
                         .get_matches();
 
-    let mut regex_list: Vec<(String, String)> = Vec::new();
+    let mut regex_list: Vec<(String, String, RuleFlags)> = Vec::new();
 
-    if let Some(mst_file_path) = args.get_one::<String>("microsyntax-file") {
-        let rlist = read_microsyntax_file(mst_file_path.to_string())?;
+    if let Some(mst_file_paths) = args.get_many::<String>("microsyntax-file") {
+        let mst_file_paths: Vec<String> = mst_file_paths.cloned().collect();
+        let rlist = read_microsyntax_files(&mst_file_paths)?;
         regex_list = rlist;
     } else if let Some(values) = args.get_occurrences::<String>("microsyntax") {
         for value_group in values {
             let value_vec: Vec<_> = value_group.collect();
             if value_vec.len() == 2 {
-                regex_list.push((value_vec[0].to_string(), value_vec[1].to_string()));
+                regex_list.push((
+                    value_vec[0].to_string(),
+                    value_vec[1].to_string(),
+                    RuleFlags::default(),
+                ));
             } else {
                 let err = Report::new(LexerError::RegexCategoryError);
                 return Err(err);
@@ -137,6 +151,8 @@ fn main() -> Result<()> {
 
     let save_minimal_dfa = args.get_flag("save-minimal-dfa");
 
+    let check_conflicts = args.get_flag("check-conflicts");
+
     let skip_whitespace = args
         .get_one::<bool>("skip-whitespace")
         .copied()
@@ -162,12 +178,27 @@ fn main() -> Result<()> {
 
     let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
 
+    if check_conflicts {
+        for conflict in analyze_conflicts(&syntax_tree_list) {
+            if conflict.shadowed {
+                eprintln!(
+                    "Warning: The microsyntax {} for {} can never match, it is shadowed by {} for {}",
+                    conflict.regex, conflict.category, conflict.other_regex, conflict.other_category
+                );
+            }
+        }
+    }
+
     let nfa = construct_nfa(syntax_tree_list, save_nfa).unwrap();
 
-    let dfa = construct_dfa(&nfa, save_dfa);
+    let dfa = construct_dfa(&nfa, save_dfa).unwrap();
     let minimal_dfa = construct_minimal_dfa(&dfa, save_minimal_dfa);
 
-    let scanner = construct_scanner(&minimal_dfa);
+    let scanner = construct_scanner(&minimal_dfa)?;
+
+    for warning in scanner.get_warnings() {
+        eprintln!("{}", warning);
+    }
 
     let token_list = scanner
         .scan(
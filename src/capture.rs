@@ -0,0 +1,396 @@
+/* Report the spans matched by the groups of a regular expression using a tagged NFA */
+
+use crate::dfa::is_word_char;
+use crate::nfa::{default_universe, unescape_char, NFAError};
+use crate::regex::{parse_microsyntax_list, Base, Factor, Quantifier, RegEx, Term};
+use color_eyre::eyre::{Report, Result};
+use std::collections::HashSet;
+use std::ops::Range;
+
+// The zero width assertions, checked against the characters around the current position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Assertion {
+    Start,
+    End,
+    WordBoundary,
+}
+
+// An instruction of the tagged NFA. Every instruction except Jump, Split and Match continues with
+// the one after it. Split prefers its first target, which makes the quantifiers greedy.
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(HashSet<char>),
+    Split(usize, usize),
+    Jump(usize),
+    Save(usize), // Record the position in a tag slot, the group boundaries
+    Assert(Assertion),
+    Match,
+}
+
+/// A regular expression compiled to a tagged NFA, which records the positions where every group
+/// opens and closes while matching. A DFA can not tell which part of the input a group matched, so
+/// this is kept apart from the DFA pipeline and used when the sub-fields of a token are needed.
+///
+/// Groups are numbered from 1 in the order of their opening parentheses, and group 0 is the whole
+/// match. Like the scanner, the longest match wins. Among the ways of matching it, quantifiers are
+/// greedy and alternation prefers its left branch.
+#[derive(Debug, Clone)]
+pub struct CaptureRegex {
+    program: Vec<Inst>,
+    num_groups: usize,
+}
+
+impl CaptureRegex {
+    /// Compile the regular expression, resolving negated character sets and '.' against the default
+    /// universe of printable ASCII characters
+    pub fn new(pattern: &str) -> Result<CaptureRegex> {
+        CaptureRegex::with_universe(pattern, &default_universe())
+    }
+
+    /// Compile the regular expression like new, resolving negated character sets and '.' against
+    /// the provided universe of characters
+    pub fn with_universe(pattern: &str, universe: &HashSet<char>) -> Result<CaptureRegex> {
+        let regex_list = vec![(pattern.to_string(), String::new())];
+        let mut syntax_tree_list = parse_microsyntax_list(regex_list)?;
+        let (_, syntax_tree, _, _) = syntax_tree_list.pop_front().unwrap();
+
+        let mut compiler = Compiler {
+            program: Vec::new(),
+            num_groups: 1,
+            universe,
+        };
+
+        compiler.program.push(Inst::Save(0));
+        compiler.compile_regex(&syntax_tree)?;
+        compiler.program.push(Inst::Save(1));
+        compiler.program.push(Inst::Match);
+
+        Ok(CaptureRegex {
+            program: compiler.program,
+            num_groups: compiler.num_groups,
+        })
+    }
+
+    /// Get the number of groups, counting the whole match as group 0
+    pub fn get_num_groups(&self) -> usize {
+        self.num_groups
+    }
+
+    /// Match the longest prefix of the input starting at the byte offset start, and return the
+    /// byte range of every group. The range of a group which took no part in the match, like the
+    /// branch of an alternation which was not taken, is None. Returns None if there is no match or
+    /// if the start offset is past the end of the input or not on a character boundary.
+    pub fn captures(&self, input: &str, start: usize) -> Option<Vec<Option<Range<usize>>>> {
+        if !input.is_char_boundary(start) {
+            return None;
+        }
+
+        let mut current: Vec<Thread> = Vec::new();
+        let mut next: Vec<Thread> = Vec::new();
+        let mut best: Option<Vec<Option<usize>>> = None;
+
+        let slots = vec![None; self.num_groups * 2];
+        self.add_thread(&mut current, 0, slots, input, start);
+
+        let mut position = start;
+        loop {
+            // Threads are in priority order, so the first one to match wins at this position
+            if let Some(thread) = current
+                .iter()
+                .find(|thread| matches!(self.program[thread.pc], Inst::Match))
+            {
+                best = Some(thread.slots.clone());
+            }
+
+            let ch = match input[position..].chars().next() {
+                Some(ch) => ch,
+                None => break,
+            };
+            let next_position = position + ch.len_utf8();
+
+            for thread in current.drain(..) {
+                if let Inst::Char(char_set) = &self.program[thread.pc] {
+                    if char_set.contains(&ch) {
+                        self.add_thread(
+                            &mut next,
+                            thread.pc + 1,
+                            thread.slots,
+                            input,
+                            next_position,
+                        );
+                    }
+                }
+            }
+
+            if next.is_empty() {
+                break;
+            }
+
+            std::mem::swap(&mut current, &mut next);
+            position = next_position;
+        }
+
+        let slots = best?;
+        let groups = (0..self.num_groups)
+            .map(|group| match (slots[group * 2], slots[group * 2 + 1]) {
+                (Some(open), Some(close)) => Some(open..close),
+                _ => None,
+            })
+            .collect();
+        Some(groups)
+    }
+
+    // Add the thread to the list, following the instructions which consume no input. A thread for
+    // an instruction already in the list is dropped, as the earlier one has a higher priority.
+    fn add_thread(
+        &self,
+        list: &mut Vec<Thread>,
+        pc: usize,
+        mut slots: Vec<Option<usize>>,
+        input: &str,
+        position: usize,
+    ) {
+        if list.iter().any(|thread| thread.pc == pc) {
+            return;
+        }
+
+        match &self.program[pc] {
+            Inst::Jump(target) => self.add_thread(list, *target, slots, input, position),
+            Inst::Split(first, second) => {
+                // Mark the split as visited so that an empty loop does not recurse forever
+                list.push(Thread {
+                    pc,
+                    slots: Vec::new(),
+                });
+                self.add_thread(list, *first, slots.clone(), input, position);
+                self.add_thread(list, *second, slots, input, position);
+            }
+            Inst::Save(slot) => {
+                list.push(Thread {
+                    pc,
+                    slots: Vec::new(),
+                });
+                slots[*slot] = Some(position);
+                self.add_thread(list, pc + 1, slots, input, position);
+            }
+            Inst::Assert(assertion) => {
+                list.push(Thread {
+                    pc,
+                    slots: Vec::new(),
+                });
+                if assertion_holds(*assertion, input, position) {
+                    self.add_thread(list, pc + 1, slots, input, position);
+                }
+            }
+            Inst::Char(_) | Inst::Match => list.push(Thread { pc, slots }),
+        }
+    }
+}
+
+// A position in the tagged NFA along with the tags recorded on the way there
+#[derive(Debug)]
+struct Thread {
+    pc: usize,
+    slots: Vec<Option<usize>>,
+}
+
+// Check a zero width assertion at the byte offset of the input
+fn assertion_holds(assertion: Assertion, input: &str, position: usize) -> bool {
+    match assertion {
+        Assertion::Start => position == 0,
+        Assertion::End => position == input.len(),
+        Assertion::WordBoundary => {
+            let word_before = input[..position]
+                .chars()
+                .next_back()
+                .is_some_and(is_word_char);
+            let word_after = input[position..].chars().next().is_some_and(is_word_char);
+            word_before != word_after
+        }
+    }
+}
+
+// Build the instructions of the tagged NFA from the syntax tree, following the same structure as
+// Thompson construction
+struct Compiler<'a> {
+    program: Vec<Inst>,
+    num_groups: usize,
+    universe: &'a HashSet<char>,
+}
+
+impl Compiler<'_> {
+    fn compile_regex(&mut self, tree: &RegEx) -> Result<()> {
+        match tree {
+            RegEx::SimpleRegex(term) => self.compile_term(term),
+            RegEx::AlterRegex(lterm, rregex) => {
+                let split = self.placeholder();
+                self.compile_term(lterm)?;
+                let jump = self.placeholder();
+                let right = self.program.len();
+                self.compile_regex(rregex)?;
+                let end = self.program.len();
+
+                self.program[split] = Inst::Split(split + 1, right);
+                self.program[jump] = Inst::Jump(end);
+                Ok(())
+            }
+        }
+    }
+
+    fn compile_term(&mut self, tree: &Term) -> Result<()> {
+        match tree {
+            Term::SimpleTerm(factor) => self.compile_factor(factor),
+            // The last factor is the outermost one, so the rest of the term comes first
+            Term::ConcatTerm(rfactor, lterm) => {
+                self.compile_term(lterm)?;
+                self.compile_factor(rfactor)
+            }
+        }
+    }
+
+    fn compile_factor(&mut self, tree: &Factor) -> Result<()> {
+        let Factor::SimpleFactor(base, quantifier) = tree;
+
+        match quantifier {
+            None => self.compile_base(base),
+            Some(Quantifier::Star) => {
+                let split = self.placeholder();
+                self.compile_base(base)?;
+                self.program.push(Inst::Jump(split));
+                let end = self.program.len();
+                self.program[split] = Inst::Split(split + 1, end);
+                Ok(())
+            }
+            Some(Quantifier::Plus) => {
+                let body = self.program.len();
+                self.compile_base(base)?;
+                let split = self.program.len();
+                self.program.push(Inst::Split(body, split + 1));
+                Ok(())
+            }
+            Some(Quantifier::Question) => {
+                let split = self.placeholder();
+                self.compile_base(base)?;
+                let end = self.program.len();
+                self.program[split] = Inst::Split(split + 1, end);
+                Ok(())
+            }
+        }
+    }
+
+    fn compile_base(&mut self, tree: &Base) -> Result<()> {
+        let char_set = match tree {
+            Base::Character(ch) => HashSet::from([*ch]),
+            Base::EscapeCharacter(ch) => HashSet::from([unescape_char(*ch)?]),
+            Base::CharSet(char_set) => char_set.clone(),
+            Base::NegatedCharSet(char_set) => self.universe.difference(char_set).cloned().collect(),
+            // Following the usual convention, '.' matches anything except a newline
+            Base::AnyChar => self
+                .universe
+                .iter()
+                .filter(|ch| **ch != '\n')
+                .cloned()
+                .collect(),
+            Base::Exp(regex) => {
+                let group = self.num_groups;
+                self.num_groups += 1;
+
+                self.program.push(Inst::Save(group * 2));
+                self.compile_regex(regex)?;
+                self.program.push(Inst::Save(group * 2 + 1));
+                return Ok(());
+            }
+            Base::StartAnchor => {
+                self.program.push(Inst::Assert(Assertion::Start));
+                return Ok(());
+            }
+            Base::EndAnchor => {
+                self.program.push(Inst::Assert(Assertion::End));
+                return Ok(());
+            }
+            Base::WordBoundary => {
+                self.program.push(Inst::Assert(Assertion::WordBoundary));
+                return Ok(());
+            }
+            Base::Empty => return Ok(()),
+            Base::NamedClass(name) => {
+                let err = Report::new(NFAError::UndefinedMacro(name.clone()));
+                return Err(err);
+            }
+        };
+
+        if char_set.is_empty() {
+            let err = Report::new(NFAError::EmptyCharacterSet);
+            return Err(err);
+        }
+
+        self.program.push(Inst::Char(char_set));
+        Ok(())
+    }
+
+    // Reserve an instruction whose targets are only known once the instructions after it are built
+    fn placeholder(&mut self) -> usize {
+        self.program.push(Inst::Match);
+        self.program.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod capture_tests {
+    use super::*;
+
+    #[test]
+    fn test_captures() {
+        let regex = CaptureRegex::new("(a+)b").unwrap();
+        assert_eq!(regex.get_num_groups(), 2);
+        assert_eq!(
+            regex.captures("aaab", 0),
+            Some(vec![Some(0..4), Some(0..3)])
+        );
+        assert_eq!(
+            regex.captures("xaab", 1),
+            Some(vec![Some(1..4), Some(1..3)])
+        );
+        assert_eq!(regex.captures("aaa", 0), None);
+        assert_eq!(regex.captures("ab", 3), None);
+        assert_eq!(regex.captures("éab", 1), None);
+
+        // Groups are numbered by their opening parentheses and the untaken branch has no span
+        let regex = CaptureRegex::new("((x)|(y))+=([0-9]+)").unwrap();
+        assert_eq!(
+            regex.captures("xy=42;", 0),
+            Some(vec![
+                Some(0..5),
+                Some(1..2),
+                Some(0..1),
+                Some(1..2),
+                Some(3..5)
+            ])
+        );
+        let regex = CaptureRegex::new("(x)|(y)").unwrap();
+        assert_eq!(
+            regex.captures("y", 0),
+            Some(vec![Some(0..1), None, Some(0..1)])
+        );
+
+        // The longest match wins and the greedy star takes as much as it can
+        let regex = CaptureRegex::new("([a-z]*)([a-z0-9]*)").unwrap();
+        assert_eq!(
+            regex.captures("ab12", 0),
+            Some(vec![Some(0..4), Some(0..2), Some(2..4)])
+        );
+
+        // Empty loops terminate
+        let regex = CaptureRegex::new("(a*)*b").unwrap();
+        assert_eq!(regex.captures("aab", 0).unwrap()[0], Some(0..3));
+
+        let regex = CaptureRegex::new("^(\\(.*\\))\\b").unwrap();
+        assert!(regex.captures("(a)", 0).is_none());
+        assert_eq!(
+            regex.captures("(a)b", 0),
+            Some(vec![Some(0..3), Some(0..3)])
+        );
+
+        assert!(CaptureRegex::new("(a").is_err());
+    }
+}
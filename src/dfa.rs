@@ -1,16 +1,26 @@
 /* Perform subset construction to convert NFA into DFA
 * Apply Hopcroft's algorithm to generate minimal DFA */
 
-use crate::fa::{Symbol, FA};
-use crate::nfa::NFA;
+use crate::fa::{
+    dot_command_args, is_assertion_marker, raw_byte, FaImageFormat, Symbol, ASSERTION_MARKERS,
+    END_ANCHOR, FA, START_ANCHOR, WORD_BOUNDARY,
+};
+use crate::nfa::{construct_nfa, construct_nfa_with_universe, default_universe, NFA};
+use crate::regex::{
+    build_syntax_tree, escape_literal, number_literal_regex, parse_microsyntax_list, Base, Factor,
+    Radix, RegEx, RuleFlags, Term,
+};
 use bitvec::prelude::*;
+use color_eyre::eyre::Result;
 use petgraph::dot::Dot;
 use petgraph::graph::DiGraph;
 use std::collections::hash_map::Values;
+use std::collections::BTreeSet;
 use std::collections::VecDeque;
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Write;
+use std::ops::Range;
 use std::process::Command;
 
 #[derive(Debug)]
@@ -20,6 +30,25 @@ pub struct DFA {
     accept_states: BitVec<u8>,
     alphabet: HashSet<char>,
     regex: String,
+    // Mapping of category and the regex of its microsyntaxes
+    category_regexes: HashMap<String, String>,
+    // Categories in the order their first microsyntax was declared
+    category_order: Vec<String>,
+    // Categories of the microsyntaxes with the s flag, whose tokens the scanner skips
+    skip_categories: HashSet<String>,
+    // Mapping of DFA state to the NFA state set it was built from
+    nfa_state_sets: HashMap<usize, BitVec<u8>>,
+    // Dense transitions on ASCII characters
+    ascii_table: Option<Vec<[Option<usize>; 128]>>,
+    // Transitions keyed by character equivalence class
+    class_table: Option<ClassTable>,
+}
+
+// The transition table compressed by grouping the characters which behave the same in every state
+#[derive(Debug)]
+struct ClassTable {
+    char_classes: HashMap<char, usize>, // Class of every character with at least one transition
+    rows: Vec<Vec<Option<usize>>>,      // One column per class, class 0 is the error column
 }
 
 #[derive(Debug, Clone)]
@@ -27,6 +56,117 @@ pub struct DFAState {
     id: usize,
     transitions: HashMap<Symbol, usize>, // Store by reference is not a thing in Rust
     category: String,
+    categories: BTreeSet<String>, // Categories of all the microsyntaxes accepting in this state
+    categories_lost: bool, // Whether minimization merged states accepting different categories
+}
+
+#[derive(Debug)]
+pub enum DFAError {
+    /// A state has an epsilon transition, which a DFA must never have
+    EpsilonTransition(usize),
+    /// A state has a transition to a state which does not exist
+    DanglingTransition(usize, usize),
+    /// The start state does not exist
+    InvalidStartState(usize),
+    /// The state at the given index has a different id
+    StateIdMismatch(usize, usize),
+    /// A state has a transition on a character which is not part of the alphabet
+    UnknownSymbol(usize, char),
+    /// The accept state markers do not cover exactly the states of the DFA
+    AcceptStatesMismatch(usize, usize),
+    /// The NFA being determinized refers to a state which it does not have
+    MissingNFAState(usize),
+    /// A line of the text format being imported could not be read
+    InvalidTextFormat(usize, String),
+    /// The subset construction needed more states than the limit it was given
+    StateLimitExceeded(usize),
+    /// A microsyntax index is not less than the number of microsyntaxes
+    InvalidMicrosyntaxIndex(usize, usize),
+    /// The automaton has ^ or $ anchors or \b word boundaries, which the table driven matcher it is
+    /// used for can not check
+    UnsupportedAssertion,
+}
+
+impl std::fmt::Display for DFAError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DFAError::EpsilonTransition(state) => {
+                write!(
+                    f,
+                    "Error: State {} has an epsilon transition in a DFA!",
+                    state
+                )
+            }
+            DFAError::DanglingTransition(state, target) => write!(
+                f,
+                "Error: State {} has a transition to non existent state {}!",
+                state, target
+            ),
+            DFAError::InvalidStartState(state) => {
+                write!(f, "Error: Start state {} does not exist!", state)
+            }
+            DFAError::StateIdMismatch(index, id) => {
+                write!(f, "Error: State at index {} has the id {}!", index, id)
+            }
+            DFAError::UnknownSymbol(state, ch) => write!(
+                f,
+                "Error: State {} has a transition on {:?} which is not in the alphabet!",
+                state, ch
+            ),
+            DFAError::AcceptStatesMismatch(accept_states, states) => write!(
+                f,
+                "Error: Found {} accept state markers for {} states!",
+                accept_states, states
+            ),
+            DFAError::MissingNFAState(state) => {
+                write!(
+                    f,
+                    "Error: The NFA refers to the non existent state {}!",
+                    state
+                )
+            }
+            DFAError::InvalidTextFormat(line, reason) => {
+                write!(
+                    f,
+                    "Error: Line {} of the DFA text is invalid, {}!",
+                    line, reason
+                )
+            }
+            DFAError::StateLimitExceeded(max_states) => {
+                write!(
+                    f,
+                    "Error: The DFA needs more than the limit of {} states!",
+                    max_states
+                )
+            }
+            DFAError::InvalidMicrosyntaxIndex(index, len) => {
+                write!(
+                    f,
+                    "Error: Microsyntax index {} is out of range for {} microsyntaxes!",
+                    index, len
+                )
+            }
+            DFAError::UnsupportedAssertion => write!(
+                f,
+                "Error: Anchors and word boundaries are not supported here, escape ^ and $ as \\^ and \\$ to match the characters!"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DFAError {}
+
+/// Counters recorded while constructing and minimizing a DFA, returned by the construction
+/// functions ending in with_metrics when the metrics feature is enabled.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConstructionMetrics {
+    /// Number of NFA state sets taken off the worklist during subset construction
+    pub worklist_iterations: usize,
+    /// Number of passes over the partition until Hopcroft's algorithm stops splitting sets
+    pub refinement_passes: usize,
+    /// Largest number of state sets held at once, the NFA state sets during subset construction
+    /// or the sets of the partition during minimization
+    pub peak_state_sets: usize,
 }
 
 struct LookupTable {
@@ -116,6 +256,8 @@ impl DFAState {
             id,
             transitions: HashMap::new(),
             category: String::new(),
+            categories: BTreeSet::new(),
+            categories_lost: false,
         }
     }
     /// Get a list of all outgoing transitions for the given state
@@ -143,23 +285,88 @@ impl DFA {
     }
 
     fn show_fa(&self, filename: &str) {
+        self.show_fa_with_format(filename, FaImageFormat::Jpg);
+    }
+
+    /// Save the DFA as an image named after filename, with the extension of the chosen format. The
+    /// DOT file Graphviz renders the image from is kept next to it.
+    pub fn show_fa_with_format(&self, filename: &str, format: FaImageFormat) {
+        // Write dot to file
+        let dot_filename = format!("{}.dot", filename);
+        let mut dot_file = File::create(&dot_filename).expect("Failed to create dot file");
+
+        self.write_dot(&mut dot_file)
+            .expect("Failed to write dot file");
+
+        Command::new("dot")
+            .args(dot_command_args(&dot_filename, filename, format))
+            .output()
+            .expect("Failed to execute Graphviz");
+
+        println!(
+            "DFA vizualization saved as {}.{}",
+            filename,
+            format.extension()
+        );
+    }
+
+    /// Returns the outgoing transitions of every state in the order of the state ids, each as a
+    /// list of (character, target) pairs sorted by the character. Unlike iterating over the
+    /// transitions directly, the order does not depend on hashing, so it is stable across runs.
+    pub fn adjacency(&self) -> Vec<(usize, Vec<(char, usize)>)> {
+        self.states
+            .iter()
+            .map(|state| {
+                let mut edges: Vec<(char, usize)> = state
+                    .transitions
+                    .iter()
+                    .filter_map(|(symbol, target)| match symbol {
+                        Symbol::Char(ch) => Some((*ch, *target)),
+                        Symbol::Epsilon => None, // A DFA never has epsilon transitions
+                    })
+                    .collect();
+                edges.sort();
+                (state.id, edges)
+            })
+            .collect()
+    }
+
+    /// Write the DFA in the graphviz DOT format. The edges are written in the order of adjacency,
+    /// so the same DFA always gives the same bytes.
+    pub fn write_dot<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let graph = self.to_digraph();
+        let dot = Dot::new(&graph);
+
+        writer.write_all(dot.to_string().as_bytes())
+    }
+
+    /// Build the labelled graph which the DFA is drawn from, so that petgraph algorithms such as
+    /// strongly connected components or shortest paths can be run on the automaton. The node with
+    /// index i is the state i, labelled like in the drawing, and every transition is an edge
+    /// labelled with its character, added in the order of adjacency.
+    pub fn to_digraph(&self) -> DiGraph<String, String> {
         let mut graph = DiGraph::new();
         let mut node_map = std::collections::HashMap::new();
 
         // Add nodes
-        for state in self.get_states() {
+        for state in &self.states {
             let node = graph.add_node(format!("State {}", state.id));
             node_map.insert(state.id, node);
         }
 
         // Add edges
-        for state in self.get_states() {
-            for (symbol, target) in &state.transitions {
-                let symbol_str = match symbol {
-                    Symbol::Char(c) => c.to_string(),
-                    Symbol::Epsilon => "𝛆".to_string(),
+        for (state, edges) in self.adjacency() {
+            for (ch, target) in edges {
+                let symbol_str = match ch {
+                    START_ANCHOR => "^".to_string(),
+                    END_ANCHOR => "$".to_string(),
+                    WORD_BOUNDARY => "\\b".to_string(),
+                    c => match raw_byte(c) {
+                        Some(byte) => format!("\\x{:X}", byte),
+                        None => c.to_string(),
+                    },
                 };
-                graph.add_edge(node_map[&state.id], node_map[&target], symbol_str);
+                graph.add_edge(node_map[&state], node_map[&target], symbol_str);
             }
         }
 
@@ -175,22 +382,7 @@ impl DFA {
             graph[accept_node] = graph[accept_node].clone() + &format!("\nAccept");
         }
 
-        let dot = Dot::new(&graph);
-
-        // Write dot to file
-        let dot_filename = format!("{}.dot", filename);
-        let mut dot_file = File::create(&dot_filename).expect("Failed to create dot file");
-
-        dot_file
-            .write_all(dot.to_string().as_bytes())
-            .expect("Failed to write dot file");
-
-        Command::new("dot")
-            .args(&["-Tjpg", &dot_filename, "-o", &format!("{}.jpg", filename)])
-            .output()
-            .expect("Failed to execute Graphviz");
-
-        println!("DFA vizualization saved as {}.jpg", filename);
+        graph
     }
 
     fn new() -> Self {
@@ -200,6 +392,12 @@ impl DFA {
             accept_states: BitVec::new(),
             alphabet: HashSet::new(),
             regex: String::new(),
+            category_regexes: HashMap::new(),
+            category_order: Vec::new(),
+            skip_categories: HashSet::new(),
+            nfa_state_sets: HashMap::new(),
+            ascii_table: None,
+            class_table: None,
         }
     }
 
@@ -208,6 +406,49 @@ impl DFA {
         let state = self.states.get(id).unwrap();
         return state;
     }
+    /// Get the regular expression that the DFA models
+    pub fn get_regex(&self) -> &String {
+        return &self.regex;
+    }
+    /// Get the source regex of the microsyntax for the category, which is useful for diagnostics
+    /// quoting the rule a token was matched by. Several microsyntaxes for the same category are
+    /// joined by | in declaration order. Returns None for a category the DFA was not built with.
+    pub fn regex_for_category(&self, category: &str) -> Option<&str> {
+        self.category_regexes
+            .get(category)
+            .map(|regex| regex.as_str())
+    }
+    /// Get the priority of the category from the order of the microsyntaxes, 0 being the category
+    /// declared first. Returns None for a category the DFA was not built with.
+    pub fn category_priority(&self, category: &str) -> Option<usize> {
+        self.category_order
+            .iter()
+            .position(|ordered| ordered == category)
+    }
+    /// Get the categories of the microsyntaxes declared with the s flag, whose tokens the scanner
+    /// skips as if they were in the skip list
+    pub fn get_skip_categories(&self) -> &HashSet<String> {
+        &self.skip_categories
+    }
+    /// Get the sorted ids of the NFA states the DFA state was built from by the subset
+    /// construction. Returns None for a state that does not exist or that was not built directly by
+    /// construct_dfa, such as the states of a minimized DFA.
+    pub fn nfa_states_of(&self, dfa_state: usize) -> Option<Vec<usize>> {
+        self.nfa_state_sets
+            .get(&dfa_state)
+            .map(|nfa_states| nfa_states.iter_ones().collect())
+    }
+
+    /// Get a snapshot of the NFA state sets explored by the subset construction, as sorted ids
+    /// listed in the order of the DFA states they became. The subset construction numbers states
+    /// in the order it discovers them, so comparing snapshots in tests catches any change in the
+    /// exploration order, such as one leaking in from the iteration order of a hash map. Empty for
+    /// a DFA that was not built directly by construct_dfa.
+    pub fn explored_nfa_state_sets(&self) -> Vec<Vec<usize>> {
+        (0..self.states.len())
+            .map_while(|state| self.nfa_states_of(state))
+            .collect()
+    }
     /// Returns a list of all states present in the DFA
     pub fn get_states(&self) -> Vec<DFAState> {
         self.states.clone()
@@ -224,672 +465,5014 @@ impl DFA {
             }
         }
     }
-}
-
-fn get_epsilon_closure(nfa: &NFA, nfa_states: BitVec<u8>) -> BitVec<u8> {
-    let num_states: usize = nfa.get_num_states();
 
-    let mut epsilon_closure: BitVec<u8, Lsb0> = BitVec::repeat(false, num_states);
-
-    let mut visited: BitVec<u8, Lsb0> = BitVec::repeat(false, num_states);
-
-    let mut nfa_states: VecDeque<_> = nfa_states.iter_ones().collect();
+    /// Check the structural invariants of the DFA: the start state and every transition target
+    /// must exist, every state must be stored at the index of its id, transitions may only be on
+    /// characters of the alphabet, and no state may have an epsilon transition.
+    pub fn validate(&self) -> Result<(), DFAError> {
+        if !self.states.is_empty() && self.start_state >= self.states.len() {
+            return Err(DFAError::InvalidStartState(self.start_state));
+        }
 
-    while !nfa_states.is_empty() {
-        let state = nfa_states.pop_front().unwrap();
-        let state = nfa.get_state(state).unwrap();
-        let transitions = state.get_transitions();
+        if self.accept_states.len() != self.states.len() {
+            return Err(DFAError::AcceptStatesMismatch(
+                self.accept_states.len(),
+                self.states.len(),
+            ));
+        }
 
-        let eps_transitions = transitions.get(&Symbol::Epsilon);
-        match eps_transitions {
-            Some(targets) => {
-                for target in targets {
-                    let target = *target; // Unboxing the value
-                    if !visited[target] {
-                        visited.set(target, true);
-                        nfa_states.push_back(target);
+        for (index, state) in self.states.iter().enumerate() {
+            if state.id != index {
+                return Err(DFAError::StateIdMismatch(index, state.id));
+            }
+            for (symbol, target) in state.transitions.iter() {
+                match symbol {
+                    Symbol::Epsilon => return Err(DFAError::EpsilonTransition(state.id)),
+                    Symbol::Char(ch) if !self.alphabet.contains(ch) => {
+                        return Err(DFAError::UnknownSymbol(state.id, *ch));
                     }
+                    Symbol::Char(_) => {}
+                }
+                if *target >= self.states.len() {
+                    return Err(DFAError::DanglingTransition(state.id, *target));
                 }
             }
-            None => {}
         }
-        epsilon_closure.set(state.get_id(), true); // Adding the state itself to the epsilon closure
+
+        Ok(())
     }
-    return epsilon_closure;
-}
 
-// This function returns the set of states accessible via char c within the set q
+    /// Returns true if the DFA is well formed and deterministic. Every state has at most one target
+    /// per symbol by construction of its transition map, so this comes down to having no epsilon
+    /// transitions and passing all the other checks of validate.
+    pub fn is_deterministic(&self) -> bool {
+        self.validate().is_ok()
+    }
 
-fn delta(nfa: &NFA, q: &BitVec<u8>, c: char) -> BitVec<u8> {
-    let mut result = BitVec::repeat(false, q.len());
-    let nodes: Vec<usize> = q.iter_ones().collect();
-    for node in nodes {
-        let nfa_state = nfa.get_state(node).unwrap();
-        let transitions = nfa_state.get_transitions();
-        let target_state_ids = transitions.get(&Symbol::Char(c));
-        let target_state_ids = match target_state_ids {
-            None => continue,
-            Some(state_ids) => state_ids,
-        };
-        for state_id in target_state_ids {
-            let state_id = *state_id; // Unwrapping the box
-            result.set(state_id, true);
+    /// Get the syntactic category accepted by the state with the provided id. Returns None if the
+    /// state does not exist or is not an accept state.
+    pub fn category_of(&self, state: usize) -> Option<&str> {
+        match self.accept_states.get(state) {
+            Some(is_accept) if *is_accept => Some(self.states[state].category.as_str()),
+            _ => None,
         }
     }
-    return result;
-}
 
-fn compare_transitions(
-    state1: &DFAState,
-    state2: &DFAState,
-    alphabet: &HashSet<char>,
-    lookup_table: &LookupTable,
-) -> bool {
-    let state1_transitions = state1.get_transitions();
-    let state2_transitions = state2.get_transitions();
+    /// Rename the syntactic categories of all accept states using the provided mapping function.
+    /// This allows categories to be merged or relabelled without rebuilding the DFA.
+    pub fn remap_categories(&mut self, f: impl Fn(&str) -> String) {
+        for accept_state in self.accept_states.iter_ones() {
+            let state = &mut self.states[accept_state];
+            let new_category = f(&state.category);
+            state.set_category(new_category);
+            state.categories = state
+                .categories
+                .iter()
+                .map(|category| f(category))
+                .collect();
+        }
 
-    let mut same_transitions = true;
+        // Categories merged by the mapping keep the regexes of all their microsyntaxes, in the
+        // order the categories were declared in
+        let mut category_regexes: Vec<(String, String)> = self.category_regexes.drain().collect();
+        category_regexes.sort_by_key(|(category, _)| {
+            let priority = self.category_priority(category).unwrap_or(usize::MAX);
+            (priority, category.clone())
+        });
+        for (category, regex) in category_regexes {
+            self.category_regexes
+                .entry(f(&category))
+                .and_modify(|merged| *merged = format!("{}|{}", merged, regex))
+                .or_insert(regex);
+        }
 
-    for c in alphabet {
-        if !same_transitions {
-            break;
+        // Merged categories keep the priority of the first of them
+        let mut category_order = Vec::new();
+        for category in self.category_order.drain(..) {
+            let category = f(&category);
+            if !category_order.contains(&category) {
+                category_order.push(category);
+            }
         }
-        let state1_dest = state1_transitions.get(&Symbol::Char(*c)); // Get destination
-                                                                     // for the state for
-                                                                     // this symbol and
-                                                                     // member
-        let state2_dest = state2_transitions.get(&Symbol::Char(*c));
+        self.category_order = category_order;
 
-        match (state1_dest, state2_dest) {
-            (None, None) => same_transitions = true, // If both don't have a transition, no splitting
-            (Some(_), None) | (None, Some(_)) => {
-                same_transitions = false;
-            } // If only one has a transition, split
-            (Some(state_dest), Some(member_dest)) => {
-                // If both have transitions,
-                // make sure both transition to
-                // same set
-                let state_dest_set = lookup_table.get_set_of_state(state_dest).unwrap();
-                let member_dest_set = lookup_table.get_set_of_state(member_dest).unwrap();
+        self.skip_categories = self
+            .skip_categories
+            .iter()
+            .map(|category| f(category))
+            .collect();
+    }
 
-                same_transitions = state_dest_set == member_dest_set;
+    /// Returns the distinct syntactic categories which the accept states of the DFA can emit, in
+    /// sorted order. Accept states without a category are ignored.
+    pub fn categories(&self) -> BTreeSet<String> {
+        self.accept_states
+            .iter_ones()
+            .map(|accept_state| &self.states[accept_state].category)
+            .filter(|category| !category.is_empty())
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the state reached from the given state on the character c, or None if the state
+    /// does not exist or has no transition on c, which means the implicit error state is reached.
+    /// The markers of the anchors and word boundaries are never consumed like a character of the
+    /// input.
+    pub fn transition(&self, state: usize, c: char) -> Option<usize> {
+        // Only a ByteDFA matches the raw bytes, a character of the input never does
+        if is_assertion_marker(c) || raw_byte(c).is_some() {
+            return None;
+        }
+
+        if let Some(ascii_table) = &self.ascii_table {
+            if c.is_ascii() {
+                return ascii_table.get(state)?[c as usize];
             }
         }
+
+        if let Some(class_table) = &self.class_table {
+            let class = class_table.char_classes.get(&c).copied().unwrap_or(0);
+            return class_table.rows.get(state)?[class];
+        }
+
+        self.states
+            .get(state)?
+            .transitions
+            .get(&Symbol::Char(c))
+            .copied()
     }
-    return same_transitions;
-}
 
-fn get_lookup_table(dfa: &DFA) -> LookupTable {
-    let alphabet = dfa.get_alphabet();
-    let mut lookup_table = LookupTable::new();
-    let states = dfa.get_acceptor_states();
-    let mut set_changes: VecDeque<(usize, usize)> = VecDeque::new();
-    // 0 is non acceptors states, 1 is acceptor states
-    // If all states are acceptor states, then 0 is the only set id
+    // Returns the state reached from the given state through the marker of an assertion
+    fn assertion_transition(&self, state: usize, marker: char) -> Option<usize> {
+        self.states
+            .get(state)?
+            .transitions
+            .get(&Symbol::Char(marker))
+            .copied()
+    }
 
-    let set_id = 0;
+    /// Returns true if the DFA has transitions on the markers of the ^ and $ anchors or the \b word
+    /// boundary. They consume no input, so they are checked against the characters around the
+    /// current position by the matchers of the DFA, but a table driven Scanner or an emitted
+    /// scanner can not follow them.
+    pub fn has_assertions(&self) -> bool {
+        ASSERTION_MARKERS
+            .iter()
+            .any(|marker| self.alphabet.contains(marker))
+    }
 
-    for non_accept_state in states.iter_zeros() {
-        lookup_table.insert_state_in_set(non_accept_state, set_id);
+    /// Build a dense table of the transitions on ASCII characters, with one array of 128 entries
+    /// per state. Once built, transitions on ASCII characters are looked up in the table instead of
+    /// hashing the character, which speeds up accepts and longest_match on mostly ASCII input. The
+    /// other characters still go through the transition map of the state.
+    pub fn build_ascii_table(&mut self) {
+        let ascii_table = self
+            .states
+            .iter()
+            .map(|state| {
+                let mut row = [None; 128];
+                for (symbol, target) in state.transitions.iter() {
+                    if let Symbol::Char(ch) = symbol {
+                        if ch.is_ascii() {
+                            row[*ch as usize] = Some(*target);
+                        }
+                    }
+                }
+                row
+            })
+            .collect();
+
+        self.ascii_table = Some(ascii_table);
     }
 
-    let set_id = if states.all() { 0 } else { 1 }; // Test if all bits are 1, i.e all states are
-                                                   // acceptors and there are no non acceptor
-                                                   // states
+    /// Returns true if the dense ASCII transition table was built with build_ascii_table
+    pub fn has_ascii_table(&self) -> bool {
+        self.ascii_table.is_some()
+    }
 
-    let mut category_set_id: HashMap<String, usize> = HashMap::new(); // Mapping of category of accept state and set id
+    /// Build a transition table with one column per character equivalence class instead of one per
+    /// character. Two characters are in the same class when every state sends them to the same
+    /// state, like all the letters of [a-z]+. Class 0 holds the characters with no transition at
+    /// all, including those outside the alphabet, so its column only leads to the error state. The
+    /// other classes are numbered in the order of their smallest character.
+    pub fn build_class_table(&mut self) {
+        let mut alphabet: Vec<char> = self.alphabet.iter().cloned().collect();
+        alphabet.sort();
+
+        let mut char_classes: HashMap<char, usize> = HashMap::new();
+        let mut signatures: HashMap<Vec<Option<usize>>, usize> = HashMap::new();
+        let mut columns: Vec<Vec<Option<usize>>> = vec![vec![None; self.states.len()]];
+
+        for ch in alphabet {
+            let signature: Vec<Option<usize>> = self
+                .states
+                .iter()
+                .map(|state| state.transitions.get(&Symbol::Char(ch)).copied())
+                .collect();
+
+            if signature.iter().all(|target| target.is_none()) {
+                continue;
+            }
 
-    for accept_state in states.iter_ones() {
-        let category = dfa.get_state(accept_state).get_category();
-        let offset_map_len = set_id + category_set_id.len();
+            let class = *signatures.entry(signature.clone()).or_insert_with(|| {
+                columns.push(signature);
+                columns.len() - 1
+            });
+            char_classes.insert(ch, class);
+        }
 
-        let insert_id = category_set_id
-            .entry(category.to_string())
-            .or_insert(offset_map_len);
+        let rows = (0..self.states.len())
+            .map(|state| columns.iter().map(|column| column[state]).collect())
+            .collect();
 
-        lookup_table.insert_state_in_set(accept_state, *insert_id);
+        self.class_table = Some(ClassTable { char_classes, rows });
     }
 
-    loop {
-        let number_of_sets = lookup_table.get_num_sets(); // Get number of sets at start of
-                                                          // iteration
-        let sets: Vec<_> = lookup_table.get_sets().cloned().collect(); // Get list of sets
-
-        // Try to split the sets further
+    /// Returns the equivalence class of the character in the table built with build_class_table.
+    /// Characters with no transition in any state are in class 0, as is every character before
+    /// the table is built.
+    pub fn char_class_of(&self, c: char) -> usize {
+        self.class_table
+            .as_ref()
+            .and_then(|class_table| class_table.char_classes.get(&c).copied())
+            .unwrap_or(0)
+    }
 
-        for set in sets.iter() {
-            if set.len() == 1 {
-                // Cannot split a set with only 1 element
-                continue;
-            }
-            let next_set = lookup_table.get_num_sets(); // The next set which will be inserted
-            let member_state_id = set.iter().next().unwrap();
+    /// Returns the number of columns of the table built with build_class_table, counting the error
+    /// column of class 0, or None if the table was not built
+    pub fn num_char_classes(&self) -> Option<usize> {
+        let class_table = self.class_table.as_ref()?;
+        Some(class_table.rows.first().map_or(1, |row| row.len()))
+    }
 
-            let member_state = dfa.get_state(*member_state_id);
+    /// Returns true if the DFA accepts no string at all, as no accept state can be reached from
+    /// the start state.
+    pub fn is_empty_language(&self) -> bool {
+        !self
+            .reachable_states()
+            .iter()
+            .any(|state| self.accept_states[*state])
+    }
 
-            for state_id in set {
-                let state = dfa.get_state(*state_id);
+    /// Returns true if the DFA accepts every string over its alphabet, including the empty string.
+    /// This holds when every reachable state accepts and has a transition on every character of
+    /// the alphabet, as any missing transition leads to the rejecting error state. Universality is
+    /// relative to the finite alphabet, so a DFA for .* is universal even though it never matches
+    /// characters outside of its universe.
+    pub fn is_universal(&self) -> bool {
+        if self.states.is_empty() {
+            return false;
+        }
 
-                if !compare_transitions(state, member_state, alphabet, &lookup_table) {
-                    set_changes.push_back((*state_id, next_set));
-                }
-            }
+        self.reachable_states().iter().all(|state| {
+            self.accept_states[*state]
+                && self
+                    .alphabet
+                    .iter()
+                    .all(|ch| self.transition(*state, *ch).is_some())
+        })
+    }
 
-            while !set_changes.is_empty() {
-                let change = set_changes.pop_front().unwrap();
-                lookup_table.insert_state_in_set(change.0, change.1);
-            }
+    /// Returns true if no DFA with fewer states accepts the same strings with the same categories.
+    /// Every state has to be reachable from the start state and reach an accept state, as a state
+    /// that reaches none is equivalent to the implicit error state, and Hopcroft's algorithm must
+    /// find no two states to merge. Only the DFA of the empty language keeps its start state
+    /// without reaching an accept state.
+    pub fn is_minimal(&self) -> bool {
+        if self.reachable_states().len() != self.states.len() {
+            return false;
         }
-        let new_number_of_sets = lookup_table.get_num_sets();
 
-        if number_of_sets == new_number_of_sets {
-            break;
+        let has_dead_state = (0..self.states.len()).any(|state| {
+            self.reachable_from(state)
+                .iter()
+                .all(|reachable| !self.accept_states[*reachable])
+        });
+        if has_dead_state && self.states.len() > 1 {
+            return false;
         }
+
+        let (minimal_dfa, _) = minimize(self, &mut ConstructionMetrics::default(), None, false);
+        minimal_dfa.get_num_states() == self.states.len()
     }
-    return lookup_table;
-}
-fn reorder_minimal_dfa(dfa: &DFA) -> DFA {
-    let mut result = DFA::new(); // Set up result DFA
-    let mut reorder_map = HashMap::new(); // Set up a re-order table
-    let mut stack: VecDeque<usize> = VecDeque::new(); // Set up a stack for DFS
 
-    let mut visited: BitVec<u8, Lsb0> = BitVec::repeat(false, dfa.states.len());
+    /// Returns the sorted characters of the alphabet that label no transition of any state
+    /// reachable from the start state. Such characters usually come from a dead branch of the
+    /// specification, like a typo in a pattern which can never be matched.
+    pub fn unused_alphabet(&self) -> Vec<char> {
+        let mut used: HashSet<char> = HashSet::new();
 
-    for _ in 0..dfa.states.len() {
-        // Add as many states as in the initial DFA because
-        // we are not adding or removing ant states, just
-        // re-ordering them.
-        result.add_state();
-    }
-    let dfa_start = dfa.start_state; // Get the starting dfa state
+        for state in self.reachable_states() {
+            for symbol in self.states[state].transitions.keys() {
+                if let Symbol::Char(ch) = symbol {
+                    used.insert(*ch);
+                }
+            }
+        }
 
-    let mut next_id = 0;
+        let mut unused: Vec<char> = self.alphabet.difference(&used).copied().collect();
+        unused.sort();
+        unused
+    }
 
-    stack.push_front(dfa_start); // Add the start state to the stack
+    // The states reachable from the start state, in the order they are first reached
+    fn reachable_states(&self) -> Vec<usize> {
+        self.reachable_from(self.start_state)
+    }
 
-    while !stack.is_empty() {
-        // Start DFS
-        let state_id = stack.pop_front().unwrap(); // Get the head of stack
+    // The states reachable from the given state, including the state itself, in breadth first order
+    fn reachable_from(&self, state: usize) -> Vec<usize> {
+        let mut reachable = Vec::new();
 
-        if *visited.get(state_id).unwrap() {
-            // If node is already visited skip
-            continue;
+        if state >= self.states.len() {
+            return reachable;
         }
 
-        visited.set(state_id, true); // Mark the current node as visited
+        let mut visited: HashSet<usize> = HashSet::from([state]);
+        let mut work_list = VecDeque::from([state]);
 
-        let reorder_state_id = match reorder_map.get(&state_id) {
-            Some(&id) => id,
-            None => {
-                reorder_map.insert(state_id, next_id);
-                let reordered_id = next_id;
-                next_id = next_id + 1;
-                reordered_id
+        while let Some(state) = work_list.pop_front() {
+            reachable.push(state);
+            for target in self.states[state].transitions.values() {
+                if visited.insert(*target) {
+                    work_list.push_back(*target);
+                }
             }
-        }; // Get the re-ordered equivalent state or add one
+        }
 
-        let state = &dfa.states[state_id]; // Get the state from the dfa
+        reachable
+    }
 
-        let transitions = &state.transitions; // Get the transitions from the original state
+    /// Returns the syntactic categories of the accept states reachable from the given state,
+    /// including the state itself, in sorted order. These are the categories a token could still
+    /// end up in after reaching the state, so from the start state they are all the categories of
+    /// the DFA. Returns an empty set if the state does not exist.
+    pub fn reachable_categories(&self, state: usize) -> BTreeSet<String> {
+        self.reachable_from(state)
+            .into_iter()
+            .filter_map(|state| self.category_of(state))
+            .filter(|category| !category.is_empty())
+            .map(|category| category.to_string())
+            .collect()
+    }
 
-        let reorder_state: &mut DFAState = result.states.get_mut(reorder_state_id).unwrap(); // Get the state from the re-ordered DFA
+    /// Returns the declared categories which label no accept state reachable from the start state,
+    /// in the order they were declared. A category is missing from the combined DFA when the
+    /// microsyntaxes with a higher priority match every lexeme of its own microsyntaxes, so it can
+    /// never produce a token, which is likely a mistake in the specification.
+    pub fn unreachable_categories(&self, declared: &[String]) -> Vec<String> {
+        let reachable = self.reachable_categories(self.start_state);
+        let mut unreachable: Vec<String> = Vec::new();
+
+        for category in declared {
+            if !reachable.contains(category) && !unreachable.contains(category) {
+                unreachable.push(category.clone());
+            }
+        }
+        unreachable
+    }
 
-        for transition in transitions {
-            // For each transition, check if the target state is
-            // present
-            let symbol = transition.0.clone();
-            let target = transition.1;
-
-            let reorder_target_id = match reorder_map.get(&target) {
-                // If not present, take the next available state and map it to the current
-                // un-ordered state
-                Some(&id) => id,
-                None => {
-                    let state_id = next_id;
-                    reorder_map.insert(*target, state_id);
-                    next_id = next_id + 1; // Pick the next available id
-                    state_id
+    /// Returns every category whose microsyntax matches the entire input, rather than only the one
+    /// picked by priority. A DFA state built from several NFA accept states remembers all of their
+    /// categories, so an input like "if" can be both a KEYWORD and an IDENTIFIER. Returns an empty
+    /// set if the input is not accepted. construct_minimal_dfa may merge accept states whose other
+    /// categories differ, and returns None if the input ends in such a state as the categories
+    /// are no longer known. Minimize with construct_minimal_dfa_preserving_categories to keep them.
+    pub fn match_all_categories(&self, input: &str) -> Option<BTreeSet<String>> {
+        if self.has_assertions() {
+            let chars = input.chars().map(|ch| (ch, ch.len_utf8()));
+            return match self
+                .assertion_matches(None, 0, chars, AnchorMode::SingleLine)
+                .pop()
+            {
+                Some((end, states)) if end == input.len() => {
+                    if states
+                        .iter()
+                        .any(|state| self.states[*state].categories_lost)
+                    {
+                        return None;
+                    }
+                    Some(
+                        states
+                            .iter()
+                            .flat_map(|state| self.state_categories(*state))
+                            .collect(),
+                    )
                 }
+                _ => Some(BTreeSet::new()),
             };
+        }
 
-            reorder_state.transitions.insert(symbol, reorder_target_id); // Add a transition from
-                                                                         // the reordered state to
-                                                                         // the reordered target
-            stack.push_front(*target); // Add the target to the head of the stack now
+        let mut state = self.start_state;
+
+        for ch in input.chars() {
+            match self.transition(state, ch) {
+                Some(target) => state = target,
+                None => return Some(BTreeSet::new()),
+            }
         }
-    }
 
-    let start_state = dfa.start_state;
+        if !self
+            .accept_states
+            .get(state)
+            .is_some_and(|is_accept| *is_accept)
+        {
+            return Some(BTreeSet::new());
+        }
 
-    let reordered_start_state = reorder_map.get(&start_state).unwrap();
-    result.start_state = *reordered_start_state;
+        if self.states[state].categories_lost {
+            return None;
+        }
+        Some(self.state_categories(state))
+    }
 
-    // Mark the acceptor states
+    // Every category accepted in the accept state
+    fn state_categories(&self, state: usize) -> BTreeSet<String> {
+        // States which were not built by subset construction only know their own category
+        let state = &self.states[state];
+        let mut categories = state.categories.clone();
+        if !state.category.is_empty() {
+            categories.insert(state.category.clone());
+        }
+        categories
+    }
 
-    for accept in dfa.accept_states.iter_ones() {
-        let remapped_id = reorder_map.get(&accept).unwrap();
-        let category = &dfa.states[accept].category;
-        result.accept_states.set(*remapped_id, true);
-        result.set_accept_category(category);
+    // Every category accepted in the accept states, the one which wins by priority first
+    fn accepted_categories(&self, states: &[usize]) -> Vec<String> {
+        let categories: BTreeSet<String> = states
+            .iter()
+            .flat_map(|state| self.state_categories(*state))
+            .collect();
+        let mut categories: Vec<String> = categories.into_iter().collect();
+
+        categories.sort_by_key(|category| {
+            let priority = self.category_priority(category).unwrap_or(usize::MAX);
+            (priority, category.clone())
+        });
+        categories
     }
 
-    return result;
-}
-/// Apply Hopcroft's algorithm on a provided DFA to minimize it. If save_minimal_dfa is set to true,
-/// the constructed minimal DFA is saved as a jpg.
-pub fn construct_minimal_dfa(dfa: &DFA, save_minimal_dfa: bool) -> DFA {
-    let lookup_table = get_lookup_table(&dfa);
-    let sets = lookup_table.set_to_states_map.values();
+    /// Returns true if the DFA accepts the entire input string. The ^ and $ anchors match at the
+    /// start and the end of the input, and the \b word boundary as in find.
+    pub fn accepts(&self, input: &str) -> bool {
+        if self.states.is_empty() {
+            return false;
+        }
 
-    // Create a new DFA
+        if self.has_assertions() {
+            let chars = input.chars().map(|ch| (ch, ch.len_utf8()));
+            let matches = self.assertion_matches(None, 0, chars, AnchorMode::SingleLine);
+            return matches.last().is_some_and(|(end, _)| *end == input.len());
+        }
 
-    let mut minimal_dfa = DFA::new();
+        let mut state = self.start_state;
 
-    // Clone the alphabet for the new DFA
+        for ch in input.chars() {
+            match self.transition(state, ch) {
+                Some(target) => state = target,
+                None => return false, // Missing transitions lead to the implicit error state
+            }
+        }
 
-    minimal_dfa.alphabet = dfa.alphabet.clone();
+        return self.accept_states[state];
+    }
 
-    minimal_dfa.regex = dfa.regex.to_string();
+    /// Returns true if the DFA accepts the entire byte string, which does not need to be valid
+    /// UTF-8. It is matched by a ByteDFA, which is built on every call, so build the ByteDFA once
+    /// instead to match many inputs. A ByteDFA can not check anchors or word boundaries, so with
+    /// them only valid UTF-8 is accepted, matched as text.
+    pub fn accepts_bytes(&self, input: &[u8]) -> bool {
+        if self.has_assertions() {
+            return std::str::from_utf8(input).is_ok_and(|text| self.accepts(text));
+        }
 
-    // For every set in the lookup table, add a state
+        ByteDFA::new(self).is_ok_and(|byte_dfa| byte_dfa.accepts(input))
+    }
 
-    for _ in 0..(lookup_table.set_to_states_map.len()) {
-        minimal_dfa.add_state();
+    /// Returns the byte range of the longest prefix of the input accepted by the DFA, or None if
+    /// no prefix is accepted. Unlike accepts, the whole input does not need to be accepted.
+    pub fn match_span(&self, input: &str) -> Option<Range<usize>> {
+        self.longest_match(input, 0)
     }
 
-    // Get the set to which the current DFA's start state belongs to and mark the state
-    // corresponsing to that set id as the starting state.
+    /// Returns the byte range of the longest match starting at the byte offset start of the
+    /// source, or None if there is none. The source can be any CharSource, such as a rope, so the
+    /// input does not need to be stored as one contiguous string. The ^ and $ anchors match at the
+    /// start and the end of the whole source, not of the match.
+    pub fn longest_match<S: CharSource + ?Sized>(
+        &self,
+        source: &S,
+        start: usize,
+    ) -> Option<Range<usize>> {
+        if self.states.is_empty() {
+            return None;
+        }
 
-    let start_state = dfa.start_state;
+        if self.has_assertions() {
+            let mut offset = start;
+            let chars = std::iter::from_fn(|| {
+                let (ch, len) = source.char_at(offset)?;
+                offset += len;
+                Some((ch, len))
+            });
+            let before = char_before(source, start);
+            let matches = self.assertion_matches(before, start, chars, AnchorMode::SingleLine);
+            return matches.last().map(|(end, _)| start..*end);
+        }
 
-    let start_set = lookup_table.state_to_set_map.get(&start_state).unwrap();
+        let mut state = self.start_state;
+        let mut last_accept_end = if self.accept_states[state] {
+            Some(start)
+        } else {
+            None
+        };
 
-    minimal_dfa.start_state = *start_set;
+        let mut offset = start;
 
-    // Repeat the same process as above for the acceptor states
+        while let Some((ch, len)) = source.char_at(offset) {
+            state = match self.transition(state, ch) {
+                Some(target) => target,
+                None => break,
+            };
 
-    let acceptor_states = &dfa.accept_states;
+            offset += len;
 
-    for accept_state in acceptor_states.iter_ones() {
-        let category = &dfa.states[accept_state].category;
-        if let Some(accept_set) = lookup_table.state_to_set_map.get(&accept_state) {
-            minimal_dfa.accept_states.set(*accept_set, true);
-            minimal_dfa.set_accept_category(category);
+            if self.accept_states[state] {
+                last_accept_end = Some(offset);
+            }
         }
+
+        return last_accept_end.map(|end| start..end);
     }
 
-    for set in sets {
-        // For each set in which all elements have the same set of transitions
-        let representative = set.iter().next().unwrap(); // Pick a representative member
+    /// Returns every accept checkpoint passed while matching from the byte offset start, as the
+    /// byte offset where the match ends and the syntactic category accepted there. Unlike
+    /// match_span, shorter matches are reported along with the longest one so that a later stage
+    /// can choose between them. Every category matching the lexeme is reported for its end, the one
+    /// which wins by priority first. On a DFA minimized by construct_minimal_dfa, an end where
+    /// DFA::match_all_categories returns None only reports the categories the merged states share.
+    /// Returns no matches if the start offset is past the end of the input or not on a character
+    /// boundary.
+    pub fn all_matches(&self, input: &str, start: usize) -> Vec<(usize, String)> {
+        let mut matches = Vec::new();
+
+        let rest = match input.get(start..) {
+            Some(rest) if !self.states.is_empty() => rest,
+            _ => return matches,
+        };
 
-        let transitions = &dfa.states[*representative].transitions; // Get the set of
-                                                                    // transitiosn for the
-                                                                    // representative
-        let current_set = lookup_table.state_to_set_map.get(representative).unwrap(); // Get the
-                                                                                      // representative
-                                                                                      // state's
-                                                                                      // set
+        if self.has_assertions() {
+            let before = input[..start].chars().next_back();
+            let chars = rest.chars().map(|ch| (ch, ch.len_utf8()));
+            for (end, states) in
+                self.assertion_matches(before, start, chars, AnchorMode::SingleLine)
+            {
+                for category in self.accepted_categories(&states) {
+                    matches.push((end, category));
+                }
+            }
+            return matches;
+        }
 
-        for transition in transitions {
-            let destination_state = transition.1;
-            let destination_set = lookup_table
-                .state_to_set_map
-                .get(&destination_state)
-                .unwrap();
+        let mut state = self.start_state;
 
-            minimal_dfa.states[*current_set]
-                .transitions
-                .insert(transition.0.clone(), *destination_set);
+        if self.accept_states[state] {
+            for category in self.accepted_categories(&[state]) {
+                matches.push((start, category));
+            }
         }
-    }
 
-    let regex = &minimal_dfa.regex;
+        for (offset, ch) in rest.char_indices() {
+            state = match self.transition(state, ch) {
+                Some(target) => target,
+                None => break,
+            };
 
-    let mut result = reorder_minimal_dfa(&minimal_dfa);
-    result.alphabet = minimal_dfa.alphabet.clone();
-    result.regex = regex.to_string();
+            if self.accept_states[state] {
+                let end = start + offset + ch.len_utf8();
+                for category in self.accepted_categories(&[state]) {
+                    matches.push((end, category));
+                }
+            }
+        }
 
-    if save_minimal_dfa {
-        let filename = format!("constructed_minimal_dfa");
-        result.show_fa(&filename);
+        return matches;
     }
 
-    return result; // We need to always reorder now as visualization is possible
-}
-///  Apply the subset construction algorithm on an NFA to build a DFA. If save_dfa is set to true,
-///  the constructed DFA is saved as a jpg.
-pub fn construct_dfa(nfa: &NFA, save_dfa: bool) -> DFA {
-    let mut result = DFA::new(); // Create new DFA
-    result.alphabet = nfa.get_alphabet().clone(); // DFA has same alphabet as NFA
+    /// Returns every accepted prefix of the input as its length in bytes and a syntactic category
+    /// accepted there, once for every category as in all_matches, walking the input from its start until the DFA reaches the error state. This
+    /// is meant for autocompletion, where the input typed so far is always matched from the start.
+    pub fn accepting_prefixes(&self, input: &str) -> Vec<(usize, String)> {
+        self.all_matches(input, 0)
+    }
 
-    let nfa_accepts = nfa.get_acceptor_states();
+    /// Returns the byte range of the leftmost longest match of the DFA anywhere in the input, or
+    /// None if there is no match. The ^ and $ anchors match at the start and end of the input, and
+    /// in AnchorMode::MultiLine also right after and right before every newline. The \b word
+    /// boundary matches between a word character ([A-Za-z0-9_]) and a non word character, where the
+    /// start and the end of the input count as non word characters.
+    pub fn find(&self, input: &str, mode: AnchorMode) -> Option<Range<usize>> {
+        if self.states.is_empty() {
+            return None;
+        }
 
-    let di = result.add_state(); // Add an iniital state
+        let mut starts: Vec<usize> = input.char_indices().map(|(offset, _)| offset).collect();
+        starts.push(input.len());
 
-    result.start_state = di;
-    let n0: usize = nfa.get_start_state(); // Get n0
-    let mut q_list = HashMap::new(); // Mapping from nfa state set to DFA state
-    let mut work_list = VecDeque::new();
+        for start in starts {
+            let before = input[..start].chars().next_back();
+            let chars = input[start..].chars().map(|ch| (ch, ch.len_utf8()));
+            if let Some((end, _)) = self.assertion_matches(before, start, chars, mode).pop() {
+                return Some(start..end);
+            }
+        }
 
-    let mut nfa_states = BitVec::repeat(false, nfa.get_num_states()); // Get the initial nfa states
-    nfa_states.set(n0, true); // Add the start state to nfa states set
+        return None;
+    }
 
-    let q0 = get_epsilon_closure(&nfa, nfa_states); // Get its epsilon closure
-    q_list.insert(q0.clone(), di); // Add it to the mapping
-    work_list.push_back(q0.clone()); // Add the first nfa states set to the work list
+    // Match the characters following the byte offset start, where before is the character in
+    // front of it, and return the end of every accepted prefix along with the accept states
+    // reached there. The markers of the assertions do not consume input and the position may
+    // satisfy them or not, so the match keeps a set of current states which both includes and
+    // skips the states reached through them.
+    fn assertion_matches(
+        &self,
+        before: Option<char>,
+        start: usize,
+        input: impl Iterator<Item = (char, usize)>,
+        mode: AnchorMode,
+    ) -> Vec<(usize, Vec<usize>)> {
+        let mut matches = Vec::new();
+
+        if self.states.is_empty() {
+            return matches;
+        }
 
-    let has_common = (q0.clone() & nfa_accepts).any();
+        let mut input = input.peekable();
+        let mut end = start;
+        let after = input.peek().map(|(ch, _)| *ch);
+        let mut states =
+            self.follow_assertions(BTreeSet::from([self.start_state]), before, after, mode);
+
+        loop {
+            let accepted: Vec<usize> = states
+                .iter()
+                .copied()
+                .filter(|state| self.accept_states[*state])
+                .collect();
+            if !accepted.is_empty() {
+                matches.push((end, accepted));
+            }
 
-    if has_common {
-        result.accept_states.set(di, true);
+            let Some((ch, len)) = input.next() else {
+                break;
+            };
+            let next: BTreeSet<usize> = states
+                .iter()
+                .filter_map(|state| self.transition(*state, ch))
+                .collect();
 
-        for state in q0.iter_ones() {
-            let category = nfa.get_state(state).unwrap().get_category();
-            if !category.is_empty() {
-                result.set_accept_category(category);
+            if next.is_empty() {
+                break;
             }
+
+            end += len;
+            let after = input.peek().map(|(ch, _)| *ch);
+            states = self.follow_assertions(next, Some(ch), after, mode);
         }
-    }
 
-    let dfa_alphabet = result.alphabet.clone();
+        return matches;
+    }
 
-    while !work_list.is_empty() {
-        let q = work_list.pop_front().unwrap();
-        for c in dfa_alphabet.iter() {
-            let end_states = delta(&nfa, &q, *c);
-            if end_states.not_any() {
-                continue;
+    // Add the states reached through the assertions which hold between the characters before and
+    // after the position, where None is the start or the end of the input
+    fn follow_assertions(
+        &self,
+        mut states: BTreeSet<usize>,
+        before: Option<char>,
+        after: Option<char>,
+        mode: AnchorMode,
+    ) -> BTreeSet<usize> {
+        let multiline = mode == AnchorMode::MultiLine;
+        let at_start = before.is_none() || (multiline && before == Some('\n'));
+        let at_end = after.is_none() || (multiline && after == Some('\n'));
+        let at_boundary = before.is_some_and(is_word_char) != after.is_some_and(is_word_char);
+
+        let mut worklist: Vec<usize> = states.iter().copied().collect();
+
+        while let Some(state) = worklist.pop() {
+            let mut targets = Vec::new();
+            if at_start {
+                targets.extend(self.assertion_transition(state, START_ANCHOR));
             }
-            let t = get_epsilon_closure(&nfa, end_states);
-
-            if !q_list.contains_key(&t) {
-                // check if di is as an acceptor state
-                let di = result.add_state();
-                q_list.insert(t.clone(), di);
-                work_list.push_back(t.clone());
-                let has_common = (t.clone() & nfa_accepts).any();
-                if has_common {
-                    result.accept_states.set(di, true);
-                    for state in t.iter_ones() {
-                        let category = nfa.get_state(state).unwrap().get_category();
-                        if !category.is_empty() {
-                            result.set_accept_category(category);
-                        }
-                    }
+            if at_end {
+                targets.extend(self.assertion_transition(state, END_ANCHOR));
+            }
+            if at_boundary {
+                targets.extend(self.assertion_transition(state, WORD_BOUNDARY));
+            }
+            for target in targets {
+                if states.insert(target) {
+                    worklist.push(target);
                 }
             }
-            // add a transition from diq to dit
-            let dq = q_list.get(&q).unwrap();
-            let di = q_list.get(&t).unwrap();
-            let di = *di;
-            let dq = *dq; // Unwrapping the box
-            result.states[dq].transitions.insert(Symbol::Char(*c), di);
         }
-    }
-    let regex = nfa.get_regex();
-    result.regex = regex.to_string();
-    if save_dfa {
-        let filename = format!("constructed_dfa");
-        result.show_fa(&filename);
+
+        return states;
     }
 
-    return result;
-}
+    // Build a copy of the DFA with a transition on every character of the given alphabet from
+    // every state. Missing transitions, including the ones on characters the DFA never saw, are
+    // routed to a new rejecting trap state which loops on every character.
+    pub(crate) fn complete(&self, alphabet: &HashSet<char>) -> DFA {
+        let mut result = DFA::new();
+        result.states = self.states.clone();
+        result.accept_states = self.accept_states.clone();
+        result.start_state = self.start_state;
+        result.alphabet = self.alphabet.union(alphabet).cloned().collect();
+        result.regex = self.regex.to_string();
+        result.category_regexes = self.category_regexes.clone();
+        result.category_order = self.category_order.clone();
+        result.skip_categories = self.skip_categories.clone();
+        result.nfa_state_sets = self.nfa_state_sets.clone();
+
+        let trap = result.add_state();
+
+        if self.states.is_empty() {
+            result.start_state = trap; // An empty DFA rejects everything
+        }
 
-#[cfg(test)]
-mod dfa_tests {
-    use super::*;
-    use std::collections::HashSet;
+        for state in result.states.iter_mut() {
+            for ch in result.alphabet.iter() {
+                state.transitions.entry(Symbol::Char(*ch)).or_insert(trap);
+            }
+        }
 
-    #[test]
-    fn test_dfa_state_creation() {
-        let state = DFAState::new(1);
-        assert_eq!(state.id, 1);
-        assert_eq!(state.get_transitions().len(), 0);
-        assert_eq!(state.get_category(), "");
+        return result;
     }
 
-    #[test]
-    fn test_dfa_basic_construction() {
-        let mut dfa = DFA::new();
-        let start = dfa.add_state();
-        let end = dfa.add_state();
+    /// Returns true if both DFAs accept exactly the same language. The syntactic categories of the
+    /// accept states are not compared. The DFAs may have been built from different alphabets, so
+    /// both are first completed over the union of their alphabets, sending every character one of
+    /// them has no transition on to a rejecting trap state, and are then stepped together.
+    pub fn equivalent(&self, other: &DFA) -> bool {
+        let alphabet: HashSet<char> = self.alphabet.union(&other.alphabet).cloned().collect();
+        let dfa1 = self.complete(&alphabet);
+        let dfa2 = other.complete(&alphabet);
 
-        assert_eq!(dfa.get_num_states(), 2);
-        assert_eq!(dfa.get_start_state(), 0);
-        assert_eq!(dfa.get_acceptor_states().len(), 2);
-        assert!(!dfa.get_acceptor_states()[end]);
-        assert!(!dfa.get_acceptor_states()[start]);
+        let mut visited: HashSet<(usize, usize)> = HashSet::new();
+        let mut work_list: VecDeque<(usize, usize)> = VecDeque::new();
 
-        // Mark end as accept state
-        dfa.accept_states.set(end, true);
-        assert!(dfa.get_acceptor_states()[end]);
+        let start_pair = (dfa1.start_state, dfa2.start_state);
 
-        // Add transition
-        dfa.states[start].transitions.insert(Symbol::Char('a'), end);
-        let transitions = dfa.get_state_transitions(start);
-        assert_eq!(transitions.len(), 1);
-        assert_eq!(*transitions[0].0, Symbol::Char('a'));
-        assert_eq!(*transitions[0].1, end);
-    }
+        visited.insert(start_pair);
+        work_list.push_back(start_pair);
 
-    #[test]
-    fn test_lookup_table() {
-        let mut lookup_table = LookupTable::new();
+        while let Some((state1, state2)) = work_list.pop_front() {
+            if dfa1.accept_states[state1] != dfa2.accept_states[state2] {
+                return false;
+            }
 
-        // Test inserting states into sets
-        lookup_table.insert_state_in_set(0, 1);
-        lookup_table.insert_state_in_set(1, 1);
-        lookup_table.insert_state_in_set(2, 2);
+            for ch in alphabet.iter() {
+                let next1 = dfa1.states[state1].transitions[&Symbol::Char(*ch)];
+                let next2 = dfa2.states[state2].transitions[&Symbol::Char(*ch)];
 
-        assert_eq!(lookup_table.get_set_of_state(&0), Some(&1));
-        assert_eq!(lookup_table.get_set_of_state(&1), Some(&1));
-        assert_eq!(lookup_table.get_set_of_state(&2), Some(&2));
-        assert_eq!(lookup_table.get_num_sets(), 2);
+                if visited.insert((next1, next2)) {
+                    work_list.push_back((next1, next2));
+                }
+            }
+        }
 
-        // Test moving a state to a different set
-        lookup_table.insert_state_in_set(0, 2);
-        assert_eq!(lookup_table.get_set_of_state(&0), Some(&2));
+        return true;
+    }
 
-        // Get sets
-        let sets: Vec<HashSet<usize>> = lookup_table.get_sets().cloned().collect();
-        assert_eq!(sets.len(), 2);
+    /// Returns true if every string accepted by this DFA is also accepted by the other DFA, which
+    /// holds when the intersection of this language with the complement of the other is empty.
+    /// Both DFAs are completed over the union of their alphabets and stepped together, and the
+    /// check fails as soon as a pair of states is reached where this DFA accepts and the other one
+    /// does not. This detects, for example, a keyword pattern subsumed by an identifier pattern.
+    pub fn is_subset_of(&self, other: &DFA) -> bool {
+        let alphabet: HashSet<char> = self.alphabet.union(&other.alphabet).cloned().collect();
+        let dfa1 = self.complete(&alphabet);
+        let dfa2 = other.complete(&alphabet);
 
-        // Check if sets contain correct elements
-        let set1 = sets.iter().find(|set| set.contains(&1)).unwrap();
-        let set2 = sets
-            .iter()
-            .find(|set| set.contains(&0) && set.contains(&2))
-            .unwrap();
+        let mut visited: HashSet<(usize, usize)> = HashSet::new();
+        let mut work_list: VecDeque<(usize, usize)> = VecDeque::new();
 
-        assert_eq!(set1.len(), 1);
-        assert_eq!(set2.len(), 2);
-    }
+        let start_pair = (dfa1.start_state, dfa2.start_state);
 
-    #[test]
-    fn test_construct_minimal_dfa() {
-        // Create a DFA with redundant states
-        let mut dfa = DFA::new();
+        visited.insert(start_pair);
+        work_list.push_back(start_pair);
 
-        // Add states
-        let s0 = dfa.add_state(); // start state
-        let s1 = dfa.add_state(); // accepts 'a'
-        let s2 = dfa.add_state(); // accepts 'b'
-        let s3 = dfa.add_state(); // duplicate of s1, accepts 'a'
+        while let Some((state1, state2)) = work_list.pop_front() {
+            if dfa1.accept_states[state1] && !dfa2.accept_states[state2] {
+                return false;
+            }
 
-        // Set start state
-        dfa.start_state = s0;
+            for ch in alphabet.iter() {
+                let next1 = dfa1.states[state1].transitions[&Symbol::Char(*ch)];
+                let next2 = dfa2.states[state2].transitions[&Symbol::Char(*ch)];
 
-        // Add characters to alphabet
-        dfa.alphabet.insert('a');
-        dfa.alphabet.insert('b');
+                if visited.insert((next1, next2)) {
+                    work_list.push_back((next1, next2));
+                }
+            }
+        }
 
-        // Add transitions
-        dfa.states[s0].transitions.insert(Symbol::Char('a'), s1);
-        dfa.states[s0].transitions.insert(Symbol::Char('b'), s2);
-        dfa.states[s1].transitions.insert(Symbol::Char('a'), s1);
-        dfa.states[s1].transitions.insert(Symbol::Char('b'), s2);
-        dfa.states[s2].transitions.insert(Symbol::Char('a'), s3);
-        dfa.states[s2].transitions.insert(Symbol::Char('b'), s2);
-        dfa.states[s3].transitions.insert(Symbol::Char('a'), s3);
-        dfa.states[s3].transitions.insert(Symbol::Char('b'), s2);
+        return true;
+    }
 
-        // Set accept states
-        dfa.accept_states.set(s1, true);
-        dfa.accept_states.set(s3, true);
+    // Returns the category shared by all accept states, or an empty string if the accept states
+    // belong to different categories.
+    fn common_category(&self) -> String {
+        let categories: HashSet<&String> = self
+            .accept_states
+            .iter_ones()
+            .map(|accept| &self.states[accept].category)
+            .collect();
+
+        if categories.len() == 1 {
+            categories.into_iter().next().unwrap().to_string()
+        } else {
+            String::new()
+        }
+    }
 
-        // Set categories
-        dfa.states[s1].category = "A".to_string();
-        dfa.states[s3].category = "A".to_string();
+    /// Build an NFA accepting the reverse of the language of the DFA. Every edge is reversed, a new
+    /// start state is added with epsilon transitions to all the old accept states and the old start
+    /// state becomes the only accept state. The accept state keeps the syntactic category only if
+    /// all the accept states of the DFA belong to the same category.
+    pub fn reverse(&self) -> NFA {
+        let mut nfa = NFA::new();
+
+        for _ in 0..self.states.len() {
+            nfa.add_state();
+        }
+
+        for state in self.states.iter() {
+            for (symbol, target) in state.transitions.iter() {
+                nfa.add_transition(*target, symbol.clone(), state.id);
+            }
+        }
+
+        let new_start = nfa.add_state();
+
+        for accept in self.accept_states.iter_ones() {
+            nfa.add_transition(new_start, Symbol::Epsilon, accept);
+        }
+
+        nfa.set_start_state(new_start);
+        nfa.set_accept_state(self.start_state, &self.common_category());
+        nfa.set_regex(&self.regex);
+
+        return nfa;
+    }
+
+    // Determinize the reversed DFA. The subset construction starts directly from the old accept
+    // states rather than the new start state of the reversed NFA, otherwise the start state of the
+    // result would be a subset which is distinct from, but equivalent to, a later subset.
+    fn determinize_reverse(&self) -> DFA {
+        let nfa = self.reverse();
+
+        let mut nfa_states = BitVec::repeat(false, nfa.get_num_states());
+        for accept in self.accept_states.iter_ones() {
+            nfa_states.set(accept, true);
+        }
+
+        let mut result = subset_construction(
+            &nfa,
+            nfa_states,
+            &TieBreak::default(),
+            &mut ConstructionMetrics::default(),
+            None,
+            None,
+        )
+        .expect("The reverse of a DFA only refers to its own states");
+
+        result.category_regexes.clear(); // The regexes do not describe the reverse
+        return result;
+    }
+
+    /// Build a DFA for the reverse language, which accepts a string exactly when this DFA accepts
+    /// the string read backwards. Like minimize_brzozowski, the accept states of the result have an
+    /// empty category, as the accept states of the reverse are the old start state.
+    pub fn reverse_dfa(&self) -> DFA {
+        let mut reversed_dfa = self.determinize_reverse();
+        reversed_dfa.regex = String::new(); // The regex of this DFA does not describe the reverse
+        reversed_dfa
+    }
+
+    /// Minimize the DFA using Brzozowski's algorithm, by reversing and determinizing the DFA
+    /// twice. This only preserves the language of the DFA, so if the accept states belong to
+    /// different syntactic categories the categories are lost and the accept states of the result
+    /// have an empty category. Use construct_minimal_dfa for DFAs with multiple categories.
+    pub fn minimize_brzozowski(&self) -> DFA {
+        let reversed_dfa = self.determinize_reverse();
+        let mut minimal_dfa = reversed_dfa.determinize_reverse();
+
+        let categories = minimal_dfa.categories();
+        minimal_dfa.category_regexes = self
+            .category_regexes
+            .iter()
+            .filter(|(category, _)| categories.contains(*category))
+            .map(|(category, regex)| (category.clone(), regex.clone()))
+            .collect();
+        minimal_dfa.category_order = self
+            .category_order
+            .iter()
+            .filter(|category| categories.contains(*category))
+            .cloned()
+            .collect();
+        minimal_dfa.skip_categories = self
+            .skip_categories
+            .iter()
+            .filter(|category| categories.contains(*category))
+            .cloned()
+            .collect();
+
+        return minimal_dfa;
+    }
+
+    /// Build the minimal DFA accepting exactly the given strings, without going through the regex
+    /// parser, Thompson construction and subset construction. The words are inserted into a prefix
+    /// trie which is then minimized, so common suffixes are shared as well. The accept states have
+    /// an empty category, and the regex of the DFA is the alternation of the escaped words.
+    pub fn from_strings(words: &[&str]) -> DFA {
+        let mut trie = build_trie(words, "");
+        let escaped: Vec<String> = words.iter().map(|word| escape_literal(word)).collect();
+        trie.regex = escaped.join("|");
+
+        construct_minimal_dfa(&trie, false)
+    }
+
+    /// Export the DFA as CSV text so that it can be loaded from other languages. The output has
+    /// three sections separated by blank lines: the transition matrix (one row per state, one
+    /// column per character of the sorted alphabet, -1 marking a missing transition), the start
+    /// state and the list of accept states along with their syntactic categories.
+    pub fn to_table_csv(&self) -> String {
+        let mut alphabet: Vec<char> = self.alphabet.iter().cloned().collect();
+        alphabet.sort();
+
+        let mut csv = String::new();
+
+        // Transition matrix section
+
+        csv.push_str("# transitions\n");
+        csv.push_str("state");
+        for ch in alphabet.iter() {
+            csv.push(',');
+            csv.push_str(&csv_field(&ch.to_string()));
+        }
+        csv.push('\n');
+
+        for state in self.states.iter() {
+            csv.push_str(&state.id.to_string());
+            for ch in alphabet.iter() {
+                let cell = match state.transitions.get(&Symbol::Char(*ch)) {
+                    Some(target) => target.to_string(),
+                    None => "-1".to_string(), // Dead cell marker
+                };
+                csv.push(',');
+                csv.push_str(&cell);
+            }
+            csv.push('\n');
+        }
+
+        // Start state section
+
+        csv.push_str("\n# start\n");
+        csv.push_str(&format!("{}\n", self.start_state));
+
+        // Accept states section
+
+        csv.push_str("\n# accept\n");
+        csv.push_str("state,category\n");
+        for accept in self.accept_states.iter_ones() {
+            let category = &self.states[accept].category;
+            csv.push_str(&format!("{},{}\n", accept, csv_field(category)));
+        }
+
+        return csv;
+    }
+
+    /// Export the DFA in a plain line based text format which other automata tools can read. The
+    /// format is made of the following lines, with the fields separated by single spaces:
+    ///
+    /// ```text
+    /// states <number of states>
+    /// alphabet <symbol> <symbol> ...
+    /// start <state>
+    /// accept <state> [category]
+    /// <from> <symbol> <to>
+    /// ```
+    ///
+    /// There is one accept line per accept state and one transition line per transition. A symbol
+    /// is written as the character itself, except for whitespace, control characters and the
+    /// backslash which are written as `\u{hex}`. Lines starting with # are comments.
+    pub fn to_text_format(&self) -> String {
+        let mut alphabet: Vec<char> = self.alphabet.iter().cloned().collect();
+        alphabet.sort();
+
+        let mut text = String::new();
+
+        text.push_str(&format!("states {}\n", self.states.len()));
+
+        text.push_str("alphabet");
+        for ch in alphabet.iter() {
+            text.push(' ');
+            text.push_str(&text_symbol(*ch));
+        }
+        text.push('\n');
+
+        text.push_str(&format!("start {}\n", self.start_state));
+
+        for accept in self.accept_states.iter_ones() {
+            let category = &self.states[accept].category;
+            if category.is_empty() {
+                text.push_str(&format!("accept {}\n", accept));
+            } else {
+                text.push_str(&format!("accept {} {}\n", accept, category));
+            }
+        }
+
+        for (from, transitions) in self.adjacency() {
+            for (ch, to) in transitions {
+                text.push_str(&format!("{} {} {}\n", from, text_symbol(ch), to));
+            }
+        }
+
+        return text;
+    }
+
+    /// Import a DFA written in the text format of to_text_format. Returns an error naming the
+    /// first line that could not be read, or if the DFA described is not well formed.
+    pub fn from_text_format(text: &str) -> Result<DFA> {
+        let mut result = DFA::new();
+        let mut start_state = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let invalid =
+                |reason: &str| DFAError::InvalidTextFormat(line_number, reason.to_string());
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split(' ').collect();
+
+            match fields[0] {
+                "states" => {
+                    if fields.len() != 2 || !result.states.is_empty() {
+                        return Err(invalid("expected a single states line with a count").into());
+                    }
+                    let count: usize = fields[1]
+                        .parse()
+                        .map_err(|_| invalid("the state count is not a number"))?;
+                    for _ in 0..count {
+                        result.add_state();
+                    }
+                }
+                "alphabet" => {
+                    for field in fields.iter().skip(1) {
+                        let ch = parse_text_symbol(field)
+                            .ok_or_else(|| invalid("the alphabet has an invalid symbol"))?;
+                        result.alphabet.insert(ch);
+                    }
+                }
+                "start" => {
+                    if fields.len() != 2 {
+                        return Err(invalid("expected a single start state").into());
+                    }
+                    let start: usize = fields[1]
+                        .parse()
+                        .map_err(|_| invalid("the start state is not a number"))?;
+                    start_state = Some(start);
+                }
+                "accept" => {
+                    if fields.len() < 2 {
+                        return Err(invalid("expected an accept state").into());
+                    }
+                    let accept: usize = fields[1]
+                        .parse()
+                        .map_err(|_| invalid("the accept state is not a number"))?;
+                    if accept >= result.states.len() {
+                        return Err(invalid("the accept state does not exist").into());
+                    }
+                    result.accept_states.set(accept, true);
+                    result.states[accept].category = fields[2..].join(" ");
+                }
+                _ => {
+                    if fields.len() != 3 {
+                        return Err(invalid("expected a transition as from symbol to").into());
+                    }
+                    let from: usize = fields[0]
+                        .parse()
+                        .map_err(|_| invalid("the source state is not a number"))?;
+                    let ch = parse_text_symbol(fields[1])
+                        .ok_or_else(|| invalid("the transition has an invalid symbol"))?;
+                    let to: usize = fields[2]
+                        .parse()
+                        .map_err(|_| invalid("the target state is not a number"))?;
+                    if from >= result.states.len() {
+                        return Err(invalid("the source state does not exist").into());
+                    }
+                    result.alphabet.insert(ch);
+                    result.states[from].transitions.insert(Symbol::Char(ch), to);
+                }
+            }
+        }
+
+        result.start_state = start_state.ok_or_else(|| {
+            DFAError::InvalidTextFormat(text.lines().count(), "no start state".to_string())
+        })?;
+
+        result.validate()?;
+
+        return Ok(result);
+    }
+}
+
+// Write a symbol of the text format, escaping the characters that would break the line apart
+fn text_symbol(ch: char) -> String {
+    if ch.is_whitespace() || ch.is_control() || ch == '\\' {
+        format!("\\u{{{:x}}}", ch as u32)
+    } else {
+        ch.to_string()
+    }
+}
+
+// Read a symbol of the text format, either a single character or an escaped code point
+fn parse_text_symbol(field: &str) -> Option<char> {
+    if let Some(hex) = field
+        .strip_prefix("\\u{")
+        .and_then(|field| field.strip_suffix('}'))
+    {
+        return u32::from_str_radix(hex, 16).ok().and_then(char::from_u32);
+    }
+
+    let mut chars = field.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Some(ch),
+        _ => None,
+    }
+}
+
+// Quote a CSV field if it contains characters which would otherwise break the row
+fn csv_field(field: &str) -> String {
+    if field.contains(|ch| matches!(ch, ',' | '"' | '\n' | '\r')) || field.trim() != field {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn get_epsilon_closure(nfa: &NFA, nfa_states: BitVec<u8>) -> Result<BitVec<u8>, DFAError> {
+    let num_states: usize = nfa.get_num_states();
+
+    let mut epsilon_closure: BitVec<u8, Lsb0> = BitVec::repeat(false, num_states);
+
+    let mut visited: BitVec<u8, Lsb0> = BitVec::repeat(false, num_states);
+
+    let mut nfa_states: VecDeque<_> = nfa_states.iter_ones().collect();
+
+    while let Some(state) = nfa_states.pop_front() {
+        let state = match nfa.get_state(state) {
+            Ok(state) => state,
+            Err(_) => return Err(DFAError::MissingNFAState(state)),
+        };
+        let transitions = state.get_transitions();
+
+        let eps_transitions = transitions.get(&Symbol::Epsilon);
+        match eps_transitions {
+            Some(targets) => {
+                for target in targets {
+                    let target = *target; // Unboxing the value
+                    if target >= num_states {
+                        return Err(DFAError::MissingNFAState(target));
+                    }
+                    if !visited[target] {
+                        visited.set(target, true);
+                        nfa_states.push_back(target);
+                    }
+                }
+            }
+            None => {}
+        }
+        epsilon_closure.set(state.get_id(), true); // Adding the state itself to the epsilon closure
+    }
+    return Ok(epsilon_closure);
+}
+
+// This function returns the set of states accessible via char c within the set q
+
+fn delta(nfa: &NFA, q: &BitVec<u8>, c: char) -> Result<BitVec<u8>, DFAError> {
+    let mut result = BitVec::repeat(false, q.len());
+    let nodes: Vec<usize> = q.iter_ones().collect();
+    for node in nodes {
+        let nfa_state = match nfa.get_state(node) {
+            Ok(nfa_state) => nfa_state,
+            Err(_) => return Err(DFAError::MissingNFAState(node)),
+        };
+        let transitions = nfa_state.get_transitions();
+        let target_state_ids = transitions.get(&Symbol::Char(c));
+        let target_state_ids = match target_state_ids {
+            None => continue,
+            Some(state_ids) => state_ids,
+        };
+        for state_id in target_state_ids {
+            let state_id = *state_id; // Unwrapping the box
+            if state_id >= result.len() {
+                return Err(DFAError::MissingNFAState(state_id));
+            }
+            result.set(state_id, true);
+        }
+    }
+    return Ok(result);
+}
+
+fn compare_transitions(
+    state1: &DFAState,
+    state2: &DFAState,
+    alphabet: &HashSet<char>,
+    lookup_table: &LookupTable,
+) -> bool {
+    let state1_transitions = state1.get_transitions();
+    let state2_transitions = state2.get_transitions();
+
+    let mut same_transitions = true;
+
+    for c in alphabet {
+        if !same_transitions {
+            break;
+        }
+        let state1_dest = state1_transitions.get(&Symbol::Char(*c)); // Get destination
+                                                                     // for the state for
+                                                                     // this symbol and
+                                                                     // member
+        let state2_dest = state2_transitions.get(&Symbol::Char(*c));
+
+        match (state1_dest, state2_dest) {
+            (None, None) => same_transitions = true, // If both don't have a transition, no splitting
+            (Some(_), None) | (None, Some(_)) => {
+                same_transitions = false;
+            } // If only one has a transition, split
+            (Some(state_dest), Some(member_dest)) => {
+                // If both have transitions,
+                // make sure both transition to
+                // same set
+                let state_dest_set = lookup_table.get_set_of_state(state_dest).unwrap();
+                let member_dest_set = lookup_table.get_set_of_state(member_dest).unwrap();
+
+                same_transitions = state_dest_set == member_dest_set;
+            }
+        }
+    }
+    return same_transitions;
+}
+
+// Refine the partition of the states until no set splits any further. The partition every pass
+// starts from is recorded, if a list of passes is provided. If preserve_categories is set, accept
+// states start in the same set only if all the categories they accept agree, not only the one
+// picked by priority.
+fn get_lookup_table(
+    dfa: &DFA,
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    metrics: &mut ConstructionMetrics,
+    mut passes: Option<&mut Vec<Vec<HashSet<usize>>>>,
+    preserve_categories: bool,
+) -> LookupTable {
+    let alphabet = dfa.get_alphabet();
+    let mut lookup_table = LookupTable::new();
+    let states = dfa.get_acceptor_states();
+    let mut set_changes: VecDeque<(usize, usize)> = VecDeque::new();
+    // 0 is non acceptors states, 1 is acceptor states
+    // If all states are acceptor states, then 0 is the only set id
+
+    let set_id = 0;
+
+    for non_accept_state in states.iter_zeros() {
+        lookup_table.insert_state_in_set(non_accept_state, set_id);
+    }
+
+    let set_id = if states.all() { 0 } else { 1 }; // Test if all bits are 1, i.e all states are
+                                                   // acceptors and there are no non acceptor
+                                                   // states
+
+    let no_categories = BTreeSet::new();
+    // Mapping of category of accept state and set id
+    let mut category_set_id: HashMap<(&String, &BTreeSet<String>), usize> = HashMap::new();
+
+    for accept_state in states.iter_ones() {
+        let state = dfa.get_state(accept_state);
+        let offset_map_len = set_id + category_set_id.len();
+        let categories = match preserve_categories {
+            true => &state.categories,
+            false => &no_categories,
+        };
+
+        let insert_id = category_set_id
+            .entry((&state.category, categories))
+            .or_insert(offset_map_len);
+
+        lookup_table.insert_state_in_set(accept_state, *insert_id);
+    }
+
+    loop {
+        #[cfg(feature = "metrics")]
+        {
+            metrics.refinement_passes += 1;
+        }
+
+        let number_of_sets = lookup_table.get_num_sets(); // Get number of sets at start of
+                                                          // iteration
+        #[cfg(feature = "metrics")]
+        {
+            metrics.peak_state_sets = metrics.peak_state_sets.max(number_of_sets);
+        }
+        let sets: Vec<_> = lookup_table.get_sets().cloned().collect(); // Get list of sets
+
+        if let Some(passes) = passes.as_mut() {
+            let mut partition = sets.clone();
+            partition.sort_by_key(|set| set.iter().min().copied());
+            passes.push(partition);
+        }
+
+        // Try to split the sets further
+
+        for set in sets.iter() {
+            if set.len() == 1 {
+                // Cannot split a set with only 1 element
+                continue;
+            }
+            let next_set = lookup_table.get_num_sets(); // The next set which will be inserted
+            let member_state_id = set.iter().next().unwrap();
+
+            let member_state = dfa.get_state(*member_state_id);
+
+            for state_id in set {
+                let state = dfa.get_state(*state_id);
+
+                if !compare_transitions(state, member_state, alphabet, &lookup_table) {
+                    set_changes.push_back((*state_id, next_set));
+                }
+            }
+
+            while !set_changes.is_empty() {
+                let change = set_changes.pop_front().unwrap();
+                lookup_table.insert_state_in_set(change.0, change.1);
+            }
+        }
+        let new_number_of_sets = lookup_table.get_num_sets();
+
+        if number_of_sets == new_number_of_sets {
+            break;
+        }
+    }
+    return lookup_table;
+}
+// Renumber the states in DFS order from the start state. The map from the old state ids to the new
+// ones is returned alongside the re-ordered DFA
+fn reorder_minimal_dfa(dfa: &DFA) -> (DFA, HashMap<usize, usize>) {
+    let mut result = DFA::new(); // Set up result DFA
+    let mut reorder_map = HashMap::new(); // Set up a re-order table
+    let mut stack: VecDeque<usize> = VecDeque::new(); // Set up a stack for DFS
+
+    let mut visited: BitVec<u8, Lsb0> = BitVec::repeat(false, dfa.states.len());
+
+    for _ in 0..dfa.states.len() {
+        // Add as many states as in the initial DFA because
+        // we are not adding or removing ant states, just
+        // re-ordering them.
+        result.add_state();
+    }
+    let dfa_start = dfa.start_state; // Get the starting dfa state
+
+    let mut next_id = 0;
+
+    stack.push_front(dfa_start); // Add the start state to the stack
+
+    while !stack.is_empty() {
+        // Start DFS
+        let state_id = stack.pop_front().unwrap(); // Get the head of stack
+
+        if *visited.get(state_id).unwrap() {
+            // If node is already visited skip
+            continue;
+        }
+
+        visited.set(state_id, true); // Mark the current node as visited
+
+        let reorder_state_id = match reorder_map.get(&state_id) {
+            Some(&id) => id,
+            None => {
+                reorder_map.insert(state_id, next_id);
+                let reordered_id = next_id;
+                next_id = next_id + 1;
+                reordered_id
+            }
+        }; // Get the re-ordered equivalent state or add one
+
+        let state = &dfa.states[state_id]; // Get the state from the dfa
+
+        // Get the transitions from the original state, sorted so the new ids do not depend on
+        // the order of the hash map
+        let mut transitions: Vec<(&Symbol, &usize)> = state.transitions.iter().collect();
+        transitions.sort();
+
+        let reorder_state: &mut DFAState = result.states.get_mut(reorder_state_id).unwrap(); // Get the state from the re-ordered DFA
+
+        for transition in transitions {
+            // For each transition, check if the target state is
+            // present
+            let symbol = transition.0.clone();
+            let target = transition.1;
+
+            let reorder_target_id = match reorder_map.get(&target) {
+                // If not present, take the next available state and map it to the current
+                // un-ordered state
+                Some(&id) => id,
+                None => {
+                    let state_id = next_id;
+                    reorder_map.insert(*target, state_id);
+                    next_id = next_id + 1; // Pick the next available id
+                    state_id
+                }
+            };
+
+            reorder_state.transitions.insert(symbol, reorder_target_id); // Add a transition from
+                                                                         // the reordered state to
+                                                                         // the reordered target
+            stack.push_front(*target); // Add the target to the head of the stack now
+        }
+    }
+
+    let start_state = dfa.start_state;
+
+    let reordered_start_state = reorder_map.get(&start_state).unwrap();
+    result.start_state = *reordered_start_state;
+
+    // Mark the acceptor states
+
+    for accept in dfa.accept_states.iter_ones() {
+        let remapped_id = reorder_map.get(&accept).unwrap();
+        let category = &dfa.states[accept].category;
+        result.accept_states.set(*remapped_id, true);
+        result.set_accept_category(category);
+        result.states[*remapped_id].categories = dfa.states[accept].categories.clone();
+        result.states[*remapped_id].categories_lost = dfa.states[accept].categories_lost;
+    }
+
+    return (result, reorder_map);
+}
+/// A source of characters addressed by byte offsets, which lets the DFA match input that is not
+/// stored as one contiguous string, such as the rope of an editor buffer
+pub trait CharSource {
+    /// Get the character starting at the byte offset along with its length in bytes, or None at
+    /// the end of the source
+    fn char_at(&self, byte: usize) -> Option<(char, usize)>;
+}
+
+impl CharSource for str {
+    fn char_at(&self, byte: usize) -> Option<(char, usize)> {
+        let ch = self.get(byte..)?.chars().next()?;
+        Some((ch, ch.len_utf8()))
+    }
+}
+
+// The character ending at the byte offset of the source, found by trying every length a character
+// can have in UTF-8
+fn char_before<S: CharSource + ?Sized>(source: &S, offset: usize) -> Option<char> {
+    (1..=offset.min(4)).find_map(|len| match source.char_at(offset - len) {
+        Some((ch, ch_len)) if ch_len == len => Some(ch),
+        _ => None,
+    })
+}
+
+// The characters a \b word boundary separates from all the others
+pub(crate) fn is_word_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_'
+}
+
+/// Where the ^ and $ anchors match when searching with DFA::find
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AnchorMode {
+    /// ^ and $ only match at the start and end of the whole input
+    #[default]
+    SingleLine,
+    /// ^ also matches after every newline and $ before every newline
+    MultiLine,
+}
+
+/// The progress of a Matcher after being fed more input
+#[derive(Debug, PartialEq, Eq)]
+pub enum FeedResult {
+    /// A token was recognized, holding its length in bytes and its syntactic category. If the
+    /// input seen so far extends past the token, the token is complete and the rest of the input
+    /// belongs to the next token.
+    Accept(usize, String),
+    /// The input seen so far is a proper prefix of a token, more input is needed
+    NeedMore,
+    /// No token can start with the input seen so far
+    Reject,
+}
+
+/// Runs a longest match over input which arrives in pieces, such as text typed in a REPL or an
+/// editor. The matcher keeps its position in the DFA between calls to feed. The characters after
+/// the current position may not have arrived yet, so anchors and word boundaries never match, use
+/// longest_match once the whole input is known instead.
+pub struct Matcher<'a> {
+    dfa: &'a DFA,
+    state: Option<usize>, // None once the DFA has reached the implicit error state
+    consumed: usize,
+    last_accept: Option<(usize, String)>,
+}
+
+impl<'a> Matcher<'a> {
+    /// Create a matcher positioned at the start state of the DFA
+    pub fn new(dfa: &'a DFA) -> Self {
+        let mut matcher = Matcher {
+            dfa,
+            state: None,
+            consumed: 0,
+            last_accept: None,
+        };
+        matcher.reset();
+        matcher
+    }
+
+    /// Forget all input fed so far and start matching a new token
+    pub fn reset(&mut self) {
+        self.consumed = 0;
+        self.last_accept = None;
+        self.state = if self.dfa.states.is_empty() {
+            None
+        } else {
+            Some(self.dfa.start_state)
+        };
+
+        if let Some(state) = self.state {
+            if self.dfa.accept_states[state] {
+                self.last_accept = Some((0, self.dfa.states[state].category.clone()));
+            }
+        }
+    }
+
+    /// Feed the next piece of input and report whether the input seen so far is a token, may
+    /// still become one, or can never be one.
+    pub fn feed(&mut self, input: &str) -> FeedResult {
+        for ch in input.chars() {
+            let state = match self.state {
+                Some(state) => state,
+                None => break,
+            };
+
+            self.state = self.dfa.transition(state, ch);
+            self.consumed += ch.len_utf8();
+
+            if let Some(state) = self.state {
+                if self.dfa.accept_states[state] {
+                    let category = self.dfa.states[state].category.clone();
+                    self.last_accept = Some((self.consumed, category));
+                }
+            }
+        }
+
+        match (self.state, &self.last_accept) {
+            // Sitting on an accept state, the token may still grow but it is already valid
+            (Some(state), Some((length, category))) if self.dfa.accept_states[state] => {
+                FeedResult::Accept(*length, category.clone())
+            }
+            (Some(_), _) => FeedResult::NeedMore,
+            (None, Some((length, category))) => FeedResult::Accept(*length, category.clone()),
+            (None, None) => FeedResult::Reject,
+        }
+    }
+}
+
+/// The kind of conflict found between two microsyntaxes
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Both microsyntaxes accept exactly the same language
+    Equivalent,
+    /// Every lexeme accepted by one microsyntax is also accepted by the other
+    Subsumed,
+}
+
+/// A conflict between two microsyntaxes. The microsyntax described by regex and category is
+/// subsumed by (or equivalent to) the one described by other_regex and other_category.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    pub regex: String,
+    pub category: String,
+    pub other_regex: String,
+    pub other_category: String,
+    /// True if the subsumed microsyntax has the lower priority, so it can never produce a token
+    pub shadowed: bool,
+}
+
+impl Conflict {
+    fn new(
+        kind: ConflictKind,
+        rule: (&String, &String),
+        other_rule: (&String, &String),
+        shadowed: bool,
+    ) -> Self {
+        Conflict {
+            kind,
+            regex: rule.0.to_string(),
+            category: rule.1.to_string(),
+            other_regex: other_rule.0.to_string(),
+            other_category: other_rule.1.to_string(),
+            shadowed,
+        }
+    }
+}
+
+/// Analyze a list of parsed microsyntaxes for conflicts. A DFA is built for every microsyntax and
+/// each pair is checked for equivalent languages or one language being a subset of the other.
+/// Such conflicts are common (keywords are usually subsumed by identifiers), but a shadowed
+/// microsyntax which can never produce a token is likely a bug in the specification. Microsyntaxes
+/// whose automaton cannot be built are left out of the analysis.
+pub fn analyze_conflicts(entries: &VecDeque<(String, RegEx, String, RuleFlags)>) -> Vec<Conflict> {
+    let mut dfa_list = Vec::new();
+
+    for (regex, syntax_tree, category, flags) in entries.iter() {
+        let mut syntax_tree_list = VecDeque::new();
+        syntax_tree_list.push_back((regex.clone(), syntax_tree.clone(), category.clone(), *flags));
+
+        let dfa = construct_nfa(syntax_tree_list, false)
+            .ok()
+            .and_then(|nfa| construct_dfa(&nfa, false).ok());
+        if let Some(dfa) = dfa {
+            dfa_list.push((regex, category, dfa));
+        }
+    }
+
+    let mut conflicts = Vec::new();
+
+    for (i, (regex, category, dfa)) in dfa_list.iter().enumerate() {
+        for (other_regex, other_category, other_dfa) in dfa_list.iter().skip(i + 1) {
+            // The microsyntax at index i has the higher priority
+            let subset = dfa.is_subset_of(other_dfa);
+            let superset = other_dfa.is_subset_of(dfa);
+
+            let rule = (*regex, *category);
+            let other_rule = (*other_regex, *other_category);
+
+            if subset && superset {
+                conflicts.push(Conflict::new(
+                    ConflictKind::Equivalent,
+                    other_rule,
+                    rule,
+                    true,
+                ));
+            } else if superset {
+                conflicts.push(Conflict::new(
+                    ConflictKind::Subsumed,
+                    other_rule,
+                    rule,
+                    true,
+                ));
+            } else if subset {
+                conflicts.push(Conflict::new(
+                    ConflictKind::Subsumed,
+                    rule,
+                    other_rule,
+                    false,
+                ));
+            }
+        }
+    }
+
+    conflicts
+}
+
+/// Returns the longest common prefix of the microsyntaxes which are literal strings, like `if`,
+/// `int` and `import` sharing `i`. A flat alternation of literals like `if|else` adds each of its
+/// strings, and the other microsyntaxes are ignored. Returns None if no microsyntax is literal.
+pub fn longest_common_literal_prefix(
+    entries: &VecDeque<(String, RegEx, String, RuleFlags)>,
+) -> Option<String> {
+    let mut literals = entries
+        .iter()
+        .filter_map(|(_, syntax_tree, _, _)| literal_alternatives(syntax_tree))
+        .flatten();
+
+    let mut prefix: Vec<char> = literals.next()?.chars().collect();
+
+    for literal in literals {
+        let common = prefix
+            .iter()
+            .zip(literal.chars())
+            .take_while(|(a, b)| **a == *b)
+            .count();
+        prefix.truncate(common);
+    }
+
+    Some(prefix.into_iter().collect())
+}
+
+/// Apply Hopcroft's algorithm on a provided DFA to minimize it. If save_minimal_dfa is set to true,
+/// the constructed minimal DFA is saved as a jpg.
+pub fn construct_minimal_dfa(dfa: &DFA, save_minimal_dfa: bool) -> DFA {
+    let (result, _) = minimize(dfa, &mut ConstructionMetrics::default(), None, false);
+
+    if save_minimal_dfa {
+        let filename = format!("constructed_minimal_dfa");
+        result.show_fa(&filename);
+    }
+
+    return result;
+}
+
+/// Apply Hopcroft's algorithm like construct_minimal_dfa, but only merge accept states if every
+/// category they accept agrees, not only the one picked by priority. The result may have more
+/// states, and DFA::match_all_categories reports the same categories on it as on the input DFA.
+pub fn construct_minimal_dfa_preserving_categories(dfa: &DFA, save_minimal_dfa: bool) -> DFA {
+    let (result, _) = minimize(dfa, &mut ConstructionMetrics::default(), None, true);
+
+    if save_minimal_dfa {
+        result.show_fa("constructed_minimal_dfa");
+    }
+    result
+}
+
+/// Apply Hopcroft's algorithm like construct_minimal_dfa and also return the counters recorded
+/// while minimizing. Only the minimization fields of the metrics are filled in.
+#[cfg(feature = "metrics")]
+pub fn construct_minimal_dfa_with_metrics(dfa: &DFA) -> (DFA, ConstructionMetrics) {
+    let mut metrics = ConstructionMetrics::default();
+    let (result, _) = minimize(dfa, &mut metrics, None, false);
+    (result, metrics)
+}
+
+/// Apply Hopcroft's algorithm like construct_minimal_dfa and also return, for every state of the
+/// minimal DFA, the sorted list of states of the input DFA that were merged into it. A minimal
+/// state whose list has more than one entry stands for a group of equivalent states.
+pub fn minimize_with_mapping(dfa: &DFA) -> (DFA, HashMap<usize, Vec<usize>>) {
+    minimize(dfa, &mut ConstructionMetrics::default(), None, false)
+}
+
+/// Apply Hopcroft's algorithm like construct_minimal_dfa and also return the partition of the
+/// states of the input DFA that every refinement pass started from, as a teaching aid to watch the
+/// sets split. The sets of a partition are ordered by their lowest state. The last pass splits
+/// nothing, so its partition is the final one, with one set per state of the minimal DFA.
+pub fn minimize_with_passes(dfa: &DFA) -> (DFA, Vec<Vec<HashSet<usize>>>) {
+    let mut passes = Vec::new();
+    let (result, _) = minimize(
+        dfa,
+        &mut ConstructionMetrics::default(),
+        Some(&mut passes),
+        false,
+    );
+    (result, passes)
+}
+
+/// Format the partitions recorded by minimize_with_passes, one line per pass listing its sets
+/// with their states in increasing order, such as `Pass 1: {0, 1, 2} {3}`.
+pub fn format_partition_passes(passes: &[Vec<HashSet<usize>>]) -> String {
+    let mut text = String::new();
+
+    for (pass, partition) in passes.iter().enumerate() {
+        let sets: Vec<String> = partition
+            .iter()
+            .map(|set| {
+                let mut states: Vec<usize> = set.iter().copied().collect();
+                states.sort();
+                let states: Vec<String> = states.iter().map(|state| state.to_string()).collect();
+                format!("{{{}}}", states.join(", "))
+            })
+            .collect();
+        text.push_str(&format!("Pass {}: {}\n", pass + 1, sets.join(" ")));
+    }
+
+    return text;
+}
+
+fn minimize(
+    dfa: &DFA,
+    metrics: &mut ConstructionMetrics,
+    passes: Option<&mut Vec<Vec<HashSet<usize>>>>,
+    preserve_categories: bool,
+) -> (DFA, HashMap<usize, Vec<usize>>) {
+    let lookup_table = get_lookup_table(&dfa, metrics, passes, preserve_categories);
+    let sets = lookup_table.set_to_states_map.values();
+
+    // Create a new DFA
+
+    let mut minimal_dfa = DFA::new();
+
+    // Clone the alphabet for the new DFA
+
+    minimal_dfa.alphabet = dfa.alphabet.clone();
+
+    minimal_dfa.regex = dfa.regex.to_string();
+
+    minimal_dfa.category_regexes = dfa.category_regexes.clone();
+
+    minimal_dfa.category_order = dfa.category_order.clone();
+
+    minimal_dfa.skip_categories = dfa.skip_categories.clone();
+
+    // For every set in the lookup table, add a state
+
+    for _ in 0..(lookup_table.set_to_states_map.len()) {
+        minimal_dfa.add_state();
+    }
+
+    // Get the set to which the current DFA's start state belongs to and mark the state
+    // corresponsing to that set id as the starting state.
+
+    let start_state = dfa.start_state;
+
+    let start_set = lookup_table.state_to_set_map.get(&start_state).unwrap();
+
+    minimal_dfa.start_state = *start_set;
+
+    // Repeat the same process as above for the acceptor states
+
+    let acceptor_states = &dfa.accept_states;
+
+    for accept_state in acceptor_states.iter_ones() {
+        let category = &dfa.states[accept_state].category;
+        if let Some(accept_set) = lookup_table.state_to_set_map.get(&accept_state) {
+            let categories = &dfa.states[accept_state].categories;
+
+            // A merged state keeps only the categories accepted by all the states merged into it,
+            // and remembers if the others were lost
+            let merged_state = &mut minimal_dfa.states[*accept_set];
+            if minimal_dfa.accept_states[*accept_set] {
+                if merged_state.categories != *categories
+                    || dfa.states[accept_state].categories_lost
+                {
+                    merged_state.categories_lost = true;
+                }
+                merged_state.categories = merged_state
+                    .categories
+                    .intersection(categories)
+                    .cloned()
+                    .collect();
+            } else {
+                merged_state.categories = categories.clone();
+                merged_state.categories_lost = dfa.states[accept_state].categories_lost;
+            }
+            minimal_dfa.accept_states.set(*accept_set, true);
+            minimal_dfa.set_accept_category(category);
+        }
+    }
+
+    for set in sets {
+        // For each set in which all elements have the same set of transitions
+        let representative = set.iter().next().unwrap(); // Pick a representative member
+
+        let transitions = &dfa.states[*representative].transitions; // Get the set of
+                                                                    // transitiosn for the
+                                                                    // representative
+        let current_set = lookup_table.state_to_set_map.get(representative).unwrap(); // Get the
+                                                                                      // representative
+                                                                                      // state's
+                                                                                      // set
+
+        for transition in transitions {
+            let destination_state = transition.1;
+            let destination_set = lookup_table
+                .state_to_set_map
+                .get(&destination_state)
+                .unwrap();
+
+            minimal_dfa.states[*current_set]
+                .transitions
+                .insert(transition.0.clone(), *destination_set);
+        }
+    }
+
+    let regex = &minimal_dfa.regex;
+
+    let (mut result, reorder_map) = reorder_minimal_dfa(&minimal_dfa);
+    result.alphabet = minimal_dfa.alphabet.clone();
+    result.regex = regex.to_string();
+    result.category_regexes = minimal_dfa.category_regexes.clone();
+    result.category_order = minimal_dfa.category_order.clone();
+    result.skip_categories = minimal_dfa.skip_categories.clone();
+
+    // Translate the set ids into the re-ordered state ids to report which original states each
+    // minimal state subsumes
+
+    let mut mapping = HashMap::new();
+
+    for (set_id, states) in &lookup_table.set_to_states_map {
+        if let Some(&minimal_state) = reorder_map.get(set_id) {
+            let mut merged: Vec<usize> = states.iter().copied().collect();
+            merged.sort();
+            mapping.insert(minimal_state, merged);
+        }
+    }
+
+    return (result, mapping); // We need to always reorder now as visualization is possible
+}
+/// The DFAs built while compiling a regular expression with compile_stages, kept apart so that
+/// both the DFA built by subset construction and its minimized form can be inspected.
+#[derive(Debug)]
+pub struct CompiledStages {
+    /// The DFA built by subset construction, or the keyword trie for a list of keywords
+    pub dfa: DFA,
+    /// The minimal DFA, present only if minimization was requested
+    pub minimal_dfa: Option<DFA>,
+}
+
+impl CompiledStages {
+    /// Take the last DFA built, the minimal DFA if there is one and the unminimized DFA otherwise
+    pub fn into_dfa(self) -> DFA {
+        self.minimal_dfa.unwrap_or(self.dfa)
+    }
+}
+
+/// Compile a single regular expression into a minimal DFA by parsing it, applying Thompson
+/// construction, subset construction and Hopcroft's algorithm. Nothing is saved to disk. The accept
+/// states of the returned DFA have an empty syntactic category.
+pub fn compile(pattern: &str) -> Result<DFA> {
+    compile_with_universe(pattern, &default_universe())
+}
+
+/// Compile a single regular expression into a minimal DFA like compile, resolving negated
+/// character sets and '.' against the provided universe of characters.
+pub fn compile_with_universe(pattern: &str, universe: &HashSet<char>) -> Result<DFA> {
+    let stages = compile_stages_with_universe(pattern, universe, true)?;
+    Ok(stages.into_dfa())
+}
+
+/// Compile a single regular expression like compile but keep the DFA built by subset construction
+/// alongside the minimal DFA, for example to report how much minimization saved. The minimal DFA
+/// is only built if minimize is true.
+pub fn compile_stages(pattern: &str, minimize: bool) -> Result<CompiledStages> {
+    compile_stages_with_universe(pattern, &default_universe(), minimize)
+}
+
+// Compile the regular expression against the universe, minimizing the DFA if requested
+fn compile_stages_with_universe(
+    pattern: &str,
+    universe: &HashSet<char>,
+    minimize: bool,
+) -> Result<CompiledStages> {
+    let regex_list = vec![(pattern.to_string(), String::new())];
+
+    let syntax_tree_list = parse_microsyntax_list(regex_list)?;
+
+    // Keyword lists skip Thompson and subset construction entirely
+    let dfa = match construct_keyword_dfa(&syntax_tree_list[0].1, "") {
+        Some(mut trie) => {
+            trie.regex = pattern.to_string();
+            trie
+        }
+        None => {
+            let nfa = construct_nfa_with_universe(syntax_tree_list, universe, false)?;
+            construct_dfa(&nfa, false)?
+        }
+    };
+
+    let minimal_dfa = if minimize {
+        Some(construct_minimal_dfa(&dfa, false))
+    } else {
+        None
+    };
+
+    Ok(CompiledStages { dfa, minimal_dfa })
+}
+
+// Get the string matched by a term made only of unquantified characters
+fn literal_term(term: &Term) -> Option<String> {
+    let (factor, prefix) = match term {
+        Term::SimpleTerm(factor) => (factor, String::new()),
+        Term::ConcatTerm(factor, term) => (factor, literal_term(term)?),
+    };
+
+    let ch = match factor {
+        Factor::SimpleFactor(Base::Character(ch), None) => *ch,
+        Factor::SimpleFactor(Base::EscapeCharacter(ch), None) => match ch {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            ch => *ch,
+        },
+        _ => return None,
+    };
+
+    Some(prefix + &ch.to_string())
+}
+
+// Get the strings of a regex which is a flat alternation of literal strings like if|else|while
+fn literal_alternatives(syntax_tree: &RegEx) -> Option<Vec<String>> {
+    match syntax_tree {
+        RegEx::SimpleRegex(term) => Some(vec![literal_term(term)?]),
+        RegEx::AlterRegex(term, regex) => {
+            let mut literals = vec![literal_term(term)?];
+            literals.extend(literal_alternatives(regex)?);
+            Some(literals)
+        }
+    }
+}
+
+/// Build a DFA for a keyword list like `if|else|while` directly as a prefix trie, without going
+/// through Thompson construction and subset construction. Every keyword ends in an accept state of
+/// the provided category. Returns None if the syntax tree is not a flat alternation of literal
+/// strings. The trie is deterministic but not minimal, as common suffixes are not shared.
+pub fn construct_keyword_dfa(syntax_tree: &RegEx, category: &str) -> Option<DFA> {
+    let keywords = literal_alternatives(syntax_tree)?;
+
+    let mut result = build_trie(&keywords, category);
+    result.regex = keywords.join("|");
+
+    if !category.is_empty() {
+        result
+            .category_regexes
+            .insert(category.to_string(), result.regex.clone());
+        result.category_order.push(category.to_string());
+    }
+
+    Some(result)
+}
+
+// Build a prefix trie of the words, with one state per distinct prefix and the word ends accepting
+// in the given category
+fn build_trie<S: AsRef<str>>(words: &[S], category: &str) -> DFA {
+    let mut result = DFA::new();
+    result.start_state = result.add_state();
+
+    for keyword in words.iter().map(|word| word.as_ref()) {
+        let mut state = result.start_state;
+
+        for ch in keyword.chars() {
+            result.alphabet.insert(ch);
+            state = match result.transition(state, ch) {
+                Some(next_state) => next_state,
+                None => {
+                    let next_state = result.add_state();
+                    result.states[state]
+                        .transitions
+                        .insert(Symbol::Char(ch), next_state);
+                    next_state
+                }
+            };
+        }
+
+        result.accept_states.set(state, true);
+        result.states[state].set_category(category.to_string());
+    }
+
+    result
+}
+
+/// The policy deciding which syntactic category a DFA state gets when the lexeme it accepts is
+/// matched by several microsyntaxes
+#[derive(Debug, Clone, Default)]
+pub enum TieBreak {
+    /// The microsyntax declared first wins
+    #[default]
+    DeclarationOrder,
+    /// The microsyntax with the longest regex wins, as a longer regex is usually more specific.
+    /// Regexes of the same length are ordered by declaration.
+    LongestPattern,
+    /// The category with the highest priority in the map wins. Categories missing from the map
+    /// have a priority of 0, and equal priorities are ordered by declaration.
+    ExplicitPriority(HashMap<String, usize>),
+}
+
+// Collect the categories of all the NFA states in the set which close a microsyntax
+fn accept_categories(nfa: &NFA, nfa_states: &BitVec<u8>) -> BTreeSet<String> {
+    nfa_states
+        .iter_ones()
+        .filter_map(|state| nfa.get_state(state).ok())
+        .map(|state| state.get_category())
+        .filter(|category| !category.is_empty())
+        .cloned()
+        .collect()
+}
+
+// Pick the category of the DFA state made of the provided NFA states according to the policy. NFA
+// states are numbered in declaration order, so the first of equally ranked states wins.
+fn resolve_category<'a>(
+    nfa: &'a NFA,
+    nfa_states: &BitVec<u8>,
+    tie_break: &TieBreak,
+) -> Option<&'a String> {
+    let mut candidates = nfa_states
+        .iter_ones()
+        .map(|state| nfa.get_state(state).unwrap())
+        .filter(|state| !state.get_category().is_empty());
+
+    let winner = match tie_break {
+        TieBreak::DeclarationOrder => candidates.next(),
+        TieBreak::LongestPattern => {
+            candidates.min_by_key(|state| std::cmp::Reverse(state.get_pattern().len()))
+        }
+        TieBreak::ExplicitPriority(priorities) => candidates.min_by_key(|state| {
+            std::cmp::Reverse(priorities.get(state.get_category()).copied().unwrap_or(0))
+        }),
+    };
+
+    winner.map(|state| state.get_category())
+}
+
+/// Keeps the syntax trees of a list of microsyntaxes so that the minimal DFA can be rebuilt after
+/// an entry changes without parsing the unchanged entries again. This is useful for editors which
+/// rebuild the DFA on every change of the microsyntax list.
+pub struct MicrosyntaxCache {
+    entries: Vec<(String, RegEx, String)>,
+    parse_count: usize, // Number of regular expressions parsed so far
+}
+
+impl MicrosyntaxCache {
+    /// Parse every microsyntax of the list and keep the syntax trees
+    pub fn new(regex_list: Vec<(String, String)>) -> Result<Self> {
+        let mut cache = MicrosyntaxCache {
+            entries: Vec::new(),
+            parse_count: 0,
+        };
+
+        for (regex, category) in regex_list {
+            let syntax_tree = cache.parse(&regex)?;
+            cache.entries.push((regex, syntax_tree, category));
+        }
+
+        Ok(cache)
+    }
+
+    fn parse(&mut self, regex: &str) -> Result<RegEx> {
+        self.parse_count += 1;
+        build_syntax_tree(regex)
+    }
+
+    /// Replace the microsyntax at the given index. The regex is only parsed again if it differs
+    /// from the cached one, so changing just the category costs no parsing at all. Returns an
+    /// error if the index is out of range.
+    pub fn update(&mut self, index: usize, regex: String, category: String) -> Result<()> {
+        if index >= self.entries.len() {
+            let err = DFAError::InvalidMicrosyntaxIndex(index, self.entries.len());
+            return Err(err.into());
+        }
+
+        if self.entries[index].0 != regex {
+            let syntax_tree = self.parse(&regex)?;
+            self.entries[index].0 = regex;
+            self.entries[index].1 = syntax_tree;
+        }
+        self.entries[index].2 = category;
+        Ok(())
+    }
+
+    /// Get the number of regular expressions parsed since the cache was created
+    pub fn get_parse_count(&self) -> usize {
+        self.parse_count
+    }
+
+    /// Build the minimal DFA for the cached microsyntaxes, running Thompson construction, subset
+    /// construction and Hopcroft's algorithm on the cached syntax trees.
+    pub fn build(&self) -> Result<DFA> {
+        let syntax_tree_list: VecDeque<(String, RegEx, String, RuleFlags)> = self
+            .entries
+            .iter()
+            .map(|(regex, syntax_tree, category)| {
+                let flags = RuleFlags::default();
+                (regex.clone(), syntax_tree.clone(), category.clone(), flags)
+            })
+            .collect();
+
+        let nfa = construct_nfa(syntax_tree_list, false)?;
+        let dfa = construct_dfa(&nfa, false)?;
+
+        Ok(construct_minimal_dfa(&dfa, false))
+    }
+}
+
+/// Collects microsyntaxes one at a time and builds the minimal DFA for all of them at once, as an
+/// alternative to passing the whole list to construct_nfa. Every rule has a priority, and when a
+/// lexeme is matched by several rules the one with the highest priority wins. Rules with the same
+/// priority are ordered by the order they were added in.
+#[derive(Debug, Default)]
+pub struct ScannerBuilder {
+    rules: Vec<(String, String, i32)>,
+}
+
+impl ScannerBuilder {
+    /// Create a builder without any rules
+    pub fn new() -> Self {
+        ScannerBuilder::default()
+    }
+
+    /// Add a rule matching the pattern as a token of the category. The pattern is parsed when the
+    /// DFA is built.
+    pub fn add_rule(&mut self, pattern: &str, category: &str, priority: i32) {
+        self.rules
+            .push((pattern.to_string(), category.to_string(), priority));
+    }
+
+    /// Add a rule matching integer literals in the provided radixes as tokens of the category,
+    /// optionally with underscores separating the digits. See number_literal_regex.
+    pub fn add_number_rule(
+        &mut self,
+        category: &str,
+        radixes: &[Radix],
+        underscores: bool,
+        priority: i32,
+    ) {
+        let pattern = number_literal_regex(radixes, underscores);
+        self.add_rule(&pattern, category, priority);
+    }
+
+    /// Build the minimal DFA for the rules added so far. The NFAs of all the rules are joined by
+    /// alternation and determinized once. Returns an error if a pattern is invalid or no rule was
+    /// added.
+    pub fn build(self) -> Result<DFA> {
+        let mut rules = self.rules;
+
+        // The categories of DFA states are picked in declaration order, so the rules are ordered
+        // by priority. The sort is stable, which keeps rules with equal priorities in order.
+        rules.sort_by_key(|(_, _, priority)| std::cmp::Reverse(*priority));
+
+        let regex_list = rules
+            .into_iter()
+            .map(|(pattern, category, _)| (pattern, category))
+            .collect();
+
+        let syntax_tree_list = parse_microsyntax_list(regex_list)?;
+        let nfa = construct_nfa(syntax_tree_list, false)?;
+        let dfa = construct_dfa(&nfa, false)?;
+
+        Ok(construct_minimal_dfa(&dfa, false))
+    }
+}
+
+///  Apply the subset construction algorithm on an NFA to build a DFA. If save_dfa is set to true,
+///  the constructed DFA is saved as a jpg. Returns an error if the NFA refers to a state which it
+///  does not have.
+pub fn construct_dfa(nfa: &NFA, save_dfa: bool) -> Result<DFA> {
+    construct_dfa_with_tie_break(nfa, &TieBreak::default(), save_dfa)
+}
+
+/// Apply the subset construction algorithm like construct_dfa, using the provided policy to pick
+/// the category of a DFA state in which several microsyntaxes accept.
+pub fn construct_dfa_with_tie_break(
+    nfa: &NFA,
+    tie_break: &TieBreak,
+    save_dfa: bool,
+) -> Result<DFA> {
+    let nfa_states = initial_nfa_states(nfa)?;
+
+    let result = subset_construction(
+        nfa,
+        nfa_states,
+        tie_break,
+        &mut ConstructionMetrics::default(),
+        None,
+        None,
+    )?;
+
+    if save_dfa {
+        let filename = format!("constructed_dfa");
+        result.show_fa(&filename);
+    }
+
+    return Ok(result);
+}
+
+// The set of NFA states holding only the start state, which the subset construction starts from
+fn initial_nfa_states(nfa: &NFA) -> Result<BitVec<u8>, DFAError> {
+    let n0: usize = nfa.get_start_state(); // Get n0
+
+    if n0 >= nfa.get_num_states() {
+        return Err(DFAError::MissingNFAState(n0));
+    }
+
+    let mut nfa_states = BitVec::repeat(false, nfa.get_num_states()); // Get the initial nfa states
+    nfa_states.set(n0, true); // Add the start state to nfa states set
+    Ok(nfa_states)
+}
+
+/// Apply the subset construction algorithm like construct_dfa and also return the counters
+/// recorded while constructing. Only the subset construction fields of the metrics are filled in.
+#[cfg(feature = "metrics")]
+pub fn construct_dfa_with_metrics(nfa: &NFA) -> Result<(DFA, ConstructionMetrics)> {
+    let nfa_states = initial_nfa_states(nfa)?;
+
+    let mut metrics = ConstructionMetrics::default();
+    let result = subset_construction(
+        nfa,
+        nfa_states,
+        &TieBreak::default(),
+        &mut metrics,
+        None,
+        None,
+    )?;
+    Ok((result, metrics))
+}
+
+/// One NFA state set taken off the worklist of the subset construction, as recorded by
+/// construct_dfa_with_trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubsetStep {
+    /// The sorted ids of the NFA states in the set
+    pub nfa_states: Vec<usize>,
+    /// The DFA state the set became
+    pub dfa_state: usize,
+    /// The transitions added from the DFA state, sorted by character
+    pub transitions: Vec<(char, usize)>,
+}
+
+/// Apply the subset construction algorithm like construct_dfa and also return a trace of the
+/// construction, with one step for every NFA state set in the order the worklist explored them.
+pub fn construct_dfa_with_trace(nfa: &NFA) -> Result<(DFA, Vec<SubsetStep>)> {
+    let nfa_states = initial_nfa_states(nfa)?;
+
+    let mut trace = Vec::new();
+    let result = subset_construction(
+        nfa,
+        nfa_states,
+        &TieBreak::default(),
+        &mut ConstructionMetrics::default(),
+        Some(&mut trace),
+        None,
+    )?;
+    Ok((result, trace))
+}
+
+/// Apply the subset construction algorithm like construct_dfa, giving up with
+/// DFAError::StateLimitExceeded once the DFA would have more than max_states states. Patterns like
+/// (a|b)*a(a|b)(a|b)(a|b) need a number of states exponential in their length, so this protects
+/// against exhausting memory when compiling patterns from an untrusted source.
+pub fn construct_dfa_with_limit(nfa: &NFA, max_states: usize) -> Result<DFA> {
+    let nfa_states = initial_nfa_states(nfa)?;
+
+    let result = subset_construction(
+        nfa,
+        nfa_states,
+        &TieBreak::default(),
+        &mut ConstructionMetrics::default(),
+        None,
+        Some(max_states),
+    )?;
+    Ok(result)
+}
+
+/// A DFA which is built from an NFA while matching, running the subset construction only for the
+/// state sets the input actually reaches. Patterns like (a|b)*a(a|b)(a|b)(a|b) need a number of DFA
+/// states exponential in their length, but any one input only visits a few of them.
+///
+/// The DFA states discovered so far are cached along with their transitions. Once the cache holds
+/// max_cache_size states, the least recently used state is evicted to make room for a new one and
+/// is rebuilt if the input reaches it again.
+pub struct LazyDFA<'a> {
+    nfa: &'a NFA,
+    start_states: BitVec<u8>, // Epsilon closure of the NFA start state
+    cache: HashMap<BitVec<u8>, usize>, // Mapping of NFA state set and its slot in the cache
+    slots: Vec<LazyState>,
+    max_cache_size: usize,
+    clock: usize, // Incremented on every visit to order the states by their last use
+    states_built: usize,
+}
+
+// A cached DFA state. A transition to None leads to the implicit error state.
+struct LazyState {
+    nfa_states: BitVec<u8>,
+    is_accept: bool,
+    transitions: HashMap<char, Option<usize>>,
+    last_used: usize,
+}
+
+impl<'a> LazyDFA<'a> {
+    /// Create a lazy DFA for the NFA which caches at most max_cache_size DFA states. The cache
+    /// always has room for at least two states, the current one and the one it moves to. Returns
+    /// an error if the NFA refers to a state which it does not have or has anchors or word
+    /// boundaries.
+    pub fn new(nfa: &'a NFA, max_cache_size: usize) -> Result<Self, DFAError> {
+        if ASSERTION_MARKERS
+            .iter()
+            .any(|marker| nfa.get_alphabet().contains(marker))
+        {
+            return Err(DFAError::UnsupportedAssertion);
+        }
+
+        let start_states = get_epsilon_closure(nfa, initial_nfa_states(nfa)?)?;
+
+        Ok(LazyDFA {
+            nfa,
+            start_states,
+            cache: HashMap::new(),
+            slots: Vec::new(),
+            max_cache_size: max_cache_size.max(2),
+            clock: 0,
+            states_built: 0,
+        })
+    }
+
+    /// Returns true if the NFA accepts the entire input string, building the DFA states on the way
+    /// which are not cached yet
+    pub fn accepts(&mut self, input: &str) -> Result<bool, DFAError> {
+        let mut state = self.state_for(self.start_states.clone(), None);
+
+        for ch in input.chars() {
+            state = match self.next_state(state, ch)? {
+                Some(next) => next,
+                None => return Ok(false),
+            };
+        }
+
+        Ok(self.slots[state].is_accept)
+    }
+
+    /// Get the number of DFA states currently held in the cache
+    pub fn num_cached_states(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Get the number of DFA states built so far, counting a state again every time it is rebuilt
+    /// after being evicted
+    pub fn num_states_built(&self) -> usize {
+        self.states_built
+    }
+
+    // Follow the transition of the cached state on the character, computing it if it is not
+    // cached. Returns None if the transition leads to the error state.
+    fn next_state(&mut self, state: usize, ch: char) -> Result<Option<usize>, DFAError> {
+        if raw_byte(ch).is_some() {
+            return Ok(None);
+        }
+
+        if let Some(next) = self.slots[state].transitions.get(&ch).copied() {
+            if let Some(next) = next {
+                self.touch(next);
+            }
+            return Ok(next);
+        }
+
+        let end_states = delta(self.nfa, &self.slots[state].nfa_states, ch)?;
+        let next = if end_states.not_any() {
+            None
+        } else {
+            let closure = get_epsilon_closure(self.nfa, end_states)?;
+            Some(self.state_for(closure, Some(state)))
+        };
+
+        self.slots[state].transitions.insert(ch, next);
+        Ok(next)
+    }
+
+    // Get the slot of the DFA state for the set of NFA states, building it if it is not cached.
+    // The current state is never evicted, as its transition to the new state is added next.
+    fn state_for(&mut self, nfa_states: BitVec<u8>, current: Option<usize>) -> usize {
+        if let Some(slot) = self.cache.get(&nfa_states).copied() {
+            self.touch(slot);
+            return slot;
+        }
+
+        let is_accept = (nfa_states.clone() & self.nfa.get_acceptor_states()).any();
+        let state = LazyState {
+            nfa_states: nfa_states.clone(),
+            is_accept,
+            transitions: HashMap::new(),
+            last_used: 0,
+        };
+
+        let slot = if self.slots.len() < self.max_cache_size {
+            self.slots.push(state);
+            self.slots.len() - 1
+        } else {
+            let slot = self.least_recently_used(current);
+            self.evict(slot);
+            self.slots[slot] = state;
+            slot
+        };
+
+        self.cache.insert(nfa_states, slot);
+        self.states_built += 1;
+        self.touch(slot);
+        slot
+    }
+
+    fn touch(&mut self, slot: usize) {
+        self.clock += 1;
+        self.slots[slot].last_used = self.clock;
+    }
+
+    fn least_recently_used(&self, current: Option<usize>) -> usize {
+        (0..self.slots.len())
+            .filter(|slot| Some(*slot) != current)
+            .min_by_key(|slot| self.slots[*slot].last_used)
+            .expect("The cache holds at least two states")
+    }
+
+    // Drop the state in the slot from the cache, along with every transition leading to it
+    fn evict(&mut self, slot: usize) {
+        self.cache.remove(&self.slots[slot].nfa_states);
+
+        for state in self.slots.iter_mut() {
+            state.transitions.retain(|_, target| *target != Some(slot));
+        }
+    }
+}
+
+/// A DFA which reads bytes instead of characters, built from a DFA to match binary input which is
+/// not valid UTF-8. ASCII characters match their byte and other characters the bytes of their UTF-8
+/// encoding, while the raw bytes of \xHH escapes above \x7f match nothing but that byte. So a byte
+/// like 0xE9 is never taken for the character é, which is the two bytes 0xC3 0xA9.
+#[derive(Debug, Clone)]
+pub struct ByteDFA {
+    start_state: usize,
+    transitions: Vec<[Option<usize>; 256]>, // A transition to None leads to the error state
+    // The category of every accepting state, resolved by priority, and None for the other states.
+    // The category is empty if the DFA was built without one.
+    categories: Vec<Option<String>>,
+}
+
+// A state of the byte automaton before determinization. It is either a state of the DFA, or part
+// way through the UTF-8 encoding of a character, with the bytes still to read and the DFA state
+// they lead to.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum ByteItem {
+    State(usize),
+    Partial(Vec<u8>, usize),
+}
+
+impl ByteDFA {
+    /// Build the byte DFA for the DFA. Returns an error if the DFA has anchors or word boundaries.
+    pub fn new(dfa: &DFA) -> Result<Self, DFAError> {
+        if dfa.has_assertions() {
+            return Err(DFAError::UnsupportedAssertion);
+        }
+
+        let mut byte_dfa = ByteDFA {
+            start_state: 0,
+            transitions: Vec::new(),
+            categories: Vec::new(),
+        };
+
+        if dfa.states.is_empty() {
+            return Ok(byte_dfa);
+        }
+
+        // Subset construction over the items, as the encodings of several characters and the raw
+        // bytes can start with the same byte
+        let start = BTreeSet::from([ByteItem::State(dfa.start_state)]);
+        let mut ids: HashMap<BTreeSet<ByteItem>, usize> = HashMap::from([(start.clone(), 0)]);
+        let mut worklist = VecDeque::from([start]);
+
+        while let Some(items) = worklist.pop_front() {
+            let mut targets: Vec<BTreeSet<ByteItem>> = vec![BTreeSet::new(); 256];
+            for item in items.iter() {
+                for (byte, target) in byte_moves(dfa, item) {
+                    targets[usize::from(byte)].insert(target);
+                }
+            }
+
+            let mut row = [None; 256];
+            for (byte, target) in targets.into_iter().enumerate() {
+                if target.is_empty() {
+                    continue;
+                }
+                let next_id = ids.len();
+                let id = *ids.entry(target.clone()).or_insert_with(|| {
+                    worklist.push_back(target);
+                    next_id
+                });
+                row[byte] = Some(id);
+            }
+            byte_dfa.transitions.push(row);
+
+            let accepting: Vec<usize> = items
+                .iter()
+                .filter_map(|item| match item {
+                    ByteItem::State(state) if dfa.accept_states[*state] => Some(*state),
+                    _ => None,
+                })
+                .collect();
+            let category = if accepting.is_empty() {
+                None
+            } else {
+                let categories = dfa.accepted_categories(&accepting);
+                Some(categories.into_iter().next().unwrap_or_default())
+            };
+            byte_dfa.categories.push(category);
+        }
+
+        Ok(byte_dfa)
+    }
+
+    /// Get the number of states of the byte DFA
+    pub fn get_num_states(&self) -> usize {
+        self.transitions.len()
+    }
+
+    /// Returns true if the byte DFA accepts the entire input
+    pub fn accepts(&self, input: &[u8]) -> bool {
+        if self.transitions.is_empty() {
+            return false;
+        }
+
+        let mut state = self.start_state;
+
+        for byte in input {
+            match self.transitions[state][usize::from(*byte)] {
+                Some(target) => state = target,
+                None => return false,
+            }
+        }
+
+        self.categories[state].is_some()
+    }
+
+    /// Returns the byte range of the longest match starting at the offset start of the input along
+    /// with its category, or None if there is none
+    pub fn longest_match(&self, input: &[u8], start: usize) -> Option<(Range<usize>, &str)> {
+        if self.transitions.is_empty() {
+            return None;
+        }
+
+        let mut state = self.start_state;
+        let mut last_accept = self.categories[state]
+            .as_deref()
+            .map(|category| (start, category));
+
+        for (offset, byte) in input.iter().enumerate().skip(start) {
+            state = match self.transitions[state][usize::from(*byte)] {
+                Some(target) => target,
+                None => break,
+            };
+            if let Some(category) = self.categories[state].as_deref() {
+                last_accept = Some((offset + 1, category));
+            }
+        }
+
+        last_accept.map(|(end, category)| (start..end, category))
+    }
+
+    /// Split the input into the longest matches one after the other, returning the byte range and
+    /// the category of every token. Returns None if no token of at least one byte starts at some
+    /// point of the input.
+    pub fn tokenize(&self, input: &[u8]) -> Option<Vec<(Range<usize>, &str)>> {
+        let mut tokens = Vec::new();
+        let mut start = 0;
+
+        while start < input.len() {
+            let (span, category) = self.longest_match(input, start)?;
+            if span.is_empty() {
+                return None;
+            }
+            start = span.end;
+            tokens.push((span, category));
+        }
+
+        Some(tokens)
+    }
+}
+
+// The moves of the item, each along with the byte it reads
+fn byte_moves(dfa: &DFA, item: &ByteItem) -> Vec<(u8, ByteItem)> {
+    let state = match item {
+        ByteItem::State(state) => *state,
+        ByteItem::Partial(bytes, target) => return vec![(bytes[0], after_byte(bytes, *target))],
+    };
+
+    dfa.states[state]
+        .transitions
+        .iter()
+        .filter_map(|(symbol, target)| {
+            let Symbol::Char(ch) = symbol else {
+                return None;
+            };
+            if let Some(byte) = raw_byte(*ch) {
+                return Some((byte, ByteItem::State(*target)));
+            }
+            let mut buffer = [0; 4];
+            let bytes = ch.encode_utf8(&mut buffer).as_bytes();
+            Some((bytes[0], after_byte(bytes, *target)))
+        })
+        .collect()
+}
+
+// The item reached after reading the first of the bytes which lead to the target state
+fn after_byte(bytes: &[u8], target: usize) -> ByteItem {
+    match bytes.len() {
+        1 => ByteItem::State(target),
+        _ => ByteItem::Partial(bytes[1..].to_vec(), target),
+    }
+}
+
+// Run the subset construction starting from the epsilon closure of the provided set of NFA states.
+// Every state set taken off the worklist is recorded in the trace, if one is provided, and the
+// construction stops with an error once it needs more states than the limit, if one is provided.
+fn subset_construction(
+    nfa: &NFA,
+    nfa_states: BitVec<u8>,
+    tie_break: &TieBreak,
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    metrics: &mut ConstructionMetrics,
+    mut trace: Option<&mut Vec<SubsetStep>>,
+    max_states: Option<usize>,
+) -> Result<DFA, DFAError> {
+    let mut result = DFA::new(); // Create new DFA
+    result.alphabet = nfa.get_alphabet().clone(); // DFA has same alphabet as NFA
+
+    let nfa_accepts = nfa.get_acceptor_states();
+
+    let di = result.add_state(); // Add an iniital state
+
+    result.start_state = di;
+    // Mapping from nfa state set to DFA state. Every set is as long as the NFA has states, so equal
+    // sets reached along different paths hash alike and share one DFA state.
+    let mut q_list = HashMap::new();
+    let mut work_list = VecDeque::new();
+
+    let q0 = get_epsilon_closure(&nfa, nfa_states)?; // Get its epsilon closure
+    q_list.insert(q0.clone(), di); // Add it to the mapping
+    #[cfg(feature = "metrics")]
+    {
+        metrics.peak_state_sets = 1;
+    }
+    work_list.push_back(q0.clone()); // Add the first nfa states set to the work list
+
+    let has_common = (q0.clone() & nfa_accepts).any();
+
+    if has_common {
+        result.accept_states.set(di, true);
+        result.states[di].categories = accept_categories(nfa, &q0);
+
+        if let Some(category) = resolve_category(nfa, &q0, tie_break) {
+            result.set_accept_category(category);
+        }
+    }
+
+    // Explore the characters in order, otherwise the ids of the DFA states would depend on the
+    // iteration order of the alphabet's hash set and change from one run to the next
+    let mut dfa_alphabet: Vec<char> = result.alphabet.iter().cloned().collect();
+    dfa_alphabet.sort();
+
+    while let Some(q) = work_list.pop_front() {
+        #[cfg(feature = "metrics")]
+        {
+            metrics.worklist_iterations += 1;
+        }
+        // Every set on the work list was mapped to a DFA state before being added
+        let dq = q_list[&q];
+        for c in dfa_alphabet.iter() {
+            let end_states = delta(&nfa, &q, *c)?;
+            if end_states.not_any() {
+                continue;
+            }
+            let t = get_epsilon_closure(&nfa, end_states)?;
+
+            let di = match q_list.get(&t) {
+                Some(di) => *di,
+                None => {
+                    if let Some(max_states) = max_states {
+                        if result.states.len() >= max_states {
+                            return Err(DFAError::StateLimitExceeded(max_states));
+                        }
+                    }
+                    // check if di is as an acceptor state
+                    let di = result.add_state();
+                    q_list.insert(t.clone(), di);
+                    #[cfg(feature = "metrics")]
+                    {
+                        metrics.peak_state_sets = metrics.peak_state_sets.max(q_list.len());
+                    }
+                    work_list.push_back(t.clone());
+                    let has_common = (t.clone() & nfa_accepts).any();
+                    if has_common {
+                        result.accept_states.set(di, true);
+                        result.states[di].categories = accept_categories(nfa, &t);
+                        if let Some(category) = resolve_category(nfa, &t, tie_break) {
+                            result.set_accept_category(category);
+                        }
+                    }
+                    di
+                }
+            };
+            // add a transition from diq to dit
+            result.states[dq].transitions.insert(Symbol::Char(*c), di);
+        }
+
+        if let Some(trace) = trace.as_mut() {
+            let mut transitions: Vec<(char, usize)> = result.states[dq]
+                .transitions
+                .iter()
+                .filter_map(|(symbol, target)| match symbol {
+                    Symbol::Char(ch) => Some((*ch, *target)),
+                    Symbol::Epsilon => None,
+                })
+                .collect();
+            transitions.sort();
+
+            trace.push(SubsetStep {
+                nfa_states: q.iter_ones().collect(),
+                dfa_state: dq,
+                transitions,
+            });
+        }
+    }
+    let regex = nfa.get_regex();
+    result.regex = regex.to_string();
+
+    result.nfa_state_sets = q_list
+        .into_iter()
+        .map(|(nfa_states, di)| (di, nfa_states))
+        .collect();
+
+    // Every state tagged with a category closes one microsyntax, and the states are numbered
+    // in declaration order
+    for id in 0..nfa.get_num_states() {
+        let state = nfa
+            .get_state(id)
+            .map_err(|_| DFAError::MissingNFAState(id))?;
+        let category = state.get_category();
+        if category.is_empty() {
+            continue;
+        }
+        if !result
+            .category_order
+            .iter()
+            .any(|ordered| ordered == category)
+        {
+            result.category_order.push(category.to_string());
+        }
+        if state.is_skipped() {
+            result.skip_categories.insert(category.to_string());
+        }
+        result
+            .category_regexes
+            .entry(category.to_string())
+            .and_modify(|regex| *regex = format!("{}|{}", regex, state.get_pattern()))
+            .or_insert(state.get_pattern().to_string());
+    }
+
+    debug_assert!(
+        result.validate().is_ok(),
+        "Subset construction built an invalid DFA"
+    );
+
+    return Ok(result);
+}
+
+#[cfg(test)]
+mod dfa_tests {
+    use super::*;
+    use crate::emit::emit_rust;
+    use crate::fa::byte_symbol;
+    use crate::nfa::NFAError;
+    use crate::scanner::{construct_scanner, ScannerError};
+    use std::collections::HashSet;
+
+    // Helper to build a minimal DFA from a list of (regex, category) pairs
+    fn build_dfa(rules: &[(&str, &str)]) -> DFA {
+        let regex_list = rules
+            .iter()
+            .map(|(regex, category)| (regex.to_string(), category.to_string()))
+            .collect();
+        let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+        let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+        let dfa = construct_dfa(&nfa, false).unwrap();
+        construct_minimal_dfa(&dfa, false)
+    }
+
+    // Helper to walk the DFA over the input and return the state reached, if any
+    fn walk(dfa: &DFA, input: &str) -> Option<usize> {
+        let mut state = dfa.get_start_state();
+        for ch in input.chars() {
+            state = *dfa
+                .get_state(state)
+                .get_transitions()
+                .get(&Symbol::Char(ch))?;
+        }
+        Some(state)
+    }
+
+    #[test]
+    fn test_dfa_state_creation() {
+        let state = DFAState::new(1);
+        assert_eq!(state.id, 1);
+        assert_eq!(state.get_transitions().len(), 0);
+        assert_eq!(state.get_category(), "");
+    }
+
+    #[test]
+    fn test_dfa_basic_construction() {
+        let mut dfa = DFA::new();
+        let start = dfa.add_state();
+        let end = dfa.add_state();
+
+        assert_eq!(dfa.get_num_states(), 2);
+        assert_eq!(dfa.get_start_state(), 0);
+        assert_eq!(dfa.get_acceptor_states().len(), 2);
+        assert!(!dfa.get_acceptor_states()[end]);
+        assert!(!dfa.get_acceptor_states()[start]);
+
+        // Mark end as accept state
+        dfa.accept_states.set(end, true);
+        assert!(dfa.get_acceptor_states()[end]);
+
+        // Add transition
+        dfa.states[start].transitions.insert(Symbol::Char('a'), end);
+        let transitions = dfa.get_state_transitions(start);
+        assert_eq!(transitions.len(), 1);
+        assert_eq!(*transitions[0].0, Symbol::Char('a'));
+        assert_eq!(*transitions[0].1, end);
+    }
+
+    #[test]
+    fn test_lookup_table() {
+        let mut lookup_table = LookupTable::new();
+
+        // Test inserting states into sets
+        lookup_table.insert_state_in_set(0, 1);
+        lookup_table.insert_state_in_set(1, 1);
+        lookup_table.insert_state_in_set(2, 2);
+
+        assert_eq!(lookup_table.get_set_of_state(&0), Some(&1));
+        assert_eq!(lookup_table.get_set_of_state(&1), Some(&1));
+        assert_eq!(lookup_table.get_set_of_state(&2), Some(&2));
+        assert_eq!(lookup_table.get_num_sets(), 2);
+
+        // Test moving a state to a different set
+        lookup_table.insert_state_in_set(0, 2);
+        assert_eq!(lookup_table.get_set_of_state(&0), Some(&2));
+
+        // Get sets
+        let sets: Vec<HashSet<usize>> = lookup_table.get_sets().cloned().collect();
+        assert_eq!(sets.len(), 2);
+
+        // Check if sets contain correct elements
+        let set1 = sets.iter().find(|set| set.contains(&1)).unwrap();
+        let set2 = sets
+            .iter()
+            .find(|set| set.contains(&0) && set.contains(&2))
+            .unwrap();
+
+        assert_eq!(set1.len(), 1);
+        assert_eq!(set2.len(), 2);
+    }
+
+    #[test]
+    fn test_construct_minimal_dfa() {
+        // Create a DFA with redundant states
+        let mut dfa = DFA::new();
+
+        // Add states
+        let s0 = dfa.add_state(); // start state
+        let s1 = dfa.add_state(); // accepts 'a'
+        let s2 = dfa.add_state(); // accepts 'b'
+        let s3 = dfa.add_state(); // duplicate of s1, accepts 'a'
+
+        // Set start state
+        dfa.start_state = s0;
+
+        // Add characters to alphabet
+        dfa.alphabet.insert('a');
+        dfa.alphabet.insert('b');
+
+        // Add transitions
+        dfa.states[s0].transitions.insert(Symbol::Char('a'), s1);
+        dfa.states[s0].transitions.insert(Symbol::Char('b'), s2);
+        dfa.states[s1].transitions.insert(Symbol::Char('a'), s1);
+        dfa.states[s1].transitions.insert(Symbol::Char('b'), s2);
+        dfa.states[s2].transitions.insert(Symbol::Char('a'), s3);
+        dfa.states[s2].transitions.insert(Symbol::Char('b'), s2);
+        dfa.states[s3].transitions.insert(Symbol::Char('a'), s3);
+        dfa.states[s3].transitions.insert(Symbol::Char('b'), s2);
+
+        // Set accept states
+        dfa.accept_states.set(s1, true);
+        dfa.accept_states.set(s3, true);
+
+        // Set categories
+        dfa.states[s1].category = "A".to_string();
+        dfa.states[s3].category = "A".to_string();
 
         // Minimize the DFA
         let minimal_dfa = construct_minimal_dfa(&dfa, false);
 
-        // The minimal DFA should have 2 states instead of 4
-        // s1 and s3 should be merged
-        assert_eq!(minimal_dfa.get_num_states(), 2);
+        // The minimal DFA should have 2 states instead of 4
+        // s1 and s3 should be merged
+        assert_eq!(minimal_dfa.get_num_states(), 2);
+
+        // Check accept states
+        let accept_count = minimal_dfa.get_acceptor_states().count_ones();
+        assert_eq!(accept_count, 1);
+
+        // Verify the category is preserved
+        let accept_states: Vec<usize> = minimal_dfa.get_acceptor_states().iter_ones().collect();
+        for state_id in accept_states {
+            assert_eq!(minimal_dfa.get_state(state_id).get_category(), "A");
+        }
+    }
+
+    #[test]
+    fn test_minimal_dfa_keeps_categories_apart() {
+        // The same DFA as above, except the two look-alike accept states belong to different
+        // categories, so they start in different blocks and are never merged
+        let mut dfa = DFA::new();
+        let s0 = dfa.add_state();
+        let s1 = dfa.add_state();
+        let s2 = dfa.add_state();
+        let s3 = dfa.add_state();
+        dfa.start_state = s0;
+        dfa.alphabet.insert('a');
+        dfa.alphabet.insert('b');
+
+        dfa.states[s0].transitions.insert(Symbol::Char('a'), s1);
+        dfa.states[s0].transitions.insert(Symbol::Char('b'), s2);
+        dfa.states[s1].transitions.insert(Symbol::Char('a'), s1);
+        dfa.states[s1].transitions.insert(Symbol::Char('b'), s2);
+        dfa.states[s2].transitions.insert(Symbol::Char('a'), s3);
+        dfa.states[s2].transitions.insert(Symbol::Char('b'), s2);
+        dfa.states[s3].transitions.insert(Symbol::Char('a'), s3);
+        dfa.states[s3].transitions.insert(Symbol::Char('b'), s2);
+
+        dfa.accept_states.set(s1, true);
+        dfa.accept_states.set(s3, true);
+        dfa.states[s1].category = "A".to_string();
+        dfa.states[s3].category = "B".to_string();
+
+        let minimal_dfa = construct_minimal_dfa(&dfa, false);
+        assert_eq!(minimal_dfa.get_num_states(), 4);
+        assert_eq!(minimal_dfa.get_acceptor_states().count_ones(), 2);
+        assert_eq!(
+            minimal_dfa.categories(),
+            BTreeSet::from(["A".into(), "B".into()])
+        );
+
+        // Both final states of a|b only differ by their category
+        let minimal_dfa = build_dfa(&[("a", "X"), ("b", "Y")]);
+        assert_eq!(minimal_dfa.get_num_states(), 3);
+        assert_eq!(
+            minimal_dfa.category_of(walk(&minimal_dfa, "a").unwrap()),
+            Some("X")
+        );
+        assert_eq!(
+            minimal_dfa.category_of(walk(&minimal_dfa, "b").unwrap()),
+            Some("Y")
+        );
+    }
+
+    #[test]
+    fn test_is_minimal() {
+        let regex_list = vec![("(a|b)*abb".to_string(), "ABB".to_string())];
+        let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+        let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+        let dfa = construct_dfa(&nfa, false).unwrap();
+
+        // The subset construction gives 5 states where 4 are enough
+        assert!(!dfa.is_minimal());
+        let minimal_dfa = construct_minimal_dfa(&dfa, false);
+        assert_eq!((dfa.get_num_states(), minimal_dfa.get_num_states()), (5, 4));
+        assert!(minimal_dfa.is_minimal());
+        assert!(DFA::from_strings(&["if", "else"]).is_minimal());
+        assert!(DFA::from_strings(&[]).is_minimal());
+
+        // Two accept states of the same category behaving the same can be merged
+        let mut dfa = DFA::new();
+        let s0 = dfa.add_state();
+        let s1 = dfa.add_state();
+        let s2 = dfa.add_state();
+        dfa.start_state = s0;
+        dfa.alphabet.insert('a');
+        dfa.alphabet.insert('b');
+        dfa.states[s0].transitions.insert(Symbol::Char('a'), s1);
+        dfa.states[s0].transitions.insert(Symbol::Char('b'), s2);
+        dfa.accept_states.set(s1, true);
+        dfa.accept_states.set(s2, true);
+        dfa.states[s1].category = "A".to_string();
+        dfa.states[s2].category = "A".to_string();
+        assert!(!dfa.is_minimal());
+
+        // With different categories they have to stay apart
+        dfa.states[s2].category = "B".to_string();
+        assert!(dfa.is_minimal());
+
+        // An unreachable state or a state reaching no accept state is never needed
+        let s3 = dfa.add_state();
+        assert!(!dfa.is_minimal());
+        dfa.states[s1].transitions.insert(Symbol::Char('a'), s3);
+        assert!(!dfa.is_minimal());
+        dfa.accept_states.set(s3, true);
+        dfa.states[s3].category = "A".to_string();
+        assert!(dfa.is_minimal());
+    }
+
+    #[test]
+    fn test_compare_transitions() {
+        // Create two DFA states with same transitions
+        let mut dfa = DFA::new();
+        let s0 = dfa.add_state();
+        let s1 = dfa.add_state();
+        let s2 = dfa.add_state();
+        let s3 = dfa.add_state();
+
+        dfa.alphabet.insert('a');
+        dfa.alphabet.insert('b');
+
+        // States with same transitions pattern
+        dfa.states[s0].transitions.insert(Symbol::Char('a'), s2);
+        dfa.states[s0].transitions.insert(Symbol::Char('b'), s3);
+        dfa.states[s1].transitions.insert(Symbol::Char('a'), s2);
+        dfa.states[s1].transitions.insert(Symbol::Char('b'), s3);
+
+        // Create lookup table
+        let mut lookup_table = LookupTable::new();
+        lookup_table.insert_state_in_set(s0, 0);
+        lookup_table.insert_state_in_set(s1, 0);
+        lookup_table.insert_state_in_set(s2, 1);
+        lookup_table.insert_state_in_set(s3, 2);
+
+        // States should have same transitions
+        let result = compare_transitions(
+            &dfa.states[s0],
+            &dfa.states[s1],
+            &dfa.alphabet,
+            &lookup_table,
+        );
+        assert!(result);
+
+        // Now modify one transition
+        dfa.states[s1].transitions.insert(Symbol::Char('b'), s2);
+
+        // States should have different transitions now
+        let result = compare_transitions(
+            &dfa.states[s0],
+            &dfa.states[s1],
+            &dfa.alphabet,
+            &lookup_table,
+        );
+        assert!(!result);
+    }
+
+    #[test]
+    fn test_reorder_minimal_dfa() {
+        // Create a DFA with unordered states
+        let mut dfa = DFA::new();
+
+        // Add states in non-consecutive order
+        let s0 = dfa.add_state();
+        let s1 = dfa.add_state();
+        let s2 = dfa.add_state();
+
+        // Set up transitions
+        dfa.states[s0].transitions.insert(Symbol::Char('a'), s2);
+        dfa.states[s2].transitions.insert(Symbol::Char('b'), s1);
+
+        // Set start and accept states
+        dfa.start_state = s0;
+        dfa.accept_states.set(s1, true);
+        dfa.states[s1].category = "TEST".to_string();
+
+        // Add to alphabet
+        dfa.alphabet.insert('a');
+        dfa.alphabet.insert('b');
+
+        // Reorder
+        let (reordered, _) = reorder_minimal_dfa(&dfa);
+
+        // Should still have 3 states
+        assert_eq!(reordered.get_num_states(), 3);
+
+        // Accept state should still be marked
+        let accept_states: Vec<usize> = reordered.get_acceptor_states().iter_ones().collect();
+        assert_eq!(accept_states.len(), 1);
+
+        // Category should be preserved
+        for accept in accept_states {
+            assert_eq!(reordered.get_state(accept).get_category(), "TEST");
+        }
+
+        // Check if transitions are preserved
+        let start_transitions = reordered.get_state_transitions(reordered.get_start_state());
+        assert_eq!(start_transitions.len(), 1);
+
+        // Follow path: start --a--> middle --b--> accept
+        let middle_state = match start_transitions[0].0 {
+            Symbol::Char('a') => *start_transitions[0].1,
+            _ => panic!("Expected 'a' transition"),
+        };
+
+        let middle_transitions = reordered.get_state_transitions(middle_state);
+        assert_eq!(middle_transitions.len(), 1);
+
+        let end_state = match middle_transitions[0].0 {
+            Symbol::Char('b') => *middle_transitions[0].1,
+            _ => panic!("Expected 'b' transition"),
+        };
+
+        assert!(reordered.get_acceptor_states()[end_state]);
+    }
+
+    #[test]
+    fn test_fa_trait_implementation_for_dfa() {
+        // Create a simple DFA
+        let mut dfa = DFA::new();
+        let s0 = dfa.add_state();
+        let s1 = dfa.add_state();
+
+        dfa.start_state = s0;
+        dfa.accept_states.set(s1, true);
+        dfa.alphabet.insert('a');
+
+        dfa.states[s0].transitions.insert(Symbol::Char('a'), s1);
+
+        // Test FA trait methods
+        assert_eq!(dfa.get_num_states(), 2);
+        assert_eq!(dfa.get_start_state(), s0);
+        assert!(dfa.get_alphabet().contains(&'a'));
+
+        let accept_states: Vec<usize> = dfa.get_acceptor_states().iter_ones().collect();
+        assert_eq!(accept_states, vec![s1]);
+
+        let transitions = dfa.get_state_transitions(s0);
+        assert_eq!(transitions.len(), 1);
+        assert_eq!(*transitions[0].0, Symbol::Char('a'));
+        assert_eq!(*transitions[0].1, s1);
+    }
+
+    #[test]
+    fn test_text_format_round_trip() {
+        let dfa = build_dfa(&[("if|[a-z]+", "WORD"), ("( |\\\\)+", "SPACE")]);
+
+        let text = dfa.to_text_format();
+        assert!(text.starts_with(&format!(
+            "states {}\nalphabet \\u{{20}} \\u{{5c}} a",
+            dfa.get_num_states()
+        )));
+        assert!(text.contains(&format!("start {}\n", dfa.get_start_state())));
+        assert!(text.contains(&format!("{} \\u{{20}} ", dfa.get_start_state())));
+
+        let imported = DFA::from_text_format(&text).unwrap();
+        assert!(imported.equivalent(&dfa));
+        assert_eq!(imported.get_num_states(), dfa.get_num_states());
+        assert_eq!(imported.to_text_format(), text);
+        assert_eq!(imported.match_span("if x"), dfa.match_span("if x"));
+
+        // Comments and blank lines are skipped
+        let imported = DFA::from_text_format(
+            "# ab*\nstates 2\nalphabet a b\n\nstart 0\naccept 1 AB\n0 a 1\n1 b 1\n",
+        )
+        .unwrap();
+        assert!(imported.equivalent(&build_dfa(&[("ab*", "AB")])));
+        assert_eq!(imported.get_state(1).get_category(), "AB");
+
+        let invalid = [
+            "states 2\nstart 0\n0 a\n",
+            "states 2\nstart 0\n0 ab 1\n",
+            "states 2\nstart 0\naccept 2\n",
+            "states 2\nstart 0\n0 a 5\n",
+            "states 2\n0 a 1\n",
+        ];
+        for text in invalid {
+            assert!(DFA::from_text_format(text).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_to_table_csv() {
+        let mut dfa = DFA::new();
+        let s0 = dfa.add_state();
+        let s1 = dfa.add_state();
+        let s2 = dfa.add_state();
+
+        dfa.start_state = s0;
+        dfa.alphabet.insert('b');
+        dfa.alphabet.insert('a');
+
+        dfa.states[s0].transitions.insert(Symbol::Char('a'), s1);
+        dfa.states[s1].transitions.insert(Symbol::Char('b'), s2);
+
+        dfa.accept_states.set(s2, true);
+        dfa.states[s2].category = "AB".to_string();
+
+        let csv = dfa.to_table_csv();
+        let sections: Vec<&str> = csv.split("\n\n").collect();
+        assert_eq!(sections.len(), 3);
+
+        // One header row plus one row per state, one column per character plus the state id
+        let rows: Vec<&str> = sections[0].lines().skip(1).collect();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0], "state,a,b");
+        for row in rows.iter() {
+            assert_eq!(row.split(',').count(), 3);
+        }
+        assert_eq!(rows[1], "0,1,-1");
+        assert_eq!(rows[2], "1,-1,2");
+        assert_eq!(rows[3], "2,-1,-1");
+
+        assert_eq!(sections[1], "# start\n0");
+        assert_eq!(sections[2], "# accept\nstate,category\n2,AB\n");
+    }
+
+    #[test]
+    fn test_remap_categories() {
+        let mut dfa = build_dfa(&[("if", "KW_IF"), ("else", "KW_ELSE"), ("[a-z]+", "IDENT")]);
+
+        let if_state = walk(&dfa, "if").unwrap();
+        let else_state = walk(&dfa, "else").unwrap();
+        let ident_state = walk(&dfa, "iff").unwrap();
+
+        assert_eq!(dfa.category_of(if_state), Some("KW_IF"));
+        assert_eq!(dfa.category_of(else_state), Some("KW_ELSE"));
+
+        dfa.remap_categories(|category| {
+            if category.starts_with("KW_") {
+                "KEYWORD".to_string()
+            } else {
+                category.to_string()
+            }
+        });
+
+        // Every accept state previously labelled with a keyword category is now a KEYWORD
+        for accept in dfa.get_acceptor_states().iter_ones() {
+            let category = dfa.category_of(accept).unwrap();
+            assert!(category == "KEYWORD" || category == "IDENT");
+        }
+
+        assert_eq!(dfa.category_of(if_state), Some("KEYWORD"));
+        assert_eq!(dfa.category_of(else_state), Some("KEYWORD"));
+        assert_eq!(dfa.category_of(ident_state), Some("IDENT"));
+
+        // Non accept states and invalid ids have no category
+        assert_eq!(dfa.category_of(dfa.get_start_state()), None);
+        assert_eq!(dfa.category_of(dfa.get_num_states()), None);
+    }
+
+    #[test]
+    fn test_accepts_and_equivalent() {
+        let dfa1 = build_dfa(&[("(a|b)*c", "C")]);
+        let dfa2 = build_dfa(&[("(b|a)*c|c", "C")]);
+        let dfa3 = build_dfa(&[("(a|b)+c", "C")]);
+
+        assert!(dfa1.accepts("c"));
+        assert!(dfa1.accepts("abbac"));
+        assert!(!dfa1.accepts("ab"));
+        assert!(!dfa1.accepts("abcd"));
+
+        assert!(dfa1.equivalent(&dfa2));
+        assert!(dfa2.equivalent(&dfa1));
+        assert!(!dfa1.equivalent(&dfa3));
+    }
+
+    #[test]
+    fn test_equivalent_disjoint_alphabets() {
+        let a_star = compile("a*").unwrap();
+        let b_star = compile("b*").unwrap();
+
+        // Both accept the empty string, but each rejects the other's characters
+        assert!(!a_star.equivalent(&b_star));
+        assert!(!b_star.equivalent(&a_star));
+        assert!(!compile("a").unwrap().equivalent(&compile("b").unwrap()));
+
+        // DFAs which only accept the empty string are equivalent whatever their alphabets are
+        let mut empty_string_x = DFA::new();
+        let start = empty_string_x.add_state();
+        empty_string_x.accept_states.set(start, true);
+        empty_string_x.alphabet = HashSet::from(['x']);
+
+        let mut empty_string_y = DFA::new();
+        let start = empty_string_y.add_state();
+        empty_string_y.accept_states.set(start, true);
+        empty_string_y.alphabet = HashSet::from(['y']);
+        let dead = empty_string_y.add_state();
+        empty_string_y.states[start]
+            .transitions
+            .insert(Symbol::Char('y'), dead);
+
+        assert!(empty_string_x.equivalent(&empty_string_y));
+        assert!(empty_string_y.equivalent(&empty_string_x));
+
+        // The union alphabet is used for containment as well
+        assert!(compile("a").unwrap().is_subset_of(&compile("a|b").unwrap()));
+        assert!(!compile("a").unwrap().is_subset_of(&b_star));
+        assert!(empty_string_x.is_subset_of(&a_star));
+    }
+
+    #[test]
+    fn test_is_subset_of() {
+        let a = compile("a").unwrap();
+        let a_or_b = compile("[ab]").unwrap();
+
+        assert!(a.is_subset_of(&a_or_b));
+        assert!(!a_or_b.is_subset_of(&a));
+        assert!(a.is_subset_of(&a));
+
+        // A keyword is subsumed by the identifier pattern, but not the other way around
+        let keyword = compile("while").unwrap();
+        let identifier = compile("[a-z]+").unwrap();
+        assert!(keyword.is_subset_of(&identifier));
+        assert!(!identifier.is_subset_of(&keyword));
+        assert!(!compile("while2").unwrap().is_subset_of(&identifier));
+
+        // The empty language is a subset of everything
+        assert!(DFA::new().is_subset_of(&a));
+        assert!(!a.is_subset_of(&DFA::new()));
+    }
+
+    #[test]
+    fn test_minimize_brzozowski() {
+        let patterns = [
+            "(a|b)*abb",
+            "a+b*",
+            "(ab|ac)*",
+            "if|in|int",
+            "[0-9]+(x[0-9]+)?",
+        ];
+
+        for pattern in patterns {
+            let regex_list = vec![(pattern.to_string(), "CATEGORY".to_string())];
+            let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+            let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+            let dfa = construct_dfa(&nfa, false).unwrap();
+
+            let hopcroft_dfa = construct_minimal_dfa(&dfa, false);
+            let brzozowski_dfa = dfa.minimize_brzozowski();
+
+            assert!(
+                brzozowski_dfa.equivalent(&hopcroft_dfa),
+                "Minimized DFAs differ for {}",
+                pattern
+            );
+            assert_eq!(
+                brzozowski_dfa.get_num_states(),
+                hopcroft_dfa.get_num_states(),
+                "Minimized DFA sizes differ for {}",
+                pattern
+            );
+
+            // A single category survives the double reversal
+            for accept in brzozowski_dfa.get_acceptor_states().iter_ones() {
+                assert_eq!(brzozowski_dfa.category_of(accept), Some("CATEGORY"));
+            }
+        }
+    }
+
+    #[test]
+    fn test_reverse() {
+        let dfa = build_dfa(&[("abc", "ABC")]);
+        let reversed = dfa.reverse();
+
+        // New start state plus one state per DFA state
+        assert_eq!(reversed.get_num_states(), dfa.get_num_states() + 1);
+
+        // The new start state only has epsilon transitions into the old accept states
+        let start_transitions = reversed.get_state_transitions(reversed.get_start_state());
+        let old_accepts: Vec<usize> = dfa.get_acceptor_states().iter_ones().collect();
+        assert_eq!(start_transitions.len(), old_accepts.len());
+        for (symbol, target) in start_transitions {
+            assert_eq!(*symbol, Symbol::Epsilon);
+            assert!(old_accepts.contains(target));
+        }
+
+        // The old start state is the only accept state
+        let accept_states: Vec<usize> = reversed.get_acceptor_states().iter_ones().collect();
+        assert_eq!(accept_states, vec![dfa.get_start_state()]);
+        assert_eq!(
+            reversed
+                .get_state(dfa.get_start_state())
+                .unwrap()
+                .get_category(),
+            "ABC"
+        );
+
+        let reversed_dfa = construct_dfa(&reversed, false).unwrap();
+        assert!(reversed_dfa.accepts("cba"));
+        assert!(!reversed_dfa.accepts("abc"));
+        assert!(!reversed_dfa.accepts("cb"));
+    }
+
+    #[test]
+    fn test_analyze_conflicts() {
+        let regex_list = vec![
+            ("if".to_string(), "KEYWORD".to_string()),
+            ("[a-z]+".to_string(), "IDENTIFIER".to_string()),
+            ("[0-9]+".to_string(), "NUMBER".to_string()),
+            ("[0-9][0-9]*".to_string(), "INTEGER".to_string()),
+        ];
+        let entries = parse_microsyntax_list(regex_list).unwrap();
+
+        let conflicts = analyze_conflicts(&entries);
+        assert_eq!(conflicts.len(), 2);
+
+        // The keyword is subsumed by the identifier but still wins due to its higher priority
+        assert_eq!(
+            conflicts[0],
+            Conflict {
+                kind: ConflictKind::Subsumed,
+                regex: "if".to_string(),
+                category: "KEYWORD".to_string(),
+                other_regex: "[a-z]+".to_string(),
+                other_category: "IDENTIFIER".to_string(),
+                shadowed: false,
+            }
+        );
+
+        // INTEGER accepts the same language as NUMBER and can never produce a token
+        assert_eq!(conflicts[1].kind, ConflictKind::Equivalent);
+        assert_eq!(conflicts[1].category, "INTEGER");
+        assert_eq!(conflicts[1].other_category, "NUMBER");
+        assert!(conflicts[1].shadowed);
+    }
+
+    #[test]
+    fn test_longest_common_literal_prefix() {
+        let regex_list = vec![
+            ("if".to_string(), "KEYWORD".to_string()),
+            ("int".to_string(), "KEYWORD".to_string()),
+            ("import".to_string(), "KEYWORD".to_string()),
+            ("[a-z]+".to_string(), "IDENTIFIER".to_string()),
+        ];
+        let entries = parse_microsyntax_list(regex_list).unwrap();
+        assert_eq!(
+            longest_common_literal_prefix(&entries),
+            Some("i".to_string())
+        );
+
+        let regex_list = vec![
+            ("import|impl".to_string(), "KEYWORD".to_string()),
+            ("[0-9]+".to_string(), "NUMBER".to_string()),
+        ];
+        let entries = parse_microsyntax_list(regex_list).unwrap();
+        assert_eq!(
+            longest_common_literal_prefix(&entries),
+            Some("imp".to_string())
+        );
+
+        let regex_list = vec![
+            ("if".to_string(), "KEYWORD".to_string()),
+            ("else".to_string(), "KEYWORD".to_string()),
+        ];
+        let entries = parse_microsyntax_list(regex_list).unwrap();
+        assert_eq!(longest_common_literal_prefix(&entries), Some(String::new()));
+
+        let regex_list = vec![("[a-z]+".to_string(), "IDENTIFIER".to_string())];
+        let entries = parse_microsyntax_list(regex_list).unwrap();
+        assert_eq!(longest_common_literal_prefix(&entries), None);
+    }
+
+    #[test]
+    fn test_quantified_char_sets() {
+        let dfa = build_dfa(&[("[a-z]+[0-9]*", "NAME")]);
+
+        assert!(dfa.accepts("abc123"));
+        assert!(dfa.accepts("abc"));
+        assert!(!dfa.accepts("123"));
+        assert!(!dfa.accepts("abc123abc"));
+
+        // The longest match takes the digits as well when they follow the letters
+        assert_eq!(dfa.match_span("abc123"), Some(0..6));
+        assert_eq!(dfa.match_span("abc"), Some(0..3));
+        assert_eq!(dfa.match_span("abc123abc"), Some(0..6));
+        assert_eq!(dfa.match_span("abc-123"), Some(0..3));
+        assert_eq!(dfa.match_span("123"), None);
+    }
+
+    #[test]
+    fn test_match_span() {
+        let dfa = build_dfa(&[("a+", "A")]);
+
+        assert_eq!(dfa.match_span("aaab"), Some(0..3));
+        assert_eq!(dfa.match_span("aaa"), Some(0..3));
+        assert_eq!(dfa.match_span("baaa"), None);
+        assert_eq!(dfa.match_span(""), None);
+
+        // Spans are byte offsets, even for multi-byte characters
+        let mut dfa = DFA::new();
+        let s0 = dfa.add_state();
+        let s1 = dfa.add_state();
+        dfa.alphabet.insert('é');
+        dfa.states[s0].transitions.insert(Symbol::Char('é'), s1);
+        dfa.states[s1].transitions.insert(Symbol::Char('é'), s1);
+        dfa.accept_states.set(s1, true);
+        assert_eq!(dfa.match_span("ééx"), Some(0..4));
+
+        // A DFA accepting the empty string matches an empty span
+        let dfa = build_dfa(&[("a*", "A")]);
+        assert_eq!(dfa.match_span("b"), Some(0..0));
+    }
+
+    #[test]
+    fn test_compile() {
+        let dfa = compile("(a|b)*c").unwrap();
+
+        for accepted in ["c", "ac", "bc", "abbac", "bbbbc"] {
+            assert!(
+                dfa.accepts(accepted),
+                "Expected {} to be accepted",
+                accepted
+            );
+        }
+        for rejected in ["", "a", "ab", "ca", "acc", "abd"] {
+            assert!(
+                !dfa.accepts(rejected),
+                "Expected {} to be rejected",
+                rejected
+            );
+        }
+
+        // (a|b)*c needs exactly two states, before and after the c
+        assert_eq!(dfa.get_num_states(), 2);
+
+        assert!(compile("(a|b").is_err());
+    }
+
+    #[test]
+    fn test_ascii_table() {
+        let rules = [
+            ("[a-z_][a-z0-9_]*", "IDENTIFIER"),
+            ("[0-9]+(\\.[0-9]+)?", "NUMBER"),
+            ("é+|日本", "UNICODE"),
+        ];
+        let mut dfa = build_dfa(&rules);
+        let plain = build_dfa(&rules);
+
+        assert!(!dfa.has_ascii_table());
+        dfa.build_ascii_table();
+        assert!(dfa.has_ascii_table());
+
+        for input in [
+            "abc_1", "3.14", "3.", "ééé", "日本", "日", "a é", "", "A", "~",
+        ] {
+            assert_eq!(dfa.accepts(input), plain.accepts(input), "{:?}", input);
+            assert_eq!(
+                dfa.match_span(input),
+                plain.match_span(input),
+                "{:?}",
+                input
+            );
+        }
+
+        for state in 0..dfa.get_num_states() {
+            for ch in (0..128u8).map(char::from).chain(['é', '日', '本']) {
+                assert_eq!(dfa.transition(state, ch), plain.transition(state, ch));
+            }
+        }
+        assert_eq!(dfa.transition(dfa.get_num_states(), 'a'), None);
+    }
+
+    #[test]
+    fn test_class_table() {
+        let mut dfa = compile("[a-z]+").unwrap();
+        assert_eq!(dfa.num_char_classes(), None);
+        dfa.build_class_table();
+
+        let class = dfa.char_class_of('a');
+        assert_ne!(class, 0);
+        assert!(('a'..='z').all(|ch| dfa.char_class_of(ch) == class));
+        assert_eq!(dfa.char_class_of('A'), 0);
+        assert_eq!(dfa.num_char_classes(), Some(2));
+
+        // Apart from the error column of class 0, only the letters have transitions
+        let rows = &dfa.class_table.as_ref().unwrap().rows;
+        assert!(rows.iter().all(|row| row[0].is_none()));
+        assert!(rows.iter().any(|row| row[class].is_some()));
+        assert!(dfa.accepts("hello"));
+        assert!(!dfa.accepts("Hello"));
+
+        let rules = [
+            ("[a-z_][a-z0-9_]*", "IDENTIFIER"),
+            ("[0-9]+(\\.[0-9]+)?", "NUMBER"),
+            ("é+|日本", "UNICODE"),
+        ];
+        let mut dfa = build_dfa(&rules);
+        let plain = build_dfa(&rules);
+        dfa.build_class_table();
+
+        assert_eq!(dfa.char_class_of('b'), dfa.char_class_of('z'));
+        assert_ne!(dfa.char_class_of('b'), dfa.char_class_of('1'));
+        assert_ne!(dfa.char_class_of('_'), dfa.char_class_of('1'));
+        for state in 0..dfa.get_num_states() {
+            for ch in (0..128u8).map(char::from).chain(['é', '日', '本', 'ü']) {
+                assert_eq!(dfa.transition(state, ch), plain.transition(state, ch));
+            }
+        }
+    }
+
+    #[test]
+    fn test_compile_stages() {
+        let stages = compile_stages("(a|b)*c", true).unwrap();
+        let minimal_dfa = stages.minimal_dfa.as_ref().unwrap();
+
+        assert!(minimal_dfa.get_num_states() <= stages.dfa.get_num_states());
+        assert!(minimal_dfa.equivalent(&stages.dfa));
+        assert_eq!(
+            minimal_dfa.get_num_states(),
+            compile("(a|b)*c").unwrap().get_num_states()
+        );
+
+        // The subset construction DFA is kept as is
+        assert_eq!(stages.dfa.get_num_states(), 4);
+        assert!(stages.dfa.nfa_states_of(0).is_some());
+
+        let stages = compile_stages("(a|b)*c", false).unwrap();
+        assert!(stages.minimal_dfa.is_none());
+        assert_eq!(stages.into_dfa().get_num_states(), 4);
+
+        assert!(compile_stages("(a|b", true).is_err());
+    }
+
+    #[test]
+    fn test_accepts_bytes() {
+        // A PNG style signature followed by a length byte and a run of 0xff padding bytes
+        let dfa = compile("\\x89PNG[\\x00-\\x0f]\\xff*").unwrap();
+
+        assert!(dfa.accepts_bytes(&[0x89, b'P', b'N', b'G', 0x03]));
+        assert!(dfa.accepts_bytes(&[0x89, b'P', b'N', b'G', 0x0f, 0xff, 0xff]));
+        assert!(!dfa.accepts_bytes(&[0x89, b'P', b'N', b'G', 0x10]));
+        assert!(!dfa.accepts_bytes(&[0x88, b'P', b'N', b'G', 0x03]));
+        assert!(!dfa.accepts_bytes(&[0x89, b'P', b'N', b'G', 0x03, 0xfe]));
+
+        // The raw bytes 0xc3 0xa9 match the encoding of é, but not the character itself
+        let dfa = compile("\\xc3\\xa9").unwrap();
+        assert!(dfa.accepts_bytes("é".as_bytes()));
+        assert!(!dfa.accepts("é"));
+
+        // A character matches its UTF-8 encoding and never the byte with the same code point
+        let dfa = compile("é").unwrap();
+        assert!(dfa.accepts_bytes("é".as_bytes()));
+        assert!(!dfa.accepts_bytes(&[0xe9]));
+
+        // A raw byte is never matched by a character, not even its private use symbol
+        let dfa = compile("\\xe9").unwrap();
+        assert!(dfa.accepts_bytes(&[0xe9]));
+        assert!(!dfa.accepts("é"));
+        assert!(!dfa.accepts(&byte_symbol(0xe9).to_string()));
+    }
+
+    // Test tokenizing binary input with a ByteDFA, where the encoding of a character and the raw
+    // bytes can start with the same byte
+    #[test]
+    fn test_byte_dfa_tokenize() {
+        let dfa = build_dfa(&[
+            ("\\x89PNG", "MAGIC"),
+            ("é", "E_ACUTE"),
+            ("[\\x80-\\xff]+", "HIGH"),
+            ("[a-z]+", "WORD"),
+        ]);
+        let byte_dfa = ByteDFA::new(&dfa).unwrap();
+
+        let tokens = byte_dfa.tokenize(b"\x89PNG\xff\xfeab\xc3\xa9").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                (0..4, "MAGIC"),
+                (4..6, "HIGH"),
+                (6..8, "WORD"),
+                (8..10, "E_ACUTE")
+            ]
+        );
+
+        // The longer run of raw bytes wins over é, and 0xe9 alone is a raw byte and not é
+        let tokens = byte_dfa.tokenize(b"\xc3\xa9\xe9z").unwrap();
+        assert_eq!(tokens, vec![(0..3, "HIGH"), (3..4, "WORD")]);
+
+        assert_eq!(byte_dfa.tokenize(b"ab!"), None);
+        assert!(ByteDFA::new(&compile("^a").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let regex_list = vec![
+            ("(a|b)*abb".to_string(), "A".to_string()),
+            ("a?c*".to_string(), "C".to_string()),
+        ];
+        let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+        let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+        let dfa = construct_dfa(&nfa, false).unwrap();
+
+        for state in dfa.get_states() {
+            assert!(
+                !state.get_transitions().contains_key(&Symbol::Epsilon),
+                "State {} has an epsilon transition",
+                state.id
+            );
+        }
+        assert!(dfa.validate().is_ok());
+        assert!(construct_minimal_dfa(&dfa, false).validate().is_ok());
+
+        let mut dfa = DFA::new();
+        let s0 = dfa.add_state();
+        let s1 = dfa.add_state();
+        dfa.states[s0].transitions.insert(Symbol::Epsilon, s1);
+        assert!(matches!(
+            dfa.validate(),
+            Err(DFAError::EpsilonTransition(state)) if state == s0
+        ));
+
+        dfa.states[s0].transitions.clear();
+        dfa.alphabet.insert('a');
+        dfa.states[s1].transitions.insert(Symbol::Char('a'), 7);
+        assert!(matches!(
+            dfa.validate(),
+            Err(DFAError::DanglingTransition(state, 7)) if state == s1
+        ));
+    }
+
+    #[test]
+    fn test_compile_with_universe() {
+        let letters: HashSet<char> = ('a'..='z').chain('A'..='Z').collect();
+
+        let dfa = compile_with_universe("[^a]", &letters).unwrap();
+        assert!(dfa.accepts("b"));
+        assert!(dfa.accepts("Z"));
+        assert!(!dfa.accepts("a"));
+        assert!(!dfa.accepts("1"));
+        assert!(!dfa.accepts("é"));
+
+        let dfa = compile_with_universe("a.c", &letters).unwrap();
+        assert!(dfa.accepts("abc"));
+        assert!(dfa.accepts("aac"));
+        assert!(!dfa.accepts("a-c"));
+        assert_eq!(dfa.get_alphabet().len(), letters.len());
+
+        // The default universe is printable ASCII
+        let dfa = compile("[^a]").unwrap();
+        assert!(dfa.accepts("-"));
+        assert!(!dfa.accepts("a"));
+        assert!(!dfa.accepts("\n"));
+
+        // The any character never matches a newline, even when it is part of the universe
+        let with_newline: HashSet<char> = letters.iter().cloned().chain(['\n']).collect();
+        let dfa = compile_with_universe(".", &with_newline).unwrap();
+        assert!(dfa.accepts("x"));
+        assert!(!dfa.accepts("\n"));
+        assert!(compile_with_universe("[^a]", &with_newline)
+            .unwrap()
+            .accepts("\n"));
+
+        // Negating the whole universe leaves nothing to match
+        assert!(compile_with_universe("[^a-zA-Z]", &letters).is_err());
+    }
+
+    #[test]
+    fn test_negated_class_complement() {
+        let printable = default_universe();
+        let dfa = compile_with_universe("[^0-9]+", &printable).unwrap();
+
+        assert!(dfa.accepts("abc"));
+        assert!(dfa.accepts("a b!"));
+        assert!(!dfa.accepts("123"));
+        assert!(!dfa.accepts("ab1"));
+        assert!(!dfa.accepts("😀"));
+        assert!(!dfa.accepts("ab😀"));
+
+        // The transitions are over exactly the universe without the digits
+        let digits: HashSet<char> = ('0'..='9').collect();
+        let complement: HashSet<char> = printable.difference(&digits).cloned().collect();
+        assert_eq!(dfa.get_alphabet(), &complement);
+        for state in dfa.get_states() {
+            for symbol in state.transitions.keys() {
+                match symbol {
+                    Symbol::Char(ch) => assert!(complement.contains(ch)),
+                    Symbol::Epsilon => assert!(false, "A DFA has no epsilon transitions"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_matcher_feed() {
+        let dfa = build_dfa(&[("while", "KEYWORD"), ("[0-9]+", "NUMBER")]);
+        let mut matcher = Matcher::new(&dfa);
+
+        assert_eq!(matcher.feed("wh"), FeedResult::NeedMore);
+        assert_eq!(matcher.feed("i"), FeedResult::NeedMore);
+        assert_eq!(
+            matcher.feed("le"),
+            FeedResult::Accept(5, "KEYWORD".to_string())
+        );
+        // Input past the end of the token leaves the completed token
+        assert_eq!(
+            matcher.feed(" x"),
+            FeedResult::Accept(5, "KEYWORD".to_string())
+        );
+
+        matcher.reset();
+        assert_eq!(matcher.feed("whale"), FeedResult::Reject);
+
+        // A token which can still grow is accepted after every piece
+        matcher.reset();
+        assert_eq!(
+            matcher.feed("4"),
+            FeedResult::Accept(1, "NUMBER".to_string())
+        );
+        assert_eq!(
+            matcher.feed("2"),
+            FeedResult::Accept(2, "NUMBER".to_string())
+        );
+    }
+
+    #[test]
+    fn test_categories() {
+        let dfa = build_dfa(&[
+            ("if|else", "KEYWORD"),
+            ("[a-z]+", "IDENTIFIER"),
+            ("[0-9]+", "NUMBER"),
+        ]);
+
+        let categories: Vec<String> = dfa.categories().into_iter().collect();
+        assert_eq!(categories, vec!["IDENTIFIER", "KEYWORD", "NUMBER"]);
+
+        // The priorities follow the declaration order and survive minimization
+        assert_eq!(dfa.category_priority("KEYWORD"), Some(0));
+        assert_eq!(dfa.category_priority("IDENTIFIER"), Some(1));
+        assert_eq!(dfa.category_priority("NUMBER"), Some(2));
+        assert_eq!(dfa.category_priority("STRING"), None);
+
+        // A regex compiled on its own has no categories
+        assert!(compile("ab*").unwrap().categories().is_empty());
+    }
+
+    #[test]
+    fn test_reachable_categories() {
+        let dfa = build_dfa(&[
+            ("if|else", "KEYWORD"),
+            ("[a-z]+", "IDENTIFIER"),
+            ("[0-9]+", "NUMBER"),
+        ]);
+
+        let reachable = |input: &str| {
+            let state = input
+                .chars()
+                .try_fold(dfa.get_start_state(), |state, ch| dfa.transition(state, ch));
+            dfa.reachable_categories(state.unwrap())
+                .into_iter()
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(reachable(""), vec!["IDENTIFIER", "KEYWORD", "NUMBER"]);
+        assert_eq!(reachable("el"), vec!["IDENTIFIER", "KEYWORD"]);
+        assert_eq!(reachable("else"), vec!["IDENTIFIER", "KEYWORD"]);
+        assert_eq!(reachable("elx"), vec!["IDENTIFIER"]);
+        assert_eq!(reachable("42"), vec!["NUMBER"]);
+
+        assert!(dfa.reachable_categories(dfa.get_num_states()).is_empty());
+    }
+
+    #[test]
+    fn test_match_all_categories() {
+        let dfa = build_dfa(&[
+            ("if|else", "KEYWORD"),
+            ("[a-z]+", "IDENTIFIER"),
+            ("[0-9]+", "NUMBER"),
+        ]);
+
+        let matches = |input: &str| {
+            dfa.match_all_categories(input)
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<String>>()
+        };
+
+        // The keyword wins by priority, but the identifier pattern matches as well
+        assert_eq!(dfa.category_of(walk(&dfa, "if").unwrap()), Some("KEYWORD"));
+        assert_eq!(matches("if"), vec!["IDENTIFIER", "KEYWORD"]);
+        assert_eq!(matches("else"), vec!["IDENTIFIER", "KEYWORD"]);
+        assert_eq!(matches("iff"), vec!["IDENTIFIER"]);
+        assert_eq!(matches("42"), vec!["NUMBER"]);
+        assert!(matches("").is_empty());
+        assert!(matches("if2").is_empty());
+
+        // Accept states with the same winning category are merged by construct_minimal_dfa, which
+        // loses the categories they do not share, and only if all their categories agree by
+        // construct_minimal_dfa_preserving_categories
+        let dfa = build_dfa(&[("a|b", "X"), ("b", "Y")]);
+        assert_eq!(dfa.get_num_states(), 2);
+        assert_eq!(dfa.match_all_categories("a"), None);
+        assert_eq!(dfa.match_all_categories("b"), None);
+        assert_eq!(dfa.match_all_categories("c"), Some(BTreeSet::new()));
+        assert!(dfa.is_minimal());
+
+        let regex_list = vec![
+            ("a|b".to_string(), "X".to_string()),
+            ("b".to_string(), "Y".to_string()),
+        ];
+        let nfa = construct_nfa(parse_microsyntax_list(regex_list).unwrap(), false).unwrap();
+        let dfa = construct_dfa(&nfa, false).unwrap();
+        let dfa = construct_minimal_dfa_preserving_categories(&dfa, false);
+        assert_eq!(dfa.get_num_states(), 3);
+        let a = dfa.match_all_categories("a").unwrap();
+        let b = dfa.match_all_categories("b").unwrap();
+        assert_eq!(a.into_iter().collect::<Vec<String>>(), vec!["X"]);
+        assert_eq!(b.into_iter().collect::<Vec<String>>(), vec!["X", "Y"]);
+    }
+
+    #[test]
+    fn test_unreachable_categories() {
+        let rules = [
+            ("if", "KEYWORD"),
+            ("[a-z]+", "IDENTIFIER"),
+            ("if", "RESERVED"),
+            ("[0-9]+", "NUMBER"),
+        ];
+        let dfa = build_dfa(&rules);
+        let declared: Vec<String> = rules
+            .iter()
+            .map(|(_, category)| category.to_string())
+            .collect();
+
+        // The reserved rule is identical to the keyword rule, which has the higher priority
+        assert_eq!(dfa.unreachable_categories(&declared), vec!["RESERVED"]);
+
+        let declared = vec!["NUMBER".to_string(), "KEYWORD".to_string()];
+        assert!(dfa.unreachable_categories(&declared).is_empty());
+    }
+
+    #[test]
+    fn test_regex_for_category() {
+        let mut dfa = build_dfa(&[
+            ("if|else", "KEYWORD"),
+            ("[a-z]+", "IDENTIFIER"),
+            ("[0-9]+", "NUMBER"),
+            ("0x[0-9a-f]+", "NUMBER"),
+        ]);
+
+        assert_eq!(dfa.get_regex(), "if|else|[a-z]+|[0-9]+|0x[0-9a-f]+");
+        assert_eq!(dfa.regex_for_category("KEYWORD"), Some("if|else"));
+        assert_eq!(dfa.regex_for_category("IDENTIFIER"), Some("[a-z]+"));
+        assert_eq!(dfa.regex_for_category("NUMBER"), Some("[0-9]+|0x[0-9a-f]+"));
+        assert_eq!(dfa.regex_for_category("STRING"), None);
+
+        dfa.remap_categories(|category| match category {
+            "KEYWORD" | "IDENTIFIER" => "WORD".to_string(),
+            other => other.to_string(),
+        });
+        assert_eq!(dfa.regex_for_category("WORD"), Some("if|else|[a-z]+"));
+        assert_eq!(dfa.regex_for_category("KEYWORD"), None);
+
+        assert_eq!(compile("ab*").unwrap().regex_for_category(""), None);
+    }
+
+    #[test]
+    fn test_all_matches() {
+        let dfa = build_dfa(&[("if", "KEYWORD"), ("[a-z]+", "IDENTIFIER")]);
+
+        let matches = dfa.all_matches("x ifs", 2);
+        assert_eq!(
+            matches,
+            vec![
+                (3, "IDENTIFIER".to_string()),
+                (4, "KEYWORD".to_string()),
+                (4, "IDENTIFIER".to_string()),
+                (5, "IDENTIFIER".to_string()),
+            ]
+        );
+
+        // Every category matching the lexeme is reported, like match_all_categories
+        let categories: Vec<String> = dfa
+            .match_all_categories("if")
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(categories, vec!["IDENTIFIER", "KEYWORD"]);
+        assert_eq!(
+            dfa.all_matches("if", 0),
+            vec![
+                (1, "IDENTIFIER".to_string()),
+                (2, "KEYWORD".to_string()),
+                (2, "IDENTIFIER".to_string()),
+            ]
+        );
+
+        assert!(dfa.all_matches("x ifs", 1).is_empty());
+        assert!(dfa.all_matches("x ifs", 6).is_empty());
+        assert!(dfa.all_matches("é", 1).is_empty());
+    }
+
+    #[test]
+    fn test_accepting_prefixes() {
+        let dfa = build_dfa(&[("in|int", "KEYWORD"), ("[0-9]+", "NUMBER")]);
+
+        assert_eq!(
+            dfa.accepting_prefixes("int"),
+            vec![(2, "KEYWORD".to_string()), (3, "KEYWORD".to_string())]
+        );
+        // The walk stops once the DFA dies, even if a later suffix would match
+        assert_eq!(
+            dfa.accepting_prefixes("ints 42"),
+            vec![(2, "KEYWORD".to_string()), (3, "KEYWORD".to_string())]
+        );
+        assert!(dfa.accepting_prefixes("i").is_empty());
+        assert!(dfa.accepting_prefixes(" int").is_empty());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_construction_metrics() {
+        let regex_list = vec![("(a|b)*abb".to_string(), "A".to_string())];
+        let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+        let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+
+        let (dfa, metrics) = construct_dfa_with_metrics(&nfa).unwrap();
+        assert_eq!(metrics.worklist_iterations, dfa.get_num_states());
+        assert_eq!(metrics.peak_state_sets, dfa.get_num_states());
+        assert_eq!(metrics.refinement_passes, 0);
+
+        let (minimal_dfa, metrics) = construct_minimal_dfa_with_metrics(&dfa);
+        assert!(metrics.refinement_passes > 1);
+        assert_eq!(metrics.peak_state_sets, minimal_dfa.get_num_states());
+        assert_eq!(metrics.worklist_iterations, 0);
+    }
+
+    #[test]
+    fn test_is_deterministic() {
+        let dfa = build_dfa(&[("if|else", "KEYWORD"), ("[a-z]+", "IDENTIFIER")]);
+        assert!(dfa.is_deterministic());
+        assert!(compile("(a|b)*abb").unwrap().is_deterministic());
+
+        // A merge which copied a state without renumbering it
+        let mut merged = build_dfa(&[("ab", "A")]);
+        let copy = merged.states[1].clone();
+        merged.states.push(copy);
+        merged.accept_states.push(false);
+        assert!(!merged.is_deterministic());
+        assert!(matches!(
+            merged.validate(),
+            Err(DFAError::StateIdMismatch(3, 1))
+        ));
+
+        let mut corrupted = build_dfa(&[("ab", "A")]);
+        let start = corrupted.start_state;
+        corrupted.states[start]
+            .transitions
+            .insert(Symbol::Epsilon, start);
+        assert!(!corrupted.is_deterministic());
+
+        let mut corrupted = build_dfa(&[("ab", "A")]);
+        corrupted.states[start]
+            .transitions
+            .insert(Symbol::Char('z'), start);
+        assert!(matches!(
+            corrupted.validate(),
+            Err(DFAError::UnknownSymbol(_, 'z'))
+        ));
+    }
 
-        // Check accept states
-        let accept_count = minimal_dfa.get_acceptor_states().count_ones();
-        assert_eq!(accept_count, 1);
+    #[test]
+    fn test_dotall() {
+        let dfa = compile("a.b").unwrap();
+        assert!(dfa.accepts("a-b"));
+        assert!(!dfa.accepts("a\nb"));
+
+        let dfa = compile("(?s)a.b").unwrap();
+        assert!(dfa.accepts("a-b"));
+        assert!(dfa.accepts("a\nb"));
+    }
 
-        // Verify the category is preserved
-        let accept_states: Vec<usize> = minimal_dfa.get_acceptor_states().iter_ones().collect();
-        for state_id in accept_states {
-            assert_eq!(minimal_dfa.get_state(state_id).get_category(), "A");
-        }
+    #[test]
+    fn test_escape_literal() {
+        let dfa = compile(&crate::regex::escape_literal("a+b")).unwrap();
+        assert!(dfa.accepts("a+b"));
+        assert!(!dfa.accepts("aab"));
+        assert!(!dfa.accepts("ab"));
+
+        let literal = "([a|b]*)\\?.+\n";
+        let dfa = compile(&crate::regex::escape_literal(literal)).unwrap();
+        assert!(dfa.accepts(literal));
+        assert!(!dfa.accepts("(a*)"));
     }
 
     #[test]
-    fn test_compare_transitions() {
-        // Create two DFA states with same transitions
-        let mut dfa = DFA::new();
-        let s0 = dfa.add_state();
-        let s1 = dfa.add_state();
-        let s2 = dfa.add_state();
-        let s3 = dfa.add_state();
+    fn test_microsyntax_cache() {
+        let regex_list = vec![
+            ("if|else".to_string(), "KEYWORD".to_string()),
+            ("[a-z]+".to_string(), "IDENTIFIER".to_string()),
+            ("[0-9]+".to_string(), "NUMBER".to_string()),
+        ];
+        let mut cache = MicrosyntaxCache::new(regex_list).unwrap();
+        assert_eq!(cache.get_parse_count(), 3);
+
+        // Changing only the category does not parse anything again
+        cache
+            .update(1, "[a-z]+".to_string(), "NAME".to_string())
+            .unwrap();
+        assert_eq!(cache.get_parse_count(), 3);
 
-        dfa.alphabet.insert('a');
-        dfa.alphabet.insert('b');
+        let dfa = cache.build().unwrap();
+        assert_eq!(dfa.category_of(walk(&dfa, "foo").unwrap()), Some("NAME"));
+        assert_eq!(dfa.category_of(walk(&dfa, "if").unwrap()), Some("KEYWORD"));
 
-        // States with same transitions pattern
-        dfa.states[s0].transitions.insert(Symbol::Char('a'), s2);
-        dfa.states[s0].transitions.insert(Symbol::Char('b'), s3);
-        dfa.states[s1].transitions.insert(Symbol::Char('a'), s2);
-        dfa.states[s1].transitions.insert(Symbol::Char('b'), s3);
+        // Changing a regex only parses that regex
+        cache
+            .update(2, "[0-9]+(.[0-9]+)?".to_string(), "NUMBER".to_string())
+            .unwrap();
+        assert_eq!(cache.get_parse_count(), 4);
 
-        // Create lookup table
-        let mut lookup_table = LookupTable::new();
-        lookup_table.insert_state_in_set(s0, 0);
-        lookup_table.insert_state_in_set(s1, 0);
-        lookup_table.insert_state_in_set(s2, 1);
-        lookup_table.insert_state_in_set(s3, 2);
+        let dfa = cache.build().unwrap();
+        assert_eq!(dfa.category_of(walk(&dfa, "4.2").unwrap()), Some("NUMBER"));
 
-        // States should have same transitions
-        let result = compare_transitions(
-            &dfa.states[s0],
-            &dfa.states[s1],
-            &dfa.alphabet,
-            &lookup_table,
+        let result = cache.update(3, "[ ]+".to_string(), "WHITESPACE".to_string());
+        match result.err().unwrap().downcast_ref() {
+            Some(DFAError::InvalidMicrosyntaxIndex(index, len)) => {
+                assert_eq!((*index, *len), (3, 3))
+            }
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn test_number_rule() {
+        let all_radixes = [
+            Radix::Hexadecimal,
+            Radix::Binary,
+            Radix::Octal,
+            Radix::Decimal,
+        ];
+
+        let mut builder = ScannerBuilder::new();
+        builder.add_number_rule("NUMBER", &all_radixes, true, 0);
+        builder.add_rule("[a-z_]+", "IDENTIFIER", 0);
+        builder.add_rule(" +", "WHITESPACE", 0);
+        let dfa = builder.build().unwrap();
+
+        // Every literal is a single token spanning the prefix, the digits and the underscores
+        let scanner = construct_scanner(&dfa).unwrap();
+        let skip_list = Some(vec!["WHITESPACE".to_string()]);
+        let token_list = scanner
+            .scan_bytes(b"0xFF_00 0b1010 1_000 0o17 42", false, skip_list)
+            .unwrap();
+        let tokens: Vec<(&str, &str)> = token_list
+            .iter()
+            .map(|token| (token.get_token().as_str(), token.get_category().as_str()))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                ("0xFF_00", "NUMBER"),
+                ("0b1010", "NUMBER"),
+                ("1_000", "NUMBER"),
+                ("0o17", "NUMBER"),
+                ("42", "NUMBER"),
+            ]
         );
-        assert!(result);
 
-        // Now modify one transition
-        dfa.states[s1].transitions.insert(Symbol::Char('b'), s2);
+        for rejected in ["1__000", "1_", "_1", "0x", "0b102", "0o8", "0x_FF"] {
+            let accepted = walk(&dfa, rejected).and_then(|state| dfa.category_of(state));
+            assert_ne!(accepted, Some("NUMBER"), "{}", rejected);
+        }
 
-        // States should have different transitions now
-        let result = compare_transitions(
-            &dfa.states[s0],
-            &dfa.states[s1],
-            &dfa.alphabet,
-            &lookup_table,
+        // Without underscores and with only some radixes, the others are not numbers
+        let dfa = compile(&number_literal_regex(
+            &[Radix::Binary, Radix::Decimal],
+            false,
+        ))
+        .unwrap();
+        assert!(dfa.accepts("0b1010"));
+        assert!(dfa.accepts("1000"));
+        assert!(!dfa.accepts("1_000"));
+        assert!(!dfa.accepts("0xFF"));
+    }
+
+    #[test]
+    fn test_scanner_builder() {
+        let mut builder = ScannerBuilder::new();
+        builder.add_rule("[a-z]+", "IDENTIFIER", 0);
+        builder.add_rule("if|else", "KEYWORD", 1);
+        builder.add_rule(" +", "WHITESPACE", 0);
+
+        // The keyword rule was added after the identifier rule but has the higher priority
+        let dfa = builder.build().unwrap();
+        assert_eq!(dfa.category_of(walk(&dfa, "if").unwrap()), Some("KEYWORD"));
+        assert_eq!(
+            dfa.category_of(walk(&dfa, "ifs").unwrap()),
+            Some("IDENTIFIER")
         );
-        assert!(!result);
+
+        let scanner = construct_scanner(&dfa).unwrap();
+        let skip_list = Some(vec!["WHITESPACE".to_string()]);
+        let token_list = scanner.scan_bytes(b"if x else", false, skip_list).unwrap();
+        let tokens: Vec<(&str, &str)> = token_list
+            .iter()
+            .map(|token| (token.get_token().as_str(), token.get_category().as_str()))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![("if", "KEYWORD"), ("x", "IDENTIFIER"), ("else", "KEYWORD")]
+        );
+
+        // Rules with equal priorities keep the order they were added in
+        let mut builder = ScannerBuilder::new();
+        builder.add_rule("[a-z]+", "IDENTIFIER", 0);
+        builder.add_rule("if", "KEYWORD", 0);
+        let dfa = builder.build().unwrap();
+        assert_eq!(
+            dfa.category_of(walk(&dfa, "if").unwrap()),
+            Some("IDENTIFIER")
+        );
+
+        let err = ScannerBuilder::new().build().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<NFAError>(),
+            Some(NFAError::NoMicrosyntaxes)
+        ));
+
+        let mut builder = ScannerBuilder::new();
+        builder.add_rule("a(", "BROKEN", 0);
+        assert!(builder.build().is_err());
     }
 
     #[test]
-    fn test_reorder_minimal_dfa() {
-        // Create a DFA with unordered states
-        let mut dfa = DFA::new();
+    fn test_from_strings() {
+        let words = ["if", "else", "while", "for", "return", "a+b"];
+        let dfa = DFA::from_strings(&words);
 
-        // Add states in non-consecutive order
-        let s0 = dfa.add_state();
-        let s1 = dfa.add_state();
-        let s2 = dfa.add_state();
+        assert!(dfa.is_deterministic());
+        for word in words {
+            assert!(dfa.accepts(word), "Expected {} to be accepted", word);
+        }
+        for rejected in [
+            "",
+            "i",
+            "els",
+            "whilee",
+            "iff",
+            "fo",
+            "forreturn",
+            "ab",
+            "a",
+        ] {
+            assert!(
+                !dfa.accepts(rejected),
+                "Expected {} to be rejected",
+                rejected
+            );
+        }
 
-        // Set up transitions
-        dfa.states[s0].transitions.insert(Symbol::Char('a'), s2);
-        dfa.states[s2].transitions.insert(Symbol::Char('b'), s1);
+        // Minimal, so the accept states at the end of every word are merged into one
+        assert_eq!(dfa.get_acceptor_states().count_ones(), 1);
+        assert_eq!(dfa.get_regex(), "if|else|while|for|return|a\\+b");
+        assert!(dfa.equivalent(&compile(dfa.get_regex()).unwrap()));
+        let minimal_dfa = construct_minimal_dfa(&compile(dfa.get_regex()).unwrap(), false);
+        assert_eq!(dfa.get_num_states(), minimal_dfa.get_num_states());
 
-        // Set start and accept states
-        dfa.start_state = s0;
-        dfa.accept_states.set(s1, true);
-        dfa.states[s1].category = "TEST".to_string();
+        // No state accepts a string outside the set, even one ending the same way as a word
+        for state in dfa.get_acceptor_states().iter_ones() {
+            assert!(dfa.get_state(state).get_transitions().is_empty());
+        }
 
-        // Add to alphabet
-        dfa.alphabet.insert('a');
-        dfa.alphabet.insert('b');
+        let dfa = DFA::from_strings(&["", "ab"]);
+        assert!(dfa.accepts(""));
+        assert!(dfa.accepts("ab"));
+        assert!(!dfa.accepts("a"));
+        assert!(!dfa.is_empty_language());
 
-        // Reorder
-        let reordered = reorder_minimal_dfa(&dfa);
+        assert!(DFA::from_strings(&[]).is_empty_language());
+    }
 
-        // Should still have 3 states
-        assert_eq!(reordered.get_num_states(), 3);
+    #[test]
+    fn test_construct_keyword_dfa() {
+        let regex_list = vec![(
+            "if|else|while|for|return|\\(\\)".to_string(),
+            "KEYWORD".to_string(),
+        )];
+        let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+        let trie = construct_keyword_dfa(&syntax_tree_list[0].1, "KEYWORD").unwrap();
+
+        assert!(trie.is_deterministic());
+        for keyword in ["if", "else", "while", "for", "return", "()"] {
+            assert!(trie.accepts(keyword), "Expected {} to be accepted", keyword);
+            assert_eq!(
+                trie.category_of(walk(&trie, keyword).unwrap()),
+                Some("KEYWORD")
+            );
+        }
+        for rejected in ["i", "", "iff", "whil", "fore", "("] {
+            assert!(
+                !trie.accepts(rejected),
+                "Expected {} to be rejected",
+                rejected
+            );
+        }
 
-        // Accept state should still be marked
-        let accept_states: Vec<usize> = reordered.get_acceptor_states().iter_ones().collect();
-        assert_eq!(accept_states.len(), 1);
+        // One state per distinct prefix, including the empty one
+        assert_eq!(trie.get_num_states(), 1 + 2 + 4 + 5 + 3 + 6 + 2);
 
-        // Category should be preserved
-        for accept in accept_states {
-            assert_eq!(reordered.get_state(accept).get_category(), "TEST");
+        let subset_dfa = build_dfa(&[("if|else|while|for|return|\\(\\)", "KEYWORD")]);
+        assert!(trie.equivalent(&subset_dfa));
+
+        // Anything other than a flat list of literals goes through the usual construction
+        for regex in ["(a|b)c", "ab*", "[ab]", "a|.", "(ab)"] {
+            let regex_list = vec![(regex.to_string(), "A".to_string())];
+            let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+            assert!(construct_keyword_dfa(&syntax_tree_list[0].1, "A").is_none());
         }
 
-        // Check if transitions are preserved
-        let start_transitions = reordered.get_state_transitions(reordered.get_start_state());
-        assert_eq!(start_transitions.len(), 1);
+        assert!(compile("if|else").unwrap().accepts("else"));
+    }
 
-        // Follow path: start --a--> middle --b--> accept
-        let middle_state = match start_transitions[0].0 {
-            Symbol::Char('a') => *start_transitions[0].1,
-            _ => panic!("Expected 'a' transition"),
+    #[test]
+    fn test_tie_break() {
+        let regex_list = vec![
+            ("if".to_string(), "KEYWORD".to_string()),
+            ("[a-z]+".to_string(), "IDENTIFIER".to_string()),
+            ("i[a-z]".to_string(), "I_WORD".to_string()),
+        ];
+        let build = |tie_break: &TieBreak| {
+            let syntax_tree_list = parse_microsyntax_list(regex_list.clone()).unwrap();
+            let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+            let dfa = construct_dfa_with_tie_break(&nfa, tie_break, false).unwrap();
+            construct_minimal_dfa(&dfa, false)
         };
 
-        let middle_transitions = reordered.get_state_transitions(middle_state);
-        assert_eq!(middle_transitions.len(), 1);
+        // All three microsyntaxes accept "if"
+        let dfa = build(&TieBreak::DeclarationOrder);
+        assert_eq!(dfa.category_of(walk(&dfa, "if").unwrap()), Some("KEYWORD"));
+        assert_eq!(
+            dfa.category_of(walk(&dfa, "in").unwrap()),
+            Some("IDENTIFIER")
+        );
 
-        let end_state = match middle_transitions[0].0 {
-            Symbol::Char('b') => *middle_transitions[0].1,
-            _ => panic!("Expected 'b' transition"),
+        let dfa = build(&TieBreak::LongestPattern);
+        assert_eq!(
+            dfa.category_of(walk(&dfa, "if").unwrap()),
+            Some("IDENTIFIER")
+        );
+
+        let priorities = HashMap::from([("I_WORD".to_string(), 10), ("KEYWORD".to_string(), 5)]);
+        let dfa = build(&TieBreak::ExplicitPriority(priorities));
+        assert_eq!(dfa.category_of(walk(&dfa, "if").unwrap()), Some("I_WORD"));
+        assert_eq!(dfa.category_of(walk(&dfa, "in").unwrap()), Some("I_WORD"));
+        assert_eq!(
+            dfa.category_of(walk(&dfa, "ink").unwrap()),
+            Some("IDENTIFIER")
+        );
+    }
+
+    #[test]
+    fn test_find_anchors() {
+        let dfa = compile("^b").unwrap();
+        assert_eq!(dfa.find("a\nb", AnchorMode::SingleLine), None);
+        assert_eq!(dfa.find("a\nb", AnchorMode::MultiLine), Some(2..3));
+        assert_eq!(dfa.find("b\nb", AnchorMode::SingleLine), Some(0..1));
+
+        let dfa = compile("a$").unwrap();
+        assert_eq!(dfa.find("a\nb", AnchorMode::SingleLine), None);
+        assert_eq!(dfa.find("a\nb", AnchorMode::MultiLine), Some(0..1));
+        assert_eq!(dfa.find("ba", AnchorMode::SingleLine), Some(1..2));
+
+        // The anchored and the unanchored alternatives are both followed
+        let dfa = compile("^ab|b+").unwrap();
+        assert_eq!(dfa.find("ab", AnchorMode::SingleLine), Some(0..2));
+        assert_eq!(dfa.find("cab", AnchorMode::SingleLine), Some(2..3));
+    }
+
+    #[test]
+    fn test_anchors_outside_find() {
+        let dfa = compile("^b").unwrap();
+        assert!(dfa.accepts("b"));
+        assert!(dfa.accepts_bytes(b"b"));
+        assert_eq!(dfa.longest_match("bb", 0), Some(0..1));
+        assert_eq!(dfa.longest_match("ab", 1), None);
+
+        let dfa = compile("a$").unwrap();
+        assert!(dfa.accepts("a"));
+        assert_eq!(dfa.match_span("a"), Some(0..1));
+        assert_eq!(dfa.match_span("ab"), None);
+
+        // The anchored and the unanchored alternatives are both followed
+        let dfa = build_dfa(&[("^ab", "START"), ("b+", "B")]);
+        assert_eq!(dfa.longest_match("ab", 0), Some(0..2));
+        assert_eq!(dfa.longest_match("cbb", 1), Some(1..3));
+        assert_eq!(dfa.all_matches("abb", 0), vec![(2, "START".to_string())]);
+        assert_eq!(
+            dfa.match_all_categories("ab"),
+            Some(BTreeSet::from(["START".to_string()]))
+        );
+
+        // The markers of the anchors never match a character of the input
+        assert!(!compile("a$").unwrap().accepts("a\u{E001}"));
+        assert!(!compile("^b").unwrap().accepts("\u{E000}b"));
+
+        // Table driven scanners can not check the anchors, the escaped characters are literals
+        let scanner = construct_scanner(&compile("a$").unwrap());
+        assert!(matches!(
+            scanner.err().unwrap().downcast_ref(),
+            Some(ScannerError::AssertionInDFA)
+        ));
+        assert!(construct_scanner(&compile("a\\$").unwrap()).is_ok());
+        assert!(matches!(
+            emit_rust(&compile("^a").unwrap()),
+            Err(DFAError::UnsupportedAssertion)
+        ));
+    }
+
+    #[test]
+    fn test_find_word_boundary() {
+        let dfa = compile("\\bcat\\b").unwrap();
+        assert_eq!(dfa.find(" cat ", AnchorMode::SingleLine), Some(1..4));
+        assert_eq!(dfa.find("cat", AnchorMode::SingleLine), Some(0..3));
+        assert_eq!(dfa.find("a cat.", AnchorMode::SingleLine), Some(2..5));
+        assert_eq!(dfa.find("scatter", AnchorMode::SingleLine), None);
+        assert_eq!(dfa.find("cat_1", AnchorMode::SingleLine), None);
+
+        // A boundary only at the start matches words beginning with the pattern
+        let dfa = compile("\\bcat").unwrap();
+        assert_eq!(
+            dfa.find("scatter catalog", AnchorMode::SingleLine),
+            Some(8..11)
+        );
+
+        // The boundary is checked by the other matchers of the DFA as well
+        let dfa = compile("\\bcat\\b").unwrap();
+        assert!(dfa.accepts("cat"));
+        assert_eq!(dfa.longest_match("a cat", 2), Some(2..5));
+        assert_eq!(dfa.longest_match("scat", 1), None);
+        assert_eq!(dfa.match_span("cats"), None);
+        assert!(!dfa.accepts("\u{E002}cat"));
+
+        let scanner = construct_scanner(&dfa);
+        assert!(matches!(
+            scanner.err().unwrap().downcast_ref(),
+            Some(ScannerError::AssertionInDFA)
+        ));
+    }
+
+    #[test]
+    fn test_transition() {
+        let dfa = build_dfa(&[("ab", "AB")]);
+        let start = dfa.get_start_state();
+
+        let next = dfa.transition(start, 'a').unwrap();
+        assert!(dfa.transition(next, 'b').is_some());
+        assert_eq!(dfa.transition(start, 'b'), None);
+        assert_eq!(dfa.transition(dfa.get_num_states(), 'a'), None);
+        assert_eq!(dfa.transition(usize::MAX, 'a'), None);
+    }
+
+    #[test]
+    fn test_construct_dfa_with_limit() {
+        // The DFA has to remember the last few characters, which takes 2^5 states
+        let regex_list = vec![(
+            "(a|b)*a(a|b)(a|b)(a|b)(a|b)".to_string(),
+            "FIFTH_LAST_A".to_string(),
+        )];
+        let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+        let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+
+        let err = construct_dfa_with_limit(&nfa, 16).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DFAError>(),
+            Some(DFAError::StateLimitExceeded(16))
+        ));
+
+        let dfa = construct_dfa_with_limit(&nfa, 64).unwrap();
+        assert!(dfa.get_num_states() <= 64);
+        assert!(dfa.equivalent(&construct_dfa(&nfa, false).unwrap()));
+        assert!(dfa.accepts("babbbb"));
+        assert!(!dfa.accepts("bbabbb"));
+    }
+
+    #[test]
+    fn test_lazy_dfa() {
+        // The eager DFA has to remember the last eleven characters, which takes at least 2^11 states
+        let pattern = format!("(a|b)*a{}", "(a|b)".repeat(10));
+        let regex_list = vec![(pattern, "ELEVENTH_LAST_A".to_string())];
+        let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+        let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+        let dfa = construct_dfa(&nfa, false).unwrap();
+        assert!(dfa.get_num_states() >= 2048);
+
+        // Pseudo random inputs from a linear congruential generator, so the test is repeatable
+        let mut seed: u64 = 42;
+        let inputs: Vec<String> = (0..100)
+            .map(|_| {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let length = (seed >> 33) % 16;
+                (0..length)
+                    .map(|bit| if (seed >> bit) & 1 == 1 { 'a' } else { 'b' })
+                    .collect()
+            })
+            .collect();
+
+        let mut lazy_dfa = LazyDFA::new(&nfa, 4096).unwrap();
+        let mut small_lazy_dfa = LazyDFA::new(&nfa, 8).unwrap();
+
+        for input in inputs.iter().chain([&"ab".repeat(40), &"c".to_string()]) {
+            let expected = dfa.accepts(input);
+            assert_eq!(lazy_dfa.accepts(input).unwrap(), expected, "{}", input);
+            assert_eq!(
+                small_lazy_dfa.accepts(input).unwrap(),
+                expected,
+                "{}",
+                input
+            );
+        }
+
+        // Only the states the inputs reach are built, and the small cache stays within its bound
+        assert!(lazy_dfa.num_cached_states() < dfa.get_num_states() / 2);
+        assert_eq!(lazy_dfa.num_states_built(), lazy_dfa.num_cached_states());
+        assert!(small_lazy_dfa.num_cached_states() <= 8);
+        assert!(small_lazy_dfa.num_states_built() > 8);
+    }
+
+    #[test]
+    fn test_converging_paths_share_states() {
+        for regex in [
+            "(a|b)*abb",
+            "(ab|ab)c",
+            "(a*)*b",
+            "(a|b|ab)*",
+            "a(b|c)*(b|c)d",
+        ] {
+            let regex_list = vec![(regex.to_string(), "R".to_string())];
+            let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+            let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+            let (dfa, trace) = construct_dfa_with_trace(&nfa).unwrap();
+
+            // Every set is explored once, so a set reached again reuses its DFA state
+            assert_eq!(trace.len(), dfa.get_num_states(), "{}", regex);
+
+            let mut sets = HashMap::new();
+            for state in 0..dfa.get_num_states() {
+                let nfa_states = dfa.nfa_states_of(state).unwrap();
+                if let Some(other) = sets.insert(nfa_states, state) {
+                    panic!(
+                        "{}: states {} and {} share an NFA state set",
+                        regex, other, state
+                    );
+                }
+            }
+        }
+
+        // The loop of a* comes back to the state it started from instead of adding a new one
+        let regex_list = vec![("ba*".to_string(), "R".to_string())];
+        let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+        let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+        let dfa = construct_dfa(&nfa, false).unwrap();
+        let after_b = dfa.transition(dfa.get_start_state(), 'b').unwrap();
+        let after_a = dfa.transition(after_b, 'a').unwrap();
+        assert_eq!(dfa.transition(after_a, 'a'), Some(after_a));
+        assert_eq!(dfa.get_num_states(), 3);
+    }
+
+    #[test]
+    fn test_nfa_states_of() {
+        let regex_list = vec![("(a|b)*c".to_string(), "ABC".to_string())];
+        let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+        let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+        let dfa = construct_dfa(&nfa, false).unwrap();
+
+        let start_closure = get_epsilon_closure(&nfa, initial_nfa_states(&nfa).unwrap()).unwrap();
+        assert_eq!(
+            dfa.nfa_states_of(dfa.get_start_state()),
+            Some(start_closure.iter_ones().collect())
+        );
+
+        // Every state is built from a distinct non empty set
+        let mut sets = HashSet::new();
+        for state in 0..dfa.get_num_states() {
+            let nfa_states = dfa.nfa_states_of(state).unwrap();
+            assert!(!nfa_states.is_empty());
+            assert!(sets.insert(nfa_states));
+        }
+        assert_eq!(dfa.nfa_states_of(dfa.get_num_states()), None);
+
+        let minimal_dfa = construct_minimal_dfa(&dfa, false);
+        assert_eq!(
+            minimal_dfa.nfa_states_of(minimal_dfa.get_start_state()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_explored_nfa_state_sets() {
+        let build = || {
+            let regex_list = vec![("a|b".to_string(), "AB".to_string())];
+            let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+            let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+            construct_dfa(&nfa, false).unwrap()
         };
+        let dfa = build();
 
-        assert!(reordered.get_acceptor_states()[end_state]);
+        // Thompson construction numbers the states of a|b as 0 -> 1 -a-> 2 -> 5 and 0 -> 3 -b-> 4 -> 5
+        assert_eq!(
+            dfa.explored_nfa_state_sets(),
+            vec![vec![0, 1, 3], vec![2, 5], vec![4, 5]]
+        );
+
+        // Building again explores the same sets in the same order
+        for _ in 0..10 {
+            assert_eq!(
+                build().explored_nfa_state_sets(),
+                dfa.explored_nfa_state_sets()
+            );
+        }
+
+        let minimal_dfa = construct_minimal_dfa(&dfa, false);
+        assert!(minimal_dfa.explored_nfa_state_sets().is_empty());
     }
 
     #[test]
-    fn test_fa_trait_implementation_for_dfa() {
-        // Create a simple DFA
+    fn test_construct_dfa_with_trace() {
+        let regex_list = vec![("a|b".to_string(), "AB".to_string())];
+        let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+        let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+        let (dfa, trace) = construct_dfa_with_trace(&nfa).unwrap();
+
+        // The start set and the sets reached on 'a' and on 'b'
+        assert_eq!(trace.len(), 3);
+        assert_eq!(trace.len(), dfa.get_num_states());
+
+        let start = &trace[0];
+        assert_eq!(start.dfa_state, dfa.get_start_state());
+        assert!(start.nfa_states.contains(&nfa.get_start_state()));
+        assert_eq!(
+            start
+                .transitions
+                .iter()
+                .map(|(ch, _)| *ch)
+                .collect::<Vec<char>>(),
+            vec!['a', 'b']
+        );
+
+        for step in trace.iter() {
+            assert!(step.nfa_states.windows(2).all(|pair| pair[0] < pair[1]));
+            for (ch, target) in step.transitions.iter() {
+                assert_eq!(dfa.transition(step.dfa_state, *ch), Some(*target));
+            }
+        }
+        for step in trace.iter().skip(1) {
+            assert!(step.transitions.is_empty());
+            assert!(dfa.get_acceptor_states()[step.dfa_state]);
+        }
+
+        // Tracing does not change the DFA
+        assert!(dfa.equivalent(&construct_dfa(&nfa, false).unwrap()));
+    }
+
+    #[test]
+    fn test_construct_dfa_missing_nfa_state() {
+        let mut nfa = NFA::new();
+        let start = nfa.add_state();
+        nfa.add_transition(start, Symbol::Char('a'), 7);
+
+        let err = construct_dfa(&nfa, false).unwrap_err();
+        match err.downcast_ref::<DFAError>() {
+            Some(DFAError::MissingNFAState(7)) => {}
+            _ => assert!(false, "Expected MissingNFAState, got {:?}", err),
+        }
+
+        let mut nfa = NFA::new();
+        nfa.set_start_state(3);
+        let err = construct_dfa(&nfa, false).unwrap_err();
+        match err.downcast_ref::<DFAError>() {
+            Some(DFAError::MissingNFAState(3)) => {}
+            _ => assert!(false, "Expected MissingNFAState, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_is_universal_and_empty_language() {
+        for pattern in [".*", "(a|b)*", "(a*b*)*"] {
+            let dfa = compile(pattern).unwrap();
+            assert!(dfa.is_universal(), "{} should be universal", pattern);
+            assert!(!dfa.is_empty_language());
+        }
+
+        // Missing the empty string, or some strings over the alphabet, is not universal
+        for pattern in ["(a|b)+", "a*b", "abc"] {
+            let dfa = compile(pattern).unwrap();
+            assert!(!dfa.is_universal(), "{} should not be universal", pattern);
+            assert!(!dfa.is_empty_language());
+        }
+
+        // A DFA whose accept state cannot be reached accepts nothing
         let mut dfa = DFA::new();
-        let s0 = dfa.add_state();
-        let s1 = dfa.add_state();
+        let start = dfa.add_state();
+        let unreachable = dfa.add_state();
+        dfa.accept_states.set(unreachable, true);
+        dfa.start_state = start;
+        assert!(dfa.is_empty_language());
+        assert!(!dfa.is_universal());
+
+        assert!(DFA::new().is_empty_language());
+        assert!(!DFA::new().is_universal());
+    }
 
-        dfa.start_state = s0;
-        dfa.accept_states.set(s1, true);
-        dfa.alphabet.insert('a');
+    #[test]
+    fn test_adjacency() {
+        let dfa = build_dfa(&[("(a|b|c)*d", "WORD"), ("[0-9]", "DIGIT")]);
+        let adjacency = dfa.adjacency();
+
+        assert_eq!(adjacency.len(), dfa.get_num_states());
+        for (position, (state, edges)) in adjacency.iter().enumerate() {
+            assert_eq!(*state, position);
+            assert!(edges.windows(2).all(|pair| pair[0].0 < pair[1].0));
+            for (ch, target) in edges {
+                assert_eq!(dfa.transition(*state, *ch), Some(*target));
+            }
+        }
+        let num_edges: usize = adjacency.iter().map(|(_, edges)| edges.len()).sum();
+        let num_transitions: usize = dfa
+            .get_states()
+            .iter()
+            .map(|state| state.transitions.len())
+            .sum();
+        assert_eq!(num_edges, num_transitions);
+
+        // Building the same DFA again gives the same adjacency list and the same DOT bytes, as the
+        // states of a minimal DFA are numbered independently of how the transitions are hashed
+        let rebuilt = build_dfa(&[("(a|b|c)*d", "WORD"), ("[0-9]", "DIGIT")]);
+        assert_eq!(rebuilt.adjacency(), adjacency);
+
+        let mut dot = Vec::new();
+        let mut rebuilt_dot = Vec::new();
+        dfa.write_dot(&mut dot).unwrap();
+        rebuilt.write_dot(&mut rebuilt_dot).unwrap();
+        assert_eq!(dot, rebuilt_dot);
+        assert!(String::from_utf8(dot).unwrap().contains("digraph"));
+    }
 
-        dfa.states[s0].transitions.insert(Symbol::Char('a'), s1);
+    #[test]
+    fn test_to_digraph() {
+        // a(b|c)* has a start state and one accept state looping on b and c
+        let dfa = compile("a(b|c)*").unwrap();
+        let graph = dfa.to_digraph();
+
+        assert_eq!(graph.node_count(), 2);
+        assert_eq!(graph.edge_count(), 3);
+
+        let start = petgraph::graph::NodeIndex::new(dfa.get_start_state());
+        assert!(graph[start].starts_with("Start"));
+        let accept = dfa.get_acceptor_states().first_one().unwrap();
+        let accept = petgraph::graph::NodeIndex::new(accept);
+        assert!(graph[accept].ends_with("Accept"));
+
+        let mut labels: Vec<&String> = graph.edge_weights().collect();
+        labels.sort();
+        assert_eq!(labels, vec!["a", "b", "c"]);
+
+        // The accept state is its own strongly connected component, as it loops on b and c
+        assert_eq!(petgraph::algo::kosaraju_scc(&graph).len(), 2);
+    }
 
-        // Test FA trait methods
-        assert_eq!(dfa.get_num_states(), 2);
-        assert_eq!(dfa.get_start_state(), s0);
-        assert!(dfa.get_alphabet().contains(&'a'));
+    #[test]
+    fn test_unused_alphabet() {
+        assert!(compile("a|bc*").unwrap().unused_alphabet().is_empty());
 
-        let accept_states: Vec<usize> = dfa.get_acceptor_states().iter_ones().collect();
-        assert_eq!(accept_states, vec![s1]);
+        // 'b' and 'c' only label transitions out of a branch which the start state cannot reach
+        let mut dfa = DFA::new();
+        let start = dfa.add_state();
+        let accept = dfa.add_state();
+        let dead = dfa.add_state();
+        dfa.start_state = start;
+        dfa.accept_states.set(accept, true);
+        dfa.alphabet = HashSet::from(['a', 'b', 'c']);
+        dfa.states[start]
+            .transitions
+            .insert(Symbol::Char('a'), accept);
+        dfa.states[dead]
+            .transitions
+            .insert(Symbol::Char('c'), accept);
+        dfa.states[dead].transitions.insert(Symbol::Char('b'), dead);
+
+        assert_eq!(dfa.unused_alphabet(), vec!['b', 'c']);
+        assert!(DFA::new().unused_alphabet().is_empty());
+    }
 
-        let transitions = dfa.get_state_transitions(s0);
-        assert_eq!(transitions.len(), 1);
-        assert_eq!(*transitions[0].0, Symbol::Char('a'));
-        assert_eq!(*transitions[0].1, s1);
+    #[test]
+    fn test_reverse_dfa() {
+        let dfa = build_dfa(&[("ab|cd", "PAIR")]);
+        let reversed_dfa = dfa.reverse_dfa();
+
+        assert!(reversed_dfa.is_deterministic());
+        assert!(reversed_dfa.accepts("ba"));
+        assert!(reversed_dfa.accepts("dc"));
+        for input in ["ab", "cd", "", "b", "bad", "bc"] {
+            assert!(!reversed_dfa.accepts(input), "{} should be rejected", input);
+        }
+        assert!(reversed_dfa.equivalent(&compile("ba|dc").unwrap()));
+
+        // Reversing twice gives back the original language
+        assert!(reversed_dfa.reverse_dfa().equivalent(&dfa));
+    }
+
+    // A rope-like source which keeps its text in separate chunks
+    struct ChunkedSource {
+        chunks: Vec<&'static str>,
+    }
+
+    impl CharSource for ChunkedSource {
+        fn char_at(&self, byte: usize) -> Option<(char, usize)> {
+            let mut chunk_start = 0;
+            for chunk in &self.chunks {
+                if byte < chunk_start + chunk.len() {
+                    return chunk.char_at(byte - chunk_start);
+                }
+                chunk_start += chunk.len();
+            }
+            None
+        }
+    }
+
+    #[test]
+    fn test_longest_match_char_source() {
+        let dfa = build_dfa(&[("[a-z]+", "WORD"), ("[0-9]+", "NUMBER"), (" ", "SPACE")]);
+
+        let chunks = vec!["ab", "c 1", "23", " x", "y"];
+        let source = ChunkedSource {
+            chunks: chunks.clone(),
+        };
+        let text = chunks.concat();
+
+        for start in 0..=text.len() {
+            assert_eq!(
+                dfa.longest_match(&source, start),
+                dfa.longest_match(text.as_str(), start),
+                "Different matches at {}",
+                start
+            );
+        }
+
+        assert_eq!(dfa.longest_match(&source, 0), Some(0..3));
+        assert_eq!(dfa.longest_match(&source, 4), Some(4..7));
+        assert_eq!(dfa.longest_match(&source, 8), Some(8..10));
+        assert_eq!(dfa.longest_match(&source, 10), None);
+    }
+
+    #[test]
+    fn test_minimize_with_mapping() {
+        let regex_list = vec![("ac|bc".to_string(), "WORD".to_string())];
+        let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+        let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+        let dfa = construct_dfa(&nfa, false).unwrap();
+
+        // Subset construction keeps separate states for the two branches after both the first and
+        // the second character
+        assert_eq!(dfa.get_states().len(), 5);
+
+        let (minimal_dfa, mapping) = minimize_with_mapping(&dfa);
+        assert_eq!(minimal_dfa.get_states().len(), 3);
+        assert_eq!(mapping.len(), 3);
+
+        // Every original state is subsumed by exactly one minimal state
+        let mut all_states: Vec<usize> = mapping.values().flatten().copied().collect();
+        all_states.sort();
+        assert_eq!(all_states, vec![0, 1, 2, 3, 4]);
+
+        for (prefix_one, prefix_two) in [("", ""), ("a", "b"), ("ac", "bc")] {
+            let minimal_state = walk(&minimal_dfa, prefix_one).unwrap();
+            let mut expected = vec![
+                walk(&dfa, prefix_one).unwrap(),
+                walk(&dfa, prefix_two).unwrap(),
+            ];
+            expected.sort();
+            expected.dedup();
+            assert_eq!(mapping[&minimal_state], expected);
+        }
+    }
+
+    #[test]
+    fn test_minimize_with_passes() {
+        let regex_list = vec![("(a|b)*abb".to_string(), "ABB".to_string())];
+        let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+        let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+        let dfa = construct_dfa(&nfa, false).unwrap();
+
+        let (minimal_dfa, passes) = minimize_with_passes(&dfa);
+        let (_, mapping) = minimize_with_mapping(&dfa);
+        assert_eq!(minimal_dfa.get_num_states(), mapping.len());
+        assert!(passes.len() > 1);
+
+        // The first pass splits the accepting states from the others
+        let accept_states: HashSet<usize> = dfa.get_acceptor_states().iter_ones().collect();
+        assert_eq!(passes[0].len(), 2);
+        assert!(passes[0].contains(&accept_states));
+
+        let all_states: HashSet<usize> = (0..dfa.get_num_states()).collect();
+        for pair in passes.windows(2) {
+            // Sets only ever split, so every set of a pass lies within a set of the pass before
+            assert!(pair[0].len() <= pair[1].len());
+            for set in pair[1].iter() {
+                assert!(pair[0].iter().any(|previous| set.is_subset(previous)));
+            }
+        }
+        for partition in passes.iter() {
+            let union: HashSet<usize> = partition.iter().flatten().copied().collect();
+            assert_eq!(union, all_states);
+        }
+
+        // The passes converge to the final partition
+        let final_partition: Vec<HashSet<usize>> = {
+            let mut sets: Vec<Vec<usize>> = mapping.values().cloned().collect();
+            sets.sort();
+            sets.into_iter()
+                .map(|set| set.into_iter().collect())
+                .collect()
+        };
+        assert_eq!(passes.last().unwrap(), &final_partition);
+        assert_ne!(passes[passes.len() - 2], final_partition);
+
+        let text = format_partition_passes(&passes);
+        assert_eq!(text.lines().count(), passes.len());
+        assert!(text.starts_with("Pass 1: {"));
+        assert_eq!(
+            format_partition_passes(&[vec![HashSet::from([3, 1]), HashSet::from([2])]]),
+            "Pass 1: {1, 3} {2}\n"
+        );
     }
 }
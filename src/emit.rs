@@ -0,0 +1,523 @@
+/* Emit table driven scanners as Rust or C source code from a DFA */
+
+use crate::dfa::{DFAError, DFA};
+use crate::fa::{raw_byte, Symbol, FA};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+// The tables shared by every emitted scanner. A cell of -1 in the transition table is the error
+// state and a category index of -1 marks a state which is not accepting.
+struct ScannerTables {
+    alphabet: Vec<char>,
+    transitions: Vec<Vec<i32>>,
+    accept_category: Vec<i32>,
+    category_names: Vec<String>,
+}
+
+impl ScannerTables {
+    fn new(dfa: &DFA) -> Self {
+        let mut alphabet: Vec<char> = dfa.get_alphabet().iter().cloned().collect();
+        alphabet.sort();
+
+        let category_names: Vec<String> = dfa
+            .get_acceptor_states()
+            .iter_ones()
+            .map(|state| dfa.get_state(state).get_category().clone())
+            .collect::<BTreeSet<String>>()
+            .into_iter()
+            .collect();
+
+        let mut transitions = Vec::new();
+        let mut accept_category = Vec::new();
+
+        for state in 0..dfa.get_num_states() {
+            let state_transitions = dfa.get_state(state).get_transitions();
+            let row = alphabet
+                .iter()
+                .map(|ch| match state_transitions.get(&Symbol::Char(*ch)) {
+                    Some(target) => *target as i32,
+                    None => -1,
+                })
+                .collect();
+            transitions.push(row);
+
+            // The category of a state was already resolved by priority during subset construction
+            let category = match dfa.category_of(state) {
+                Some(category) => category_names
+                    .iter()
+                    .position(|name| name == category)
+                    .unwrap() as i32,
+                None => -1,
+            };
+            accept_category.push(category);
+        }
+
+        ScannerTables {
+            alphabet,
+            transitions,
+            accept_category,
+            category_names,
+        }
+    }
+}
+
+fn join_row(row: &[i32]) -> String {
+    row.iter()
+        .map(|cell| cell.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// Quote a string as a C string literal, escaping anything which is not printable ASCII
+fn c_string_literal(value: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in value.bytes() {
+        match byte {
+            b'"' => literal.push_str("\\\""),
+            b'\\' => literal.push_str("\\\\"),
+            b' '..=b'~' => literal.push(byte as char),
+            _ => literal.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// Emit the DFA as a self contained Rust scanner. The generated source has a `scan` function which
+/// returns the length in bytes and the syntactic category of the longest token at the start of the
+/// input, along with the tables it is driven by: `TRANSITIONS`, `ACCEPT_CATEGORY` which maps every
+/// state to an index into `CATEGORY_NAMES` (or -1 if it is not accepting) and `CATEGORY_NAMES`.
+/// Returns an error if the DFA has anchors or word boundaries, which the emitted tables can not
+/// check.
+pub fn emit_rust(dfa: &DFA) -> Result<String, DFAError> {
+    if dfa.has_assertions() {
+        return Err(DFAError::UnsupportedAssertion);
+    }
+
+    let tables = ScannerTables::new(dfa);
+    let num_states = tables.transitions.len();
+    let num_columns = tables.alphabet.len();
+    let mut source = String::new();
+
+    writeln!(
+        source,
+        "// Generated by lexviz from the regular expression: {}",
+        dfa.get_regex().escape_debug()
+    )
+    .unwrap();
+    writeln!(source).unwrap();
+    writeln!(
+        source,
+        "pub const START_STATE: usize = {};",
+        dfa.get_start_state()
+    )
+    .unwrap();
+    writeln!(source).unwrap();
+
+    writeln!(
+        source,
+        "pub const TRANSITIONS: [[i32; {}]; {}] = [",
+        num_columns, num_states
+    )
+    .unwrap();
+    for row in tables.transitions.iter() {
+        writeln!(source, "    [{}],", join_row(row)).unwrap();
+    }
+    writeln!(source, "];").unwrap();
+    writeln!(source).unwrap();
+
+    writeln!(
+        source,
+        "pub const ACCEPT_CATEGORY: [i32; {}] = [{}];",
+        num_states,
+        join_row(&tables.accept_category)
+    )
+    .unwrap();
+    writeln!(source).unwrap();
+
+    let names: Vec<String> = tables
+        .category_names
+        .iter()
+        .map(|name| format!("{:?}", name))
+        .collect();
+    writeln!(
+        source,
+        "pub const CATEGORY_NAMES: [&str; {}] = [{}];",
+        names.len(),
+        names.join(", ")
+    )
+    .unwrap();
+    writeln!(source).unwrap();
+
+    writeln!(source, "fn column(ch: char) -> Option<usize> {{").unwrap();
+    writeln!(source, "    match ch {{").unwrap();
+    for (column, ch) in tables.alphabet.iter().enumerate() {
+        // The raw bytes of \xHH escapes are never matched by the characters of a string
+        if raw_byte(*ch).is_none() {
+            writeln!(source, "        {:?} => Some({}),", ch, column).unwrap();
+        }
+    }
+    writeln!(source, "        _ => None,").unwrap();
+    writeln!(source, "    }}").unwrap();
+    writeln!(source, "}}").unwrap();
+    writeln!(source).unwrap();
+
+    source.push_str(
+        "/// Returns the length in bytes and the category of the longest token at the start of input
+pub fn scan(input: &str) -> Option<(usize, &'static str)> {
+    let mut state = START_STATE;
+    let mut last_accept = None;
+
+    if ACCEPT_CATEGORY[state] >= 0 {
+        last_accept = Some((0, ACCEPT_CATEGORY[state]));
+    }
+
+    for (position, ch) in input.char_indices() {
+        let next_state = match column(ch) {
+            Some(column) => TRANSITIONS[state][column],
+            None => -1,
+        };
+        if next_state < 0 {
+            break;
+        }
+        state = next_state as usize;
+        if ACCEPT_CATEGORY[state] >= 0 {
+            last_accept = Some((position + ch.len_utf8(), ACCEPT_CATEGORY[state]));
+        }
+    }
+
+    last_accept.map(|(length, category)| (length, CATEGORY_NAMES[category as usize]))
+}
+",
+    );
+
+    Ok(source)
+}
+
+/// Emit the DFA as a self contained C scanner. The generated scanner works on bytes, matching ASCII
+/// characters by their byte and the raw bytes of \xHH escapes above \x7f by that byte, so the other
+/// characters of the alphabet can never be matched by it. `lexviz_scan` returns the length of the longest token at the start of
+/// the input or -1 and stores its index into `lexviz_category_names` through the category pointer.
+/// `lexviz_accept_category` maps every state to its category index or -1 if it is not accepting.
+/// Returns an error if the DFA has anchors or word boundaries, which the emitted tables can not
+/// check.
+pub fn emit_c(dfa: &DFA) -> Result<String, DFAError> {
+    if dfa.has_assertions() {
+        return Err(DFAError::UnsupportedAssertion);
+    }
+
+    let tables = ScannerTables::new(dfa);
+    let num_states = tables.transitions.len();
+    // C does not allow zero length arrays, so an empty alphabet still gets one unused column
+    let num_columns = tables.alphabet.len().max(1);
+    let mut source = String::new();
+
+    writeln!(
+        source,
+        "/* Generated by lexviz from the regular expression: {} */",
+        dfa.get_regex()
+            .escape_debug()
+            .to_string()
+            .replace("*/", "*\\/")
+    )
+    .unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "#include <stddef.h>").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "#define LEXVIZ_NUM_STATES {}", num_states).unwrap();
+    writeln!(source, "#define LEXVIZ_NUM_COLUMNS {}", num_columns).unwrap();
+    writeln!(
+        source,
+        "#define LEXVIZ_NUM_CATEGORIES {}",
+        tables.category_names.len()
+    )
+    .unwrap();
+    writeln!(
+        source,
+        "#define LEXVIZ_START_STATE {}",
+        dfa.get_start_state()
+    )
+    .unwrap();
+    writeln!(source).unwrap();
+
+    writeln!(
+        source,
+        "static const int lexviz_transitions[LEXVIZ_NUM_STATES][LEXVIZ_NUM_COLUMNS] = {{"
+    )
+    .unwrap();
+    for row in tables.transitions.iter() {
+        let row = if row.is_empty() {
+            vec![-1]
+        } else {
+            row.clone()
+        };
+        writeln!(source, "    {{{}}},", join_row(&row)).unwrap();
+    }
+    writeln!(source, "}};").unwrap();
+    writeln!(source).unwrap();
+
+    writeln!(
+        source,
+        "static const int lexviz_accept_category[LEXVIZ_NUM_STATES] = {{{}}};",
+        join_row(&tables.accept_category)
+    )
+    .unwrap();
+    writeln!(source).unwrap();
+
+    // The name table is terminated by NULL which also keeps it valid when there are no categories
+    let mut names: Vec<String> = tables
+        .category_names
+        .iter()
+        .map(|name| c_string_literal(name))
+        .collect();
+    names.push("NULL".to_string());
+    writeln!(
+        source,
+        "const char *const lexviz_category_names[] = {{{}}};",
+        names.join(", ")
+    )
+    .unwrap();
+    writeln!(source).unwrap();
+
+    writeln!(source, "static int lexviz_column(unsigned char c) {{").unwrap();
+    writeln!(source, "    switch (c) {{").unwrap();
+    for (column, ch) in tables.alphabet.iter().enumerate() {
+        let (byte, label) = match raw_byte(*ch) {
+            Some(byte) => (byte, format!("\\x{:X}", byte)),
+            None if ch.is_ascii() => (*ch as u8, ch.escape_debug().to_string()),
+            None => continue,
+        };
+        writeln!(
+            source,
+            "    case {}: return {}; /* {} */",
+            byte,
+            column,
+            label.replace("*/", "*\\/")
+        )
+        .unwrap();
+    }
+    writeln!(source, "    default: return -1;").unwrap();
+    writeln!(source, "    }}").unwrap();
+    writeln!(source, "}}").unwrap();
+    writeln!(source).unwrap();
+
+    source.push_str(
+        "/* Scan the longest token at the start of input. Returns its length in bytes, or -1 if no
+ * token matches, and stores its index into lexviz_category_names in *category. */
+long lexviz_scan(const char *input, size_t len, int *category) {
+    int state = LEXVIZ_START_STATE;
+    long last_accept = -1;
+    size_t position;
+
+    if (lexviz_accept_category[state] >= 0) {
+        last_accept = 0;
+        *category = lexviz_accept_category[state];
+    }
+
+    for (position = 0; position < len; position++) {
+        int column = lexviz_column((unsigned char)input[position]);
+        if (column < 0 || lexviz_transitions[state][column] < 0) {
+            break;
+        }
+        state = lexviz_transitions[state][column];
+        if (lexviz_accept_category[state] >= 0) {
+            last_accept = (long)position + 1;
+            *category = lexviz_accept_category[state];
+        }
+    }
+
+    return last_accept;
+}
+",
+    );
+
+    Ok(source)
+}
+
+#[cfg(test)]
+mod emit_tests {
+    use super::*;
+    use crate::dfa::{construct_dfa, construct_minimal_dfa};
+    use crate::nfa::construct_nfa;
+    use crate::regex::parse_microsyntax_list;
+
+    fn build_dfa(rules: &[(&str, &str)]) -> DFA {
+        let regex_list = rules
+            .iter()
+            .map(|(regex, category)| (regex.to_string(), category.to_string()))
+            .collect();
+        let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+        let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+        let dfa = construct_dfa(&nfa, false).unwrap();
+        construct_minimal_dfa(&dfa, false)
+    }
+
+    fn walk(dfa: &DFA, input: &str) -> usize {
+        let mut state = dfa.get_start_state();
+        for ch in input.chars() {
+            state = dfa.get_state(state).get_transitions()[&Symbol::Char(ch)];
+        }
+        state
+    }
+
+    // Find the line of the emitted source which declares the provided name
+    fn declaration<'a>(source: &'a str, name: &str) -> &'a str {
+        source
+            .lines()
+            .find(|line| line.contains(name))
+            .unwrap_or_else(|| panic!("{} is missing from the emitted source", name))
+    }
+
+    // Parse the comma separated integers between the last pair of braces or brackets of a line
+    fn parse_list(line: &str, open: char, close: char) -> Vec<i32> {
+        let start = line.rfind(open).unwrap() + 1;
+        let end = line.rfind(close).unwrap();
+        line[start..end]
+            .split(',')
+            .map(|cell| cell.trim().parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_emit_rust_accept_category() {
+        let dfa = build_dfa(&[("if|else", "KEYWORD"), ("[a-z]+", "IDENTIFIER")]);
+        let source = emit_rust(&dfa).unwrap();
+
+        assert!(
+            source.contains("pub const CATEGORY_NAMES: [&str; 2] = [\"IDENTIFIER\", \"KEYWORD\"];")
+        );
+
+        let accept_category = parse_list(declaration(&source, "ACCEPT_CATEGORY: ["), '[', ']');
+        assert_eq!(accept_category.len(), dfa.get_num_states());
+
+        assert_eq!(accept_category[walk(&dfa, "if")], 1);
+        assert_eq!(accept_category[walk(&dfa, "else")], 1);
+        assert_eq!(accept_category[walk(&dfa, "ifs")], 0);
+        assert_eq!(accept_category[dfa.get_start_state()], -1);
+    }
+
+    #[test]
+    fn test_emit_c_accept_category() {
+        let dfa = build_dfa(&[("if|else", "KEYWORD"), ("[a-z]+", "IDENTIFIER")]);
+        let source = emit_c(&dfa).unwrap();
+
+        assert!(source.contains(
+            "const char *const lexviz_category_names[] = {\"IDENTIFIER\", \"KEYWORD\", NULL};"
+        ));
+        assert!(source.contains("#define LEXVIZ_NUM_CATEGORIES 2"));
+
+        let accept_category = parse_list(
+            declaration(&source, "lexviz_accept_category[LEXVIZ_NUM_STATES] ="),
+            '{',
+            '}',
+        );
+        assert_eq!(accept_category.len(), dfa.get_num_states());
+
+        assert_eq!(accept_category[walk(&dfa, "else")], 1);
+        assert_eq!(accept_category[walk(&dfa, "el")], 0);
+    }
+
+    // Compile the emitted Rust scanner together with a main function which scans every command line
+    // argument, and return the path of the executable. None is returned if rustc can not be run.
+    fn compile_emitted_rust(source: &str, name: &str) -> Option<std::path::PathBuf> {
+        let directory =
+            std::env::temp_dir().join(format!("lexviz_round_trip_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let source_path = directory.join("scanner.rs");
+        let binary_path = directory.join("scanner");
+        let main = "
+fn main() {
+    for input in std::env::args().skip(1) {
+        match scan(&input) {
+            Some((length, category)) => println!(\"{} {}\", length, category),
+            None => println!(\"none\"),
+        }
+    }
+}
+";
+        std::fs::write(&source_path, format!("{}{}", source, main)).unwrap();
+
+        let rustc = std::env::var("RUSTC").unwrap_or("rustc".to_string());
+        let output = std::process::Command::new(rustc)
+            .args(["--edition", "2021", "-o"])
+            .arg(&binary_path)
+            .arg(&source_path)
+            .output()
+            .ok()?;
+        assert!(
+            output.status.success(),
+            "The emitted scanner does not compile: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        Some(binary_path)
+    }
+
+    // The longest match of the DFA at the start of the input, printed the same way as the emitted
+    // scanner prints it
+    fn expected_match(dfa: &DFA, input: &str) -> String {
+        match dfa.longest_match(input, 0) {
+            Some(range) => {
+                let state = walk(dfa, &input[range.clone()]);
+                format!("{} {}", range.end, dfa.category_of(state).unwrap())
+            }
+            None => "none".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_emit_rust_round_trip() {
+        let cases: [(&str, &[(&str, &str)], &[&str]); 3] = [
+            (
+                "keywords",
+                &[
+                    ("if|else", "KEYWORD"),
+                    ("[a-z]+", "IDENTIFIER"),
+                    ("[0-9]+", "NUMBER"),
+                ],
+                &["if", "ifs", "else(", "42abc", "x", "", "?", "elsewhere 1"],
+            ),
+            (
+                "closure",
+                &[("(ab|a)*c", "WORD")],
+                &["c", "abac", "aab", "ababc!", "abab", "b"],
+            ),
+            (
+                "unicode",
+                &[("é+", "ACCENT"), ("[a-zé]*z", "ENDS_IN_Z")],
+                &["ééé", "éz", "ééx", "aéz", "z", "日本"],
+            ),
+        ];
+
+        for (name, rules, inputs) in cases {
+            let dfa = build_dfa(rules);
+            let binary = match compile_emitted_rust(&emit_rust(&dfa).unwrap(), name) {
+                Some(binary) => binary,
+                None => {
+                    eprintln!("Skipping the round trip test, rustc is not available");
+                    return;
+                }
+            };
+
+            let output = std::process::Command::new(&binary)
+                .args(inputs.iter())
+                .output()
+                .unwrap();
+            let stdout = String::from_utf8(output.stdout).unwrap();
+            let results: Vec<&str> = stdout.lines().collect();
+
+            let expected: Vec<String> = inputs
+                .iter()
+                .map(|input| expected_match(&dfa, input))
+                .collect();
+            assert_eq!(
+                results, expected,
+                "The emitted scanner for {} disagrees",
+                name
+            );
+
+            std::fs::remove_dir_all(binary.parent().unwrap()).unwrap();
+        }
+    }
+}
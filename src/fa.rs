@@ -1,11 +1,86 @@
 use bitvec::prelude::BitVec;
 use std::collections::HashSet;
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub enum Symbol {
     Epsilon,
     Char(char),
 }
 
+/// The ^ and $ anchors and the \b word boundary consume no input, so the automata keep them as
+/// transitions on these private use characters. A matcher takes such a transition without
+/// consuming input when the characters around its position satisfy the anchor.
+pub const START_ANCHOR: char = '\u{E000}';
+pub const END_ANCHOR: char = '\u{E001}';
+pub const WORD_BOUNDARY: char = '\u{E002}';
+
+// The marker characters of the assertions. A character of the input is never matched against
+// them, even if it happens to be the same private use character.
+pub(crate) const ASSERTION_MARKERS: [char; 3] = [START_ANCHOR, END_ANCHOR, WORD_BOUNDARY];
+
+pub(crate) fn is_assertion_marker(ch: char) -> bool {
+    ASSERTION_MARKERS.contains(&ch)
+}
+
+// The raw bytes 0x80 to 0xFF are kept as transitions on the private use characters from
+// U+F0080 on, so they can never be confused with the characters of the same code point
+const RAW_BYTES: u32 = 0xF0000;
+
+/// Get the symbol the \xHH escape of the byte stands for. A byte up to 0x7F is the ASCII character
+/// with the same code point, as ASCII text is the same in bytes and in characters. A byte above it
+/// is a raw byte, which only a ByteDFA matches and a character of the input never does.
+pub fn byte_symbol(byte: u8) -> char {
+    if byte.is_ascii() {
+        return char::from(byte);
+    }
+    char::from_u32(RAW_BYTES + u32::from(byte)).unwrap()
+}
+
+/// Get the raw byte the symbol stands for, or None if it is a character
+pub fn raw_byte(symbol: char) -> Option<u8> {
+    let byte = u32::from(symbol).checked_sub(RAW_BYTES)?;
+    match u8::try_from(byte) {
+        Ok(byte) if !byte.is_ascii() => Some(byte),
+        _ => None,
+    }
+}
+
+/// The image formats Graphviz can render a finite automaton to. JPG is used when an automaton is
+/// saved during construction, SVG gives the crispest diagrams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FaImageFormat {
+    #[default]
+    Jpg,
+    Png,
+    Svg,
+    Pdf,
+}
+
+impl FaImageFormat {
+    /// Get the file extension of the format, which is also the name Graphviz uses for it
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FaImageFormat::Jpg => "jpg",
+            FaImageFormat::Png => "png",
+            FaImageFormat::Svg => "svg",
+            FaImageFormat::Pdf => "pdf",
+        }
+    }
+}
+
+// The arguments to the dot command which render the dot file into an image named after filename
+pub(crate) fn dot_command_args(
+    dot_filename: &str,
+    filename: &str,
+    format: FaImageFormat,
+) -> Vec<String> {
+    vec![
+        format!("-T{}", format.extension()),
+        dot_filename.to_string(),
+        "-o".to_string(),
+        format!("{}.{}", filename, format.extension()),
+    ]
+}
+
 pub trait FA {
     /// Get the number of states in the finite automata
     fn get_num_states(&self) -> usize;
@@ -19,3 +94,33 @@ pub trait FA {
     /// Get the list of all outgoing transitions for the given state
     fn get_state_transitions(&self, id: usize) -> Vec<(&Symbol, &usize)>;
 }
+
+#[cfg(test)]
+mod fa_tests {
+    use super::*;
+
+    #[test]
+    fn test_dot_command_args() {
+        let formats = [
+            (FaImageFormat::Jpg, "jpg"),
+            (FaImageFormat::Png, "png"),
+            (FaImageFormat::Svg, "svg"),
+            (FaImageFormat::Pdf, "pdf"),
+        ];
+
+        for (format, extension) in formats {
+            assert_eq!(format.extension(), extension);
+            assert_eq!(
+                dot_command_args("automaton.dot", "automaton", format),
+                vec![
+                    format!("-T{}", extension),
+                    "automaton.dot".to_string(),
+                    "-o".to_string(),
+                    format!("automaton.{}", extension),
+                ]
+            );
+        }
+
+        assert_eq!(FaImageFormat::default(), FaImageFormat::Jpg);
+    }
+}
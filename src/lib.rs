@@ -9,23 +9,42 @@
 //! - Convert NFAs to DFAs using Subset Construction
 //! - Minimize DFAs using Hopcroft's Algorithm
 //! - Scan and tokenize input based on the constructed automata
+//! - Report the spans matched by the groups of a regular expression
+//! - Emit table driven scanners as Rust or C source code
 //! - Visualize the automata state machine
 
 use std::{error, fmt};
 
 // Re-export the modules
+pub mod capture;
 pub mod dfa;
+pub mod emit;
 pub mod fa;
 pub mod nfa;
+pub mod prelude;
 pub mod regex;
 pub mod scanner;
 pub mod visualizer;
 
 // Re-export commonly used functions for convenience
-pub use dfa::{construct_dfa, construct_minimal_dfa};
-pub use nfa::construct_nfa;
-pub use regex::{parse_microsyntax_list, read_microsyntax_file};
-pub use scanner::construct_scanner;
+pub use capture::CaptureRegex;
+pub use dfa::{
+    analyze_conflicts, compile, compile_stages, compile_with_universe, construct_dfa,
+    construct_dfa_with_limit, construct_dfa_with_tie_break, construct_dfa_with_trace,
+    construct_keyword_dfa, construct_minimal_dfa, construct_minimal_dfa_preserving_categories,
+    format_partition_passes, longest_common_literal_prefix, minimize_with_mapping,
+    minimize_with_passes,
+};
+#[cfg(feature = "metrics")]
+pub use dfa::{construct_dfa_with_metrics, construct_minimal_dfa_with_metrics};
+pub use emit::{emit_c, emit_rust};
+pub use nfa::{construct_nfa, construct_nfa_with_universe, default_universe};
+pub use regex::{
+    escape_literal, expand_macros, number_literal_regex, parse_microsyntax_list,
+    read_alphabet_file, read_microsyntax_file, read_microsyntax_files, regex_to_dot,
+    strip_extended,
+};
+pub use scanner::{construct_conditional_scanner, construct_scanner};
 pub use visualizer::visualize;
 
 // List of all possible Lexer Error Codes
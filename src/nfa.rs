@@ -10,13 +10,18 @@ use std::hash::{Hash, Hasher};
 use std::io::Write;
 use std::process::Command;
 
-use crate::fa::{Symbol, FA};
-use crate::regex::{Base, Factor, Quantifier, RegEx, Term};
+use crate::fa::{
+    dot_command_args, raw_byte, FaImageFormat, Symbol, END_ANCHOR, FA, START_ANCHOR, WORD_BOUNDARY,
+};
+use crate::regex::{Base, Factor, Quantifier, RegEx, RuleFlags, Term};
 
 #[derive(Debug)]
 pub enum NFAError {
     InvalidEscapeCharError(char),
     InvalidIndexError,
+    EmptyCharacterSet,
+    UndefinedMacro(String),
+    NoMicrosyntaxes,
 }
 
 impl fmt::Display for NFAError {
@@ -26,6 +31,19 @@ impl fmt::Display for NFAError {
                 write!(f, "Error: Invalid escape character {} provided!", ch)
             }
             NFAError::InvalidIndexError => write!(f, "Error: Invalid index provided!"),
+            NFAError::EmptyCharacterSet => write!(
+                f,
+                "Error: Character set does not match any character of the universe!"
+            ),
+            NFAError::UndefinedMacro(name) => {
+                write!(f, "Error: The macro {{{}}} is not defined!", name)
+            }
+            NFAError::NoMicrosyntaxes => {
+                write!(
+                    f,
+                    "Error: No microsyntaxes were provided to build the NFA from!"
+                )
+            }
         }
     }
 }
@@ -37,6 +55,8 @@ pub struct NFAState {
     id: usize,
     transitions: HashMap<Symbol, HashSet<usize>>, // Store by reference is not a thing in Rust
     category: String,
+    pattern: String, // The regex of the microsyntax whose accept state this is
+    skip: bool,      // Whether the tokens of the microsyntax are skipped, see RuleFlags
 }
 
 #[derive(Debug)]
@@ -90,6 +110,8 @@ impl NFAState {
             id,
             transitions: HashMap::new(),
             category: String::new(),
+            pattern: String::new(),
+            skip: false,
         }
     }
     /// Get a list of all outgoing transitions for the given state
@@ -105,10 +127,20 @@ impl NFAState {
     pub fn get_category(&self) -> &String {
         &self.category
     }
+    /// Get the regular expression of the microsyntax that this state accepts, if it is an accept
+    /// state of a microsyntax. Otherwise, it returns an empty string.
+    pub fn get_pattern(&self) -> &String {
+        &self.pattern
+    }
+    /// Returns true if this state accepts a microsyntax with the s flag, whose tokens are skipped
+    /// while scanning
+    pub fn is_skipped(&self) -> bool {
+        self.skip
+    }
 }
 
 impl NFA {
-    fn add_state(&mut self) -> usize {
+    pub(crate) fn add_state(&mut self) -> usize {
         let state_id = self.states.len();
         let new_state: NFAState = NFAState::new(state_id);
         self.states.push(new_state);
@@ -116,7 +148,152 @@ impl NFA {
         return state_id;
     }
 
-    fn show_fa(&self, filename: &str) {
+    /// Merge every strongly connected component of epsilon edges into a single state. The states
+    /// of such a cycle reach each other without consuming any input, so they are interchangeable
+    /// and the language of the NFA is preserved. Nested closures like (a*)* create these cycles,
+    /// which otherwise bloat the NFA and slow down the epsilon closures of subset construction.
+    pub fn remove_epsilon_cycles(&mut self) {
+        let component = self.epsilon_components();
+        let num_components = component.iter().max().map_or(0, |max| max + 1);
+
+        let mut states: Vec<NFAState> = (0..num_components).map(NFAState::new).collect();
+        let mut accept_states: BitVec<u8> = BitVec::repeat(false, num_components);
+
+        // States are visited in order, so the category of the lowest accept state in a component
+        // wins as it would have during subset construction
+        for state in &self.states {
+            let from = component[state.id];
+
+            for (symbol, targets) in &state.transitions {
+                for target in targets {
+                    let to = component[*target];
+                    if *symbol == Symbol::Epsilon && from == to {
+                        continue; // The cycle collapses into the state itself
+                    }
+                    states[from]
+                        .transitions
+                        .entry(symbol.clone())
+                        .or_insert_with(HashSet::new)
+                        .insert(to);
+                }
+            }
+
+            if self.accept_states[state.id] {
+                accept_states.set(from, true);
+                if states[from].category.is_empty() {
+                    states[from].category = state.category.clone();
+                    states[from].pattern = state.pattern.clone();
+                    states[from].skip = state.skip;
+                }
+            }
+        }
+
+        self.start_state = component[self.start_state];
+        self.states = states;
+        self.accept_states = accept_states;
+    }
+
+    // Label every state with its strongly connected component over epsilon edges using Tarjan's
+    // algorithm. The components are numbered in the order of their lowest state, so merging them
+    // keeps the relative order of the states, which decides the priority of the categories.
+    fn epsilon_components(&self) -> Vec<usize> {
+        let num_states = self.states.len();
+        let successors: Vec<Vec<usize>> = self
+            .states
+            .iter()
+            .map(|state| match state.transitions.get(&Symbol::Epsilon) {
+                Some(targets) => targets.iter().copied().collect(),
+                None => Vec::new(),
+            })
+            .collect();
+
+        let mut index: Vec<Option<usize>> = vec![None; num_states];
+        let mut low_link: Vec<usize> = vec![0; num_states];
+        let mut on_stack: Vec<bool> = vec![false; num_states];
+        let mut stack: Vec<usize> = Vec::new();
+        let mut tarjan_component: Vec<usize> = vec![0; num_states];
+        let mut next_index = 0;
+        let mut num_components = 0;
+
+        for root in 0..num_states {
+            if index[root].is_some() {
+                continue;
+            }
+
+            // The recursion is kept on an explicit stack of (state, next successor to visit)
+            let mut call_stack: Vec<(usize, usize)> = vec![(root, 0)];
+            index[root] = Some(next_index);
+            low_link[root] = next_index;
+            next_index += 1;
+            stack.push(root);
+            on_stack[root] = true;
+
+            while let Some((state, position)) = call_stack.pop() {
+                if let Some(&next) = successors[state].get(position) {
+                    call_stack.push((state, position + 1));
+                    match index[next] {
+                        None => {
+                            index[next] = Some(next_index);
+                            low_link[next] = next_index;
+                            next_index += 1;
+                            stack.push(next);
+                            on_stack[next] = true;
+                            call_stack.push((next, 0));
+                        }
+                        Some(order) if on_stack[next] => {
+                            low_link[state] = low_link[state].min(order);
+                        }
+                        Some(_) => {}
+                    }
+                    continue;
+                }
+
+                if let Some((parent, _)) = call_stack.last() {
+                    low_link[*parent] = low_link[*parent].min(low_link[state]);
+                }
+
+                if Some(low_link[state]) == index[state] {
+                    loop {
+                        let member = stack.pop().unwrap();
+                        on_stack[member] = false;
+                        tarjan_component[member] = num_components;
+                        if member == state {
+                            break;
+                        }
+                    }
+                    num_components += 1;
+                }
+            }
+        }
+
+        // Renumber the components in the order of their lowest state
+        let mut renumbered: Vec<Option<usize>> = vec![None; num_components];
+        let mut next_component = 0;
+
+        tarjan_component
+            .iter()
+            .map(|component| {
+                *renumbered[*component].get_or_insert_with(|| {
+                    next_component += 1;
+                    next_component - 1
+                })
+            })
+            .collect()
+    }
+
+    /// Get the number of epsilon edges in the NFA, counting one edge per target state
+    pub fn get_num_epsilon_edges(&self) -> usize {
+        self.states
+            .iter()
+            .filter_map(|state| state.transitions.get(&Symbol::Epsilon))
+            .map(|targets| targets.len())
+            .sum()
+    }
+
+    /// Write the NFA in the graphviz DOT format, with epsilon edges labelled as ε. This is the
+    /// automaton before determinization, which is saved as a jpg when the NFA is constructed with
+    /// save_nfa set to true.
+    pub fn write_dot<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
         let mut graph = DiGraph::new();
         let mut node_map = std::collections::HashMap::new();
 
@@ -131,8 +308,14 @@ impl NFA {
             for (symbol, targets) in &state.transitions {
                 for target in targets {
                     let symbol_str = match symbol {
-                        Symbol::Char(c) => c.to_string(),
-                        Symbol::Epsilon => "𝛆".to_string(),
+                        Symbol::Char(START_ANCHOR) => "^".to_string(),
+                        Symbol::Char(END_ANCHOR) => "$".to_string(),
+                        Symbol::Char(WORD_BOUNDARY) => "\\b".to_string(),
+                        Symbol::Char(c) => match raw_byte(*c) {
+                            Some(byte) => format!("\\x{:X}", byte),
+                            None => c.to_string(),
+                        },
+                        Symbol::Epsilon => "ε".to_string(),
                     };
                     graph.add_edge(node_map[&state.id], node_map[&target], symbol_str);
                 }
@@ -153,23 +336,36 @@ impl NFA {
 
         let dot = Dot::new(&graph);
 
+        writer.write_all(dot.to_string().as_bytes())
+    }
+
+    fn show_fa(&self, filename: &str) {
+        self.show_fa_with_format(filename, FaImageFormat::Jpg);
+    }
+
+    /// Save the NFA as an image named after filename, with the extension of the chosen format. The
+    /// DOT file Graphviz renders the image from is kept next to it.
+    pub fn show_fa_with_format(&self, filename: &str, format: FaImageFormat) {
         // Write dot to file
         let dot_filename = format!("{}.dot", filename);
         let mut dot_file = File::create(&dot_filename).expect("Failed to create dot file");
 
-        dot_file
-            .write_all(dot.to_string().as_bytes())
+        self.write_dot(&mut dot_file)
             .expect("Failed to write dot file");
 
         Command::new("dot")
-            .args(&["-Tjpg", &dot_filename, "-o", &format!("{}.jpg", filename)])
+            .args(dot_command_args(&dot_filename, filename, format))
             .output()
             .expect("Failed to execute Graphviz");
 
-        println!("NFA vizualization saved as {filename}.jpg");
+        println!(
+            "NFA vizualization saved as {}.{}",
+            filename,
+            format.extension()
+        );
     }
 
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         NFA {
             states: Vec::new(),
             start_state: 0,
@@ -412,26 +608,29 @@ impl NFA {
         return result;
     }
 
+    // An NFA matching only the empty string, used for the empty branch of an alternation
+    fn empty_construction() -> NFA {
+        let mut result: NFA = NFA::new();
+        let start_state = result.add_state();
+        let end_state = result.add_state();
+
+        result.states[start_state]
+            .transitions
+            .entry(Symbol::Epsilon)
+            .or_default()
+            .insert(end_state);
+
+        result.start_state = start_state;
+        result.accept_states.set(end_state, true);
+        return result;
+    }
+
     fn escape_literal_construction(character: char) -> Result<NFA, NFAError> {
         let mut result: NFA = NFA::new();
         let start_state = result.add_state();
         let end_state = result.add_state();
 
-        let escape_character = match character {
-            'n' => '\n',
-            't' => '\t',
-            'r' => '\r',
-            '\\' => '\\',
-            '(' => '(',
-            ')' => ')',
-            '[' => '[',
-            ']' => ']',
-            '|' => '|',
-            '*' => '*',
-            '+' => '+',
-            '?' => '?',
-            _ => return Err(NFAError::InvalidEscapeCharError(character)),
-        };
+        let escape_character = unescape_char(character)?;
 
         result.alphabet.insert(escape_character);
         result.states[start_state]
@@ -445,6 +644,19 @@ impl NFA {
 
         return Ok(result);
     }
+    // Build an NFA accepting any single character of the set
+    fn char_set_construction(char_set: &HashSet<char>) -> Result<NFA, NFAError> {
+        let mut chars = char_set.iter();
+        let mut result = match chars.next() {
+            Some(ch) => NFA::literal_construction(*ch),
+            None => return Err(NFAError::EmptyCharacterSet),
+        };
+        for ch in chars {
+            let char_nfa = NFA::literal_construction(*ch);
+            result = NFA::alternation(char_nfa, result);
+        }
+        Ok(result)
+    }
     /// Get the state for the provided id
     pub fn get_state(&self, id: usize) -> Result<&NFAState, NFAError> {
         let state = self.states.get(id);
@@ -469,13 +681,85 @@ impl NFA {
         }
         Ok(())
     }
+    fn set_accept_pattern(&mut self, pattern: &str) {
+        for state in self.accept_states.iter_ones() {
+            if self.states[state].pattern.is_empty() {
+                self.states[state].pattern = pattern.to_string();
+            }
+        }
+    }
+    fn set_accept_skip(&mut self, skip: bool) {
+        for state in self.accept_states.iter_ones() {
+            self.states[state].skip = skip;
+        }
+    }
     /// Get the regular expression that the NFA models
     pub fn get_regex(&self) -> &String {
         return &self.regex;
     }
+
+    pub(crate) fn add_transition(&mut self, from: usize, symbol: Symbol, to: usize) {
+        if let Symbol::Char(ch) = symbol {
+            self.alphabet.insert(ch);
+        }
+        self.states[from]
+            .transitions
+            .entry(symbol)
+            .or_default()
+            .insert(to);
+    }
+
+    pub(crate) fn set_start_state(&mut self, state: usize) {
+        self.start_state = state;
+    }
+
+    pub(crate) fn set_accept_state(&mut self, state: usize, category: &str) {
+        self.accept_states.set(state, true);
+        self.states[state].category = category.to_string();
+    }
+
+    pub(crate) fn set_regex(&mut self, regex: &str) {
+        self.regex = regex.to_string();
+    }
 }
 
-fn parse_base_tree(tree: Base) -> Result<NFA> {
+// Get the character matched by an escape sequence, the escaped character itself for a
+// metacharacter
+pub(crate) fn unescape_char(character: char) -> Result<char, NFAError> {
+    let escape_character = match character {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '\\' => '\\',
+        '(' => '(',
+        ')' => ')',
+        '[' => '[',
+        ']' => ']',
+        '|' => '|',
+        '*' => '*',
+        '+' => '+',
+        '?' => '?',
+        '.' => '.',
+        '^' => '^',
+        '$' => '$',
+        '{' => '{',
+        '}' => '}',
+        _ => return Err(NFAError::InvalidEscapeCharError(character)),
+    };
+    Ok(escape_character)
+}
+
+fn parse_char_set_tree(char_set: &HashSet<char>) -> Result<NFA> {
+    match NFA::char_set_construction(char_set) {
+        Ok(result) => Ok(result),
+        Err(err) => {
+            let err = Report::new(err);
+            return Err(err);
+        }
+    }
+}
+
+fn parse_base_tree(tree: Base, universe: &HashSet<char>) -> Result<NFA> {
     match tree {
         Base::Character(character) => Ok(NFA::literal_construction(character)),
         Base::EscapeCharacter(character) => match NFA::escape_literal_construction(character) {
@@ -487,24 +771,37 @@ fn parse_base_tree(tree: Base) -> Result<NFA> {
         },
         Base::Exp(regex) => {
             let regex = *regex;
-            parse_regex_tree(regex)
-        }
-        Base::CharSet(char_set) => {
-            let next_char = char_set.iter().next().unwrap();
-            let mut result = NFA::literal_construction(*next_char);
-            for char in char_set {
-                let char_nfa = NFA::literal_construction(char);
-                result = NFA::alternation(char_nfa, result);
-            }
-            Ok(result)
+            parse_regex_tree(regex, universe)
+        }
+        Base::CharSet(char_set) => parse_char_set_tree(&char_set),
+        // Negation and the any character are resolved against the universe so the alphabet of the
+        // automaton stays finite
+        Base::NegatedCharSet(char_set) => {
+            let char_set = universe.difference(&char_set).cloned().collect();
+            parse_char_set_tree(&char_set)
+        }
+        // Following the usual convention, '.' matches anything except a newline
+        Base::AnyChar => {
+            let char_set = universe.iter().filter(|ch| **ch != '\n').cloned().collect();
+            parse_char_set_tree(&char_set)
+        }
+        Base::StartAnchor => Ok(NFA::literal_construction(START_ANCHOR)),
+        Base::WordBoundary => Ok(NFA::literal_construction(WORD_BOUNDARY)),
+        Base::Empty => Ok(NFA::empty_construction()),
+        Base::EndAnchor => Ok(NFA::literal_construction(END_ANCHOR)),
+        // Macro references are expanded while reading the microsyntaxes, so one reaching the NFA
+        // was never defined
+        Base::NamedClass(name) => {
+            let err = Report::new(NFAError::UndefinedMacro(name));
+            return Err(err);
         }
     }
 }
 
-fn parse_factor_tree(tree: Factor) -> Result<NFA> {
+fn parse_factor_tree(tree: Factor, universe: &HashSet<char>) -> Result<NFA> {
     match tree {
         Factor::SimpleFactor(base, quantifier) => {
-            let nfa = parse_base_tree(base)?;
+            let nfa = parse_base_tree(base, universe)?;
             match quantifier {
                 None => Ok(nfa),
                 Some(quantifier) => Ok(NFA::closure(nfa, quantifier)),
@@ -513,48 +810,76 @@ fn parse_factor_tree(tree: Factor) -> Result<NFA> {
     }
 }
 
-fn parse_term_tree(tree: Term) -> Result<NFA> {
+fn parse_term_tree(tree: Term, universe: &HashSet<char>) -> Result<NFA> {
     match tree {
-        Term::SimpleTerm(factor) => parse_factor_tree(factor),
+        Term::SimpleTerm(factor) => parse_factor_tree(factor, universe),
         Term::ConcatTerm(rfactor, lterm) => {
             let lterm = *lterm;
-            let nfa1 = parse_term_tree(lterm)?;
-            let nfa2 = parse_factor_tree(rfactor)?;
+            let nfa1 = parse_term_tree(lterm, universe)?;
+            let nfa2 = parse_factor_tree(rfactor, universe)?;
             Ok(NFA::concatenate(nfa1, nfa2))
         }
     }
 }
 
-fn parse_regex_tree(tree: RegEx) -> Result<NFA> {
+fn parse_regex_tree(tree: RegEx, universe: &HashSet<char>) -> Result<NFA> {
     match tree {
-        RegEx::SimpleRegex(term) => parse_term_tree(term),
+        RegEx::SimpleRegex(term) => parse_term_tree(term, universe),
         RegEx::AlterRegex(lterm, rregex) => {
             let rregex = *rregex; // Unboxing the value
-            let nfa1 = parse_term_tree(lterm)?;
-            let nfa2 = parse_regex_tree(rregex)?;
+            let nfa1 = parse_term_tree(lterm, universe)?;
+            let nfa2 = parse_regex_tree(rregex, universe)?;
             Ok(NFA::alternation(nfa1, nfa2))
         }
     }
 }
 
+/// The universe of characters used by default to resolve negated character sets and the any
+/// character '.', which is printable ASCII.
+pub fn default_universe() -> HashSet<char> {
+    (' '..='~').collect()
+}
+
 /// Apply Thomson construction algorithm to build an NFA for a given regular expression syntax
-/// tree. If save_nfa is set to true, the constructed NFA is saved as a jpg.
+/// tree. If save_nfa is set to true, the constructed NFA is saved as a jpg. Negated character sets
+/// and '.' are resolved against the default universe of printable ASCII characters. The accept
+/// states of the microsyntaxes with the s flag are marked as skipped.
 pub fn construct_nfa(
-    mut syntax_tree_list: VecDeque<(String, RegEx, String)>,
+    syntax_tree_list: VecDeque<(String, RegEx, String, RuleFlags)>,
+    save_nfa: bool,
+) -> Result<NFA> {
+    construct_nfa_with_universe(syntax_tree_list, &default_universe(), save_nfa)
+}
+
+/// Apply Thomson construction algorithm like construct_nfa, resolving negated character sets and
+/// '.' against the provided universe of characters instead of the default one.
+pub fn construct_nfa_with_universe(
+    mut syntax_tree_list: VecDeque<(String, RegEx, String, RuleFlags)>,
+    universe: &HashSet<char>,
     save_nfa: bool,
 ) -> Result<NFA> {
-    let (regex, syntax_tree, category) = syntax_tree_list.pop_front().unwrap();
+    let (regex, syntax_tree, category, flags) = match syntax_tree_list.pop_front() {
+        Some(entry) => entry,
+        None => {
+            let err = Report::new(NFAError::NoMicrosyntaxes);
+            return Err(err);
+        }
+    };
 
-    let mut result = parse_regex_tree(syntax_tree)?;
+    let mut result = parse_regex_tree(syntax_tree, universe)?;
     result.regex = regex.to_string();
 
     result.set_accept_category(category).unwrap();
+    result.set_accept_pattern(&regex);
+    result.set_accept_skip(flags.skip);
 
     while !syntax_tree_list.is_empty() {
-        let (regex, syntax_tree, category) = syntax_tree_list.pop_front().unwrap();
-        let mut nfa = parse_regex_tree(syntax_tree)?;
+        let (regex, syntax_tree, category, flags) = syntax_tree_list.pop_front().unwrap();
+        let mut nfa = parse_regex_tree(syntax_tree, universe)?;
         nfa.regex = regex.to_string();
         nfa.set_accept_category(category).unwrap();
+        nfa.set_accept_pattern(&regex);
+        nfa.set_accept_skip(flags.skip);
         let old_regex = result.regex.clone();
         result = NFA::alternation(result, nfa);
         let new_regex = format!("{old_regex}|{regex}");
@@ -571,6 +896,8 @@ pub fn construct_nfa(
 #[cfg(test)]
 mod nfa_tests {
     use super::*;
+    use crate::dfa::construct_dfa;
+    use crate::regex::{parse_microsyntax_list, parse_with_options, ParseOptions};
 
     #[test]
     fn test_nfa_state_creation() {
@@ -885,4 +1212,101 @@ mod nfa_tests {
         let accept_count = nfa_final.get_acceptor_states().count_ones();
         assert_eq!(accept_count, 1);
     }
+
+    #[test]
+    fn test_write_dot() {
+        let nfa1 = NFA::literal_construction('a');
+        let nfa2 = NFA::literal_construction('b');
+        let nfa = NFA::alternation(nfa1, nfa2);
+
+        assert_eq!(nfa.get_num_states(), 6);
+        assert_eq!(nfa.get_num_epsilon_edges(), 4);
+
+        let mut dot = Vec::new();
+        nfa.write_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+
+        assert_eq!(dot.matches("label = \"ε\"").count(), 4);
+        assert!(dot.contains("0 -> 1 [ label = \"ε\" ]"));
+        assert!(dot.contains("0 -> 3 [ label = \"ε\" ]"));
+        assert!(dot.contains("2 -> 5 [ label = \"ε\" ]"));
+        assert!(dot.contains("4 -> 5 [ label = \"ε\" ]"));
+        assert!(dot.contains("1 -> 2 [ label = \"a\" ]"));
+        assert!(dot.contains("3 -> 4 [ label = \"b\" ]"));
+    }
+
+    #[test]
+    fn test_remove_epsilon_cycles() {
+        let syntax_tree_list =
+            parse_microsyntax_list(vec![("(a*)*".to_string(), "A".to_string())]).unwrap();
+        let mut nfa = construct_nfa(syntax_tree_list, false).unwrap();
+        let num_states = nfa.get_num_states();
+
+        nfa.remove_epsilon_cycles();
+        assert!(nfa.get_num_states() < num_states);
+        // Every state is now its own component
+        let components = nfa.epsilon_components();
+        assert_eq!(
+            components,
+            (0..nfa.get_num_states()).collect::<Vec<usize>>()
+        );
+
+        let dfa = construct_dfa(&nfa, false).unwrap();
+        assert!(dfa.accepts(""));
+        assert!(dfa.accepts("a"));
+        assert!(dfa.accepts("aaaa"));
+        assert!(!dfa.accepts("b"));
+        let accept_state = dfa.get_acceptor_states().first_one().unwrap();
+        assert_eq!(dfa.category_of(accept_state), Some("A"));
+    }
+
+    #[test]
+    fn test_question_on_compound_bases() {
+        let build = |regex: &str| {
+            let syntax_tree_list =
+                parse_microsyntax_list(vec![(regex.to_string(), "OPT".to_string())]).unwrap();
+            let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+            construct_dfa(&nfa, false).unwrap()
+        };
+
+        let set_dfa = build("[abc]?");
+        for input in ["", "a", "b", "c"] {
+            assert!(set_dfa.accepts(input), "{:?} should be accepted", input);
+        }
+        for input in ["d", "ab", "aa"] {
+            assert!(!set_dfa.accepts(input), "{:?} should be rejected", input);
+        }
+
+        let group_dfa = build("(ab)?");
+        assert!(group_dfa.accepts(""));
+        assert!(group_dfa.accepts("ab"));
+        for input in ["a", "b", "abab", "ba"] {
+            assert!(!group_dfa.accepts(input), "{:?} should be rejected", input);
+        }
+    }
+
+    #[test]
+    fn test_empty_alternation_branch() {
+        let allow_empty = ParseOptions {
+            allow_empty_alternation: true,
+            ..ParseOptions::default()
+        };
+
+        for (regex, suffix) in [("a|", ""), ("(a|)b", "b")] {
+            let (syntax_tree, _) = parse_with_options(regex, &allow_empty).unwrap();
+            let syntax_tree_list = VecDeque::from([(
+                regex.to_string(),
+                syntax_tree,
+                "OPT".to_string(),
+                RuleFlags::default(),
+            )]);
+            let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+            let dfa = construct_dfa(&nfa, false).unwrap();
+
+            // The empty branch lets the group be skipped
+            assert!(dfa.accepts(suffix), "{} should accept {:?}", regex, suffix);
+            assert!(dfa.accepts(&format!("a{}", suffix)));
+            assert!(!dfa.accepts(&format!("aa{}", suffix)));
+        }
+    }
 }
@@ -0,0 +1,14 @@
+//! The types and functions needed to build and run a scanner, gathered in one place so a
+//! downstream crate can bring them into scope with a single glob import,
+//! `use lexviz::prelude::*;`. Regexes are parsed with `parse_microsyntax_list` and scanning errors
+//! are reported as `ScannerError`.
+
+pub use crate::dfa::{
+    compile, construct_dfa, construct_minimal_dfa, DFAError, ScannerBuilder, DFA,
+};
+pub use crate::fa::FA;
+pub use crate::nfa::{construct_nfa, NFAError, NFA};
+pub use crate::regex::{
+    parse_microsyntax_list, read_microsyntax_file, read_microsyntax_files, RegEx, RegExError,
+};
+pub use crate::scanner::{construct_scanner, Scanner, ScannerError, Token};
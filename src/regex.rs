@@ -1,61 +1,430 @@
 /* Good resource for parsing regex at
  * https://matt.might.net/articles/parsing-regex-with-recursive-descent/ */
 
+use crate::fa::{byte_symbol, raw_byte};
 use color_eyre::eyre::{Report, Result};
-use std::collections::HashSet;
+use petgraph::dot::{Config, Dot};
+use petgraph::graph::{DiGraph, NodeIndex};
 use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
+use std::hash::{Hash, Hasher};
 use std::io::{BufRead, BufReader};
 use std::path::PathBuf;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Quantifier {
     Star,
     Question,
     Plus,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Base {
     Character(char),
     EscapeCharacter(char),
     Exp(Box<RegEx>),
     CharSet(HashSet<char>),
+    NegatedCharSet(HashSet<char>),
+    AnyChar,
+    StartAnchor,
+    EndAnchor,
+    WordBoundary,
+    NamedClass(String),
+    Empty,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Factor {
     SimpleFactor(Base, Option<Quantifier>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Term {
     SimpleTerm(Factor),
     ConcatTerm(Factor, Box<Term>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum RegEx {
     SimpleRegex(Term),
     AlterRegex(Term, Box<RegEx>),
 }
 
+// HashSet does not implement Hash, so character sets are hashed as their sorted list of members
+impl Hash for Base {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Base::Character(ch) | Base::EscapeCharacter(ch) => ch.hash(state),
+            Base::Exp(regex) => regex.hash(state),
+            Base::NamedClass(name) => name.hash(state),
+            Base::CharSet(char_set) | Base::NegatedCharSet(char_set) => {
+                let mut chars: Vec<&char> = char_set.iter().collect();
+                chars.sort();
+                chars.hash(state);
+            }
+            Base::AnyChar
+            | Base::StartAnchor
+            | Base::EndAnchor
+            | Base::WordBoundary
+            | Base::Empty => {}
+        }
+    }
+}
+
+impl RegEx {
+    /// Render the syntax tree as an S-expression such as (cat (star (alt (char a) (char b)))
+    /// (char c)) for (a|b)*c. The right nested chains of alternatives and concatenations the
+    /// parser builds are flattened and listed in the order they appear in the regex. A group is
+    /// rendered as the expression inside it and is not flattened into the expression around it, so
+    /// (a|b)|c is (alt (alt (char a) (char b)) (char c)). The output is stable and easy to compare
+    /// in tests.
+    pub fn to_sexp(&self) -> String {
+        let mut alternatives = Vec::new();
+        let mut regex = self;
+
+        loop {
+            match regex {
+                RegEx::SimpleRegex(term) => {
+                    alternatives.push(term_sexp(term));
+                    break;
+                }
+                RegEx::AlterRegex(term, rest) => {
+                    alternatives.push(term_sexp(term));
+                    regex = rest;
+                }
+            }
+        }
+
+        if alternatives.len() == 1 {
+            return alternatives.pop().unwrap();
+        }
+        format!("(alt {})", alternatives.join(" "))
+    }
+
+    /// Render the syntax tree as a pattern for the regex crate, so the microsyntax files of this
+    /// crate can be kept while the matching is done elsewhere. Characters which are special to the
+    /// regex crate are escaped, and the escapes of this crate become the character they stand for.
+    /// Groups stay capturing groups so they are numbered the same way as in CaptureRegex.
+    ///
+    /// Where the semantics differ, the translation follows the regex crate:
+    /// - Negated character sets and '.' match any Unicode character rather than only the
+    ///   characters of the universe. '.' still does not match a newline.
+    /// - The regex crate prefers the leftmost alternative rather than the longest match, so a|ab
+    ///   matches only "a" of "ab" unless the pattern is anchored at both ends.
+    /// - \b is restricted to ASCII word characters, like the word boundary of this crate.
+    /// - A macro reference which was never expanded is written as the literal text {NAME}.
+    /// - A raw byte above \x7f is written in a (?-u:...) group, which only the regex::bytes API
+    ///   accepts.
+    pub fn to_std_regex_string(&self) -> String {
+        match self {
+            RegEx::SimpleRegex(term) => term_std_regex(term),
+            RegEx::AlterRegex(term, rest) => {
+                format!("{}|{}", term_std_regex(term), rest.to_std_regex_string())
+            }
+        }
+    }
+}
+
+// Concatenations are built with the last factor outermost, so the factors are collected in
+// reverse
+fn term_sexp(term: &Term) -> String {
+    let mut factors = Vec::new();
+    let mut term = term;
+
+    loop {
+        match term {
+            Term::SimpleTerm(factor) => {
+                factors.push(factor_sexp(factor));
+                break;
+            }
+            Term::ConcatTerm(factor, rest) => {
+                factors.push(factor_sexp(factor));
+                term = rest;
+            }
+        }
+    }
+
+    factors.reverse();
+
+    if factors.len() == 1 {
+        return factors.pop().unwrap();
+    }
+    format!("(cat {})", factors.join(" "))
+}
+
+fn factor_sexp(factor: &Factor) -> String {
+    let Factor::SimpleFactor(base, quantifier) = factor;
+    let base = base_sexp(base);
+
+    match quantifier {
+        None => base,
+        Some(Quantifier::Star) => format!("(star {})", base),
+        Some(Quantifier::Plus) => format!("(plus {})", base),
+        Some(Quantifier::Question) => format!("(opt {})", base),
+    }
+}
+
+fn base_sexp(base: &Base) -> String {
+    match base {
+        Base::Character(ch) => format!("(char {})", sexp_char(*ch)),
+        Base::EscapeCharacter(ch) => format!("(esc {})", sexp_char(*ch)),
+        Base::Exp(regex) => regex.to_sexp(),
+        Base::CharSet(char_set) | Base::NegatedCharSet(char_set) => {
+            let mut chars: Vec<&char> = char_set.iter().collect();
+            chars.sort();
+            let name = match base {
+                Base::CharSet(_) => "set",
+                _ => "not-set",
+            };
+            let mut parts = vec![name.to_string()];
+            parts.extend(chars.into_iter().map(|ch| sexp_char(*ch)));
+            format!("({})", parts.join(" "))
+        }
+        Base::AnyChar => "(any)".to_string(),
+        Base::StartAnchor => "(start)".to_string(),
+        Base::EndAnchor => "(end)".to_string(),
+        Base::WordBoundary => "(boundary)".to_string(),
+        Base::NamedClass(name) => format!("(macro {})", name),
+        Base::Empty => "(empty)".to_string(),
+    }
+}
+
+/// Render the syntax tree as a Graphviz DOT digraph, to see how the precedence of the operators
+/// shaped it. Unlike to_sexp, nothing is flattened: every alternation and concatenation is a binary
+/// alt or cat node, with the left operand drawn first. Quantifiers are star, plus and opt nodes
+/// above their base, parentheses are group nodes and the bases are leaves. The root is node 0.
+pub fn regex_to_dot(regex: &RegEx) -> String {
+    let mut graph = DiGraph::new();
+    regex_node(regex, &mut graph);
+
+    Dot::with_config(&graph, &[Config::EdgeNoLabel]).to_string()
+}
+
+// Add an edge from the node to each of its children, in order
+fn add_tree_edges(graph: &mut DiGraph<String, String>, node: NodeIndex, children: &[NodeIndex]) {
+    for child in children {
+        graph.add_edge(node, *child, String::new());
+    }
+}
+
+fn regex_node(regex: &RegEx, graph: &mut DiGraph<String, String>) -> NodeIndex {
+    match regex {
+        RegEx::SimpleRegex(term) => term_node(term, graph),
+        RegEx::AlterRegex(term, rest) => {
+            let node = graph.add_node("alt".to_string());
+            let left = term_node(term, graph);
+            let right = regex_node(rest, graph);
+            add_tree_edges(graph, node, &[left, right]);
+            node
+        }
+    }
+}
+
+// The last factor is the outermost one, so the rest of the term is the left operand
+fn term_node(term: &Term, graph: &mut DiGraph<String, String>) -> NodeIndex {
+    match term {
+        Term::SimpleTerm(factor) => factor_node(factor, graph),
+        Term::ConcatTerm(factor, rest) => {
+            let node = graph.add_node("cat".to_string());
+            let left = term_node(rest, graph);
+            let right = factor_node(factor, graph);
+            add_tree_edges(graph, node, &[left, right]);
+            node
+        }
+    }
+}
+
+fn factor_node(factor: &Factor, graph: &mut DiGraph<String, String>) -> NodeIndex {
+    let Factor::SimpleFactor(base, quantifier) = factor;
+
+    let label = match quantifier {
+        None => return base_node(base, graph),
+        Some(Quantifier::Star) => "star",
+        Some(Quantifier::Plus) => "plus",
+        Some(Quantifier::Question) => "opt",
+    };
+    let node = graph.add_node(label.to_string());
+    let child = base_node(base, graph);
+    add_tree_edges(graph, node, &[child]);
+    node
+}
+
+fn base_node(base: &Base, graph: &mut DiGraph<String, String>) -> NodeIndex {
+    match base {
+        Base::Exp(regex) => {
+            let node = graph.add_node("group".to_string());
+            let child = regex_node(regex, graph);
+            add_tree_edges(graph, node, &[child]);
+            node
+        }
+        // The S-expression of any other base is a single parenthesized list
+        base => {
+            let sexp = base_sexp(base);
+            graph.add_node(sexp[1..sexp.len() - 1].to_string())
+        }
+    }
+}
+
+// The last factor is the outermost one, so the rest of the term is written first
+fn term_std_regex(term: &Term) -> String {
+    match term {
+        Term::SimpleTerm(factor) => factor_std_regex(factor),
+        Term::ConcatTerm(factor, rest) => {
+            format!("{}{}", term_std_regex(rest), factor_std_regex(factor))
+        }
+    }
+}
+
+fn factor_std_regex(factor: &Factor) -> String {
+    let Factor::SimpleFactor(base, quantifier) = factor;
+    let mut pattern = base_std_regex(base);
+
+    // An empty base has nothing to repeat, so it is wrapped in a group the regex crate accepts
+    if pattern.is_empty() && quantifier.is_some() {
+        pattern = "(?:)".to_string();
+    }
+
+    match quantifier {
+        None => pattern,
+        Some(Quantifier::Star) => pattern + "*",
+        Some(Quantifier::Plus) => pattern + "+",
+        Some(Quantifier::Question) => pattern + "?",
+    }
+}
+
+fn base_std_regex(base: &Base) -> String {
+    match base {
+        Base::Character(ch) if raw_byte(*ch).is_some() => {
+            format!("(?-u:{})", std_regex_char(*ch))
+        }
+        Base::Character(ch) => std_regex_char(*ch),
+        Base::EscapeCharacter(ch) => match ch {
+            'n' => "\\n".to_string(),
+            't' => "\\t".to_string(),
+            'r' => "\\r".to_string(),
+            _ => std_regex_char(*ch),
+        },
+        Base::Exp(regex) => format!("({})", regex.to_std_regex_string()),
+        Base::CharSet(char_set) | Base::NegatedCharSet(char_set) => {
+            let mut chars: Vec<&char> = char_set.iter().collect();
+            chars.sort();
+            let negation = match base {
+                Base::CharSet(_) => "",
+                _ => "^",
+            };
+            // Runs of consecutive characters are written back as ranges to keep the class short
+            let mut class = String::new();
+            let mut index = 0;
+            while index < chars.len() {
+                let mut end = index;
+                while end + 1 < chars.len() && *chars[end + 1] as u32 == *chars[end] as u32 + 1 {
+                    end += 1;
+                }
+                class.push_str(&std_regex_char(*chars[index]));
+                if end >= index + 2 {
+                    class.push('-');
+                    class.push_str(&std_regex_char(*chars[end]));
+                    index = end + 1;
+                } else {
+                    index += 1;
+                }
+            }
+            if chars.iter().any(|ch| raw_byte(**ch).is_some()) {
+                format!("(?-u:[{}{}])", negation, class)
+            } else {
+                format!("[{}{}]", negation, class)
+            }
+        }
+        Base::AnyChar => ".".to_string(),
+        Base::StartAnchor => "^".to_string(),
+        Base::EndAnchor => "$".to_string(),
+        Base::WordBoundary => "(?-u:\\b)".to_string(),
+        Base::NamedClass(name) => format!("\\{{{}\\}}", name),
+        Base::Empty => String::new(),
+    }
+}
+
+// The same escaping works both inside and outside of a character class, as the regex crate allows
+// any of its meta characters to be escaped anywhere
+fn std_regex_char(ch: char) -> String {
+    if let Some(byte) = raw_byte(ch) {
+        return format!("\\x{:X}", byte);
+    }
+
+    match ch {
+        '\\' | '.' | '+' | '*' | '?' | '(' | ')' | '|' | '[' | ']' | '{' | '}' | '^' | '$'
+        | '#' | '&' | '-' | '~' => format!("\\{}", ch),
+        '\n' => "\\n".to_string(),
+        '\t' => "\\t".to_string(),
+        '\r' => "\\r".to_string(),
+        _ if ch.is_control() || (ch.is_whitespace() && ch != ' ') => {
+            format!("\\x{{{:X}}}", ch as u32)
+        }
+        _ => ch.to_string(),
+    }
+}
+
+// Characters which would be ambiguous inside an S-expression are written as quoted literals
+fn sexp_char(ch: char) -> String {
+    if let Some(byte) = raw_byte(ch) {
+        format!("\\x{:X}", byte)
+    } else if ch.is_alphanumeric()
+        || (ch.is_ascii_punctuation() && !matches!(ch, '(' | ')' | '\'' | '\\'))
+    {
+        ch.to_string()
+    } else {
+        format!("{:?}", ch)
+    }
+}
+
+/// The first bracket which breaks the nesting of a regex, along with its position in the regex
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnmatchedBracket {
+    /// An opening parenthesis or bracket which is never closed
+    Unclosed(char, usize),
+    /// A closing parenthesis without a matching opening parenthesis
+    Unexpected(char, usize),
+}
+
+impl std::fmt::Display for UnmatchedBracket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnmatchedBracket::Unclosed(bracket, position) => {
+                write!(f, "unmatched '{}' at position {}", bracket, position)
+            }
+            UnmatchedBracket::Unexpected(bracket, position) => {
+                write!(f, "unexpected '{}' at position {}", bracket, position)
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum RegExError {
     MalformedMicrosyntaxError(String),
     InvalidRegexError(String),
-    UnbalancedParenthesisError(String),
+    UnbalancedParenthesisError(String, UnmatchedBracket),
     FileOpenError(String),
     FileReadError(String),
     InvalidCharacterRange(char, char),
     InvalidEscapeCharacter(char),
+    MissingQuantifierOperand(char, usize),
+    UndefinedMacro(String),
+    MacroCycle(String),
+    UnknownRuleFlag(char, String),
 }
 
 impl std::fmt::Display for RegExError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            RegExError::UnbalancedParenthesisError(regex) => {
-                write!(f, "Error: {} has unbalanced parenthesis!", regex)
+            RegExError::UnbalancedParenthesisError(regex, bracket) => {
+                write!(
+                    f,
+                    "Error: {} has unbalanced parenthesis, {}!",
+                    regex, bracket
+                )
             }
             RegExError::InvalidRegexError(regex) => {
                 write!(f, "Error: Invalid regex provided: {}", regex)
@@ -73,111 +442,317 @@ impl std::fmt::Display for RegExError {
             RegExError::InvalidEscapeCharacter(ch) => {
                 write!(f, "Error: Invalid escape character {}  provided!", ch)
             }
+            RegExError::MissingQuantifierOperand(quantifier, position) => write!(
+                f,
+                "Error: Quantifier '{}' has no preceding expression at position {}!",
+                quantifier, position
+            ),
+            RegExError::UndefinedMacro(name) => {
+                write!(f, "Error: The macro {{{}}} is not defined!", name)
+            }
+            RegExError::MacroCycle(name) => {
+                write!(
+                    f,
+                    "Error: The macro {{{}}} is defined in terms of itself!",
+                    name
+                )
+            }
+            RegExError::UnknownRuleFlag(flag, line) => {
+                write!(
+                    f,
+                    "Error: Unknown flag '{}' in the microsyntax entry {}!",
+                    flag, line
+                )
+            }
         }
     }
 }
 
 impl std::error::Error for RegExError {}
 
-fn balanced_brackets(regex: &str) -> bool {
-    let mut stack = Vec::new();
-    let mut chars = regex.chars().peekable();
+/// Options which control how a regular expression is parsed
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Report a warning when a character class contains metacharacters such as '|', '*' or '.'.
+    /// Inside a class these are plain members, so `[a|b]` is the set of 'a', '|' and 'b', which is
+    /// rarely what was intended.
+    pub strict: bool,
+    /// Let '.' also match a newline, the same as starting the regex with the `(?s)` flag
+    pub dotall: bool,
+    /// Treat an empty branch of an alternation, such as the one in `a|` or `(a|)`, as matching the
+    /// empty string. By default an empty branch is an error.
+    pub allow_empty_alternation: bool,
+    /// Ignore unescaped whitespace and comments from # to the end of the line, the same as
+    /// starting the regex with the `(?x)` flag. See strip_extended.
+    pub extended: bool,
+    /// Match letters regardless of their case, the same as starting the regex with the `(?i)` flag
+    pub case_insensitive: bool,
+}
 
-    while let Some(ch) = chars.next() {
+/// Non fatal diagnostics found while parsing a regular expression
+#[derive(Debug, PartialEq, Eq)]
+pub enum RegExWarning {
+    /// A metacharacter was found inside a character class at the given position of the regex
+    MetacharacterInClass(String, char, usize),
+}
+
+impl std::fmt::Display for RegExWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegExWarning::MetacharacterInClass(regex, ch, position) => write!(
+                f,
+                "Warning: The character class in {} contains the metacharacter '{}' at position {}, it is treated as a literal member!",
+                regex, ch, position
+            ),
+        }
+    }
+}
+
+// Find the first bracket which breaks the nesting of the regex. Positions count characters.
+// Parentheses inside a character class are literals and are not counted.
+fn balanced_brackets(regex: &str) -> Result<(), UnmatchedBracket> {
+    let mut open_parens: Vec<usize> = Vec::new(); // Positions of the unclosed parentheses
+    let mut open_class: Option<usize> = None; // Position of the bracket opening the current class
+    let mut chars = regex.chars().enumerate();
+
+    while let Some((position, ch)) = chars.next() {
         if ch == '\\' {
             chars.next();
             continue;
         }
         match ch {
-            '(' => {
-                stack.push(ch);
-            }
+            ']' if open_class.is_some() => open_class = None,
+            _ if open_class.is_some() => {}
+            '[' => open_class = Some(position),
+            '(' => open_parens.push(position),
             ')' => {
-                if stack.is_empty() || stack.pop() != Some('(') {
-                    return false;
-                }
-            }
-            '[' => {
-                stack.push(ch);
-            }
-            ']' => {
-                if stack.is_empty() || stack.pop() != Some('[') {
-                    return false;
+                if open_parens.pop().is_none() {
+                    return Err(UnmatchedBracket::Unexpected(ch, position));
                 }
             }
             _ => {}
         }
     }
-    stack.is_empty()
+
+    // Report whichever unclosed bracket comes first
+    match (open_parens.first(), open_class) {
+        (Some(paren), Some(class)) if class < *paren => Err(UnmatchedBracket::Unclosed('[', class)),
+        (Some(paren), _) => Err(UnmatchedBracket::Unclosed('(', *paren)),
+        (None, Some(class)) => Err(UnmatchedBracket::Unclosed('[', class)),
+        (None, None) => Ok(()),
+    }
 }
 
 fn nchar_is_valid(nchar: char) -> bool {
     match nchar {
-        '*' | '|' | '?' | ')' | ']' => false,
+        '*' | '|' | '?' | ')' => false, // A stray ']' or '}' is a literal
         _ => true,
     }
 }
 
 fn is_escape_char(escape_ch: char) -> bool {
     match escape_ch {
-        'n' | 't' | 'r' | '\\' | '(' | ')' | '[' | ']' | '|' | '*' | '+' | '?' => true,
+        'n' | 't' | 'r' | '\\' | '(' | ')' | '[' | ']' | '|' | '*' | '+' | '?' | '.' | '^'
+        | '$' | '{' | '}' => true,
         _ => false,
     }
 }
 
+/// A radix numeric literals can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    Binary,
+    Octal,
+    Decimal,
+    Hexadecimal,
+}
+
+impl Radix {
+    /// The prefix introducing a literal of the radix, which is empty for decimal literals
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Radix::Binary => "0b",
+            Radix::Octal => "0o",
+            Radix::Decimal => "",
+            Radix::Hexadecimal => "0x",
+        }
+    }
+
+    /// The character class of the digits of the radix
+    pub fn digits(&self) -> &'static str {
+        match self {
+            Radix::Binary => "[01]",
+            Radix::Octal => "[0-7]",
+            Radix::Decimal => "[0-9]",
+            Radix::Hexadecimal => "[0-9a-fA-F]",
+        }
+    }
+}
+
+/// Generate the regular expression of an integer literal written in any of the provided radixes,
+/// such as `0xFF_00`, `0b1010` or `1_000`. Literals of every radix but decimal start with their
+/// prefix. If underscores is true, single underscores may separate the digits, but a literal can
+/// not start or end with an underscore or contain two in a row.
+pub fn number_literal_regex(radixes: &[Radix], underscores: bool) -> String {
+    let alternatives: Vec<String> = radixes
+        .iter()
+        .map(|radix| {
+            let digits = radix.digits();
+            match underscores {
+                true => format!("{}{}(_?{})*", radix.prefix(), digits, digits),
+                false => format!("{}{}+", radix.prefix(), digits),
+            }
+        })
+        .collect();
+
+    alternatives.join("|")
+}
+
+/// Escape a string into a regular expression which matches exactly that string, by putting a
+/// backslash in front of every metacharacter and writing newlines, tabs and carriage returns as
+/// their escape sequences.
+pub fn escape_literal(literal: &str) -> String {
+    let mut regex = String::new();
+
+    for ch in literal.chars() {
+        match ch {
+            '\n' => regex.push_str("\\n"),
+            '\t' => regex.push_str("\\t"),
+            '\r' => regex.push_str("\\r"),
+            // The remaining escape characters are the metacharacters themselves
+            ch if is_escape_char(ch) && !ch.is_alphabetic() => {
+                regex.push('\\');
+                regex.push(ch);
+            }
+            ch => regex.push(ch),
+        }
+    }
+
+    regex
+}
+
+// Parse a two digit hexadecimal escape like \x7f starting at the backslash. The byte is returned as
+// its symbol, which is the ASCII character up to \x7f and a raw byte above it.
+fn parse_hex_escape(regex: &str, start: usize) -> Result<char, RegExError> {
+    let digits: String = regex.chars().skip(start + 2).take(2).collect();
+
+    if digits.len() != 2 {
+        return Err(RegExError::InvalidEscapeCharacter('x'));
+    }
+
+    match u8::from_str_radix(&digits, 16) {
+        Ok(byte) => Ok(byte_symbol(byte)),
+        Err(_) => Err(RegExError::InvalidEscapeCharacter('x')),
+    }
+}
+
+// Parse a single member of a character class, which is either a literal character or an escape
+// sequence. Returns the character along with the position right after it.
+fn parse_class_char(regex: &str, start: usize) -> Result<(char, usize), RegExError> {
+    let ch = match regex.chars().nth(start) {
+        Some(ch) => ch,
+        None => return Err(RegExError::InvalidRegexError(regex.to_string())),
+    };
+
+    if ch != '\\' {
+        return Ok((ch, start + 1));
+    }
+
+    let escape_ch = match regex.chars().nth(start + 1) {
+        Some(escape_ch) => escape_ch,
+        None => return Err(RegExError::InvalidRegexError(regex.to_string())),
+    };
+
+    if escape_ch == 'x' {
+        let ch = parse_hex_escape(regex, start)?;
+        return Ok((ch, start + 4));
+    }
+
+    if !is_escape_char(escape_ch) {
+        return Err(RegExError::InvalidEscapeCharacter(escape_ch));
+    }
+
+    let ch = match escape_ch {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        escape_ch => escape_ch, // The remaining escapes are the metacharacters themselves
+    };
+
+    Ok((ch, start + 2))
+}
+
+// Get the members of the shorthand class at the start position, \d for the digits, \w for the word
+// characters and \s for whitespace, and the position after it. Like the word boundary, the
+// shorthand classes only cover ASCII characters.
+fn parse_class_shorthand(regex: &str, start: usize) -> Option<(HashSet<char>, usize)> {
+    if regex.chars().nth(start) != Some('\\') {
+        return None;
+    }
+
+    let char_set = match regex.chars().nth(start + 1)? {
+        'd' => ('0'..='9').collect(),
+        'w' => ('0'..='9')
+            .chain('A'..='Z')
+            .chain('a'..='z')
+            .chain(['_'])
+            .collect(),
+        's' => HashSet::from([' ', '\t', '\n', '\r', '\x0B', '\x0C']),
+        _ => return None,
+    };
+
+    Some((char_set, start + 2))
+}
+
+// Get the members of the range from start to end. A range from an ASCII character to a raw byte,
+// like [\x00-\xff], covers the ASCII characters up to \x7f and the raw bytes from \x80 on.
+fn class_range(start: char, end: char) -> Result<Vec<char>, RegExError> {
+    match (raw_byte(start), raw_byte(end)) {
+        (None, None) | (Some(_), Some(_)) if start <= end => Ok((start..=end).collect()),
+        (None, Some(_)) if start.is_ascii() => {
+            Ok((start..='\x7f').chain(byte_symbol(0x80)..=end).collect())
+        }
+        _ => Err(RegExError::InvalidCharacterRange(start, end)),
+    }
+}
+
 fn parse_char_class(regex: &str, start: usize) -> Result<(HashSet<char>, usize), RegExError> {
     let mut new_start = start;
     let mut char_set: HashSet<char> = HashSet::new();
 
-    while new_start < regex.len() && regex.chars().nth(new_start).unwrap() != ']' {
-        if regex.chars().nth(new_start + 1).unwrap() == '-' {
-            let char_start = regex.chars().nth(new_start).unwrap();
-            let char_end = regex.chars().nth(new_start + 2).unwrap();
-            if char_end < char_start {
-                return Err(RegExError::InvalidCharacterRange(char_start, char_end));
-            }
-            for char in char_start..=char_end {
-                char_set.insert(char);
-            }
-            new_start = new_start + 3;
+    while regex.chars().nth(new_start).is_some_and(|ch| ch != ']') {
+        // A shorthand class adds all its members and can not be the end of a range, so a dash
+        // after it is a literal member
+        if let Some((shorthand, next_start)) = parse_class_shorthand(regex, new_start) {
+            char_set.extend(shorthand);
+            new_start = next_start;
+            continue;
+        }
+
+        let (char_start, next_start) = parse_class_char(regex, new_start)?;
+
+        // A dash followed by another member of the class makes this a range. A dash right before
+        // the closing bracket, or at the end of the regex, is a literal member instead.
+        let is_range = regex.chars().nth(next_start) == Some('-')
+            && regex
+                .chars()
+                .nth(next_start + 1)
+                .is_some_and(|ch| ch != ']');
+
+        if is_range {
+            let (char_end, next_start) = parse_class_char(regex, next_start + 1)?;
+            char_set.extend(class_range(char_start, char_end)?);
+            new_start = next_start;
         } else {
-            if regex.chars().nth(new_start).unwrap() == '\\' {
-                if !is_escape_char(regex.chars().nth(new_start + 1).unwrap()) {
-                    return Err(RegExError::InvalidEscapeCharacter(
-                        regex.chars().nth(new_start + 1).unwrap(),
-                    ));
-                }
-                match regex.chars().nth(new_start + 1).unwrap() {
-                    'n' => char_set.insert('\n'),
-                    't' => char_set.insert('\t'),
-                    'r' => char_set.insert('\r'),
-                    '\\' => char_set.insert('\\'),
-                    '(' => char_set.insert('('),
-                    ')' => char_set.insert(')'),
-                    '[' => char_set.insert('['),
-                    ']' => char_set.insert(']'),
-                    '|' => char_set.insert('|'),
-                    '*' => char_set.insert('*'),
-                    '+' => char_set.insert('+'),
-                    '?' => char_set.insert('?'),
-                    _ => {
-                        return Err(RegExError::InvalidEscapeCharacter(
-                            regex.chars().nth(new_start + 1).unwrap(),
-                        ))
-                    }
-                };
-                new_start = new_start + 2;
-            } else {
-                char_set.insert(regex.chars().nth(new_start).unwrap());
-                new_start = new_start + 1;
-            }
+            char_set.insert(char_start);
+            new_start = next_start;
         }
     }
 
     return Ok((char_set, new_start));
 }
 
-fn parse_base(regex: &str, start: usize) -> Result<(Base, usize)> {
+fn parse_base(regex: &str, start: usize, allow_empty: bool) -> Result<(Base, usize)> {
     let nchar = regex.chars().nth(start);
     let nchar = match nchar {
         None => {
@@ -187,10 +762,21 @@ fn parse_base(regex: &str, start: usize) -> Result<(Base, usize)> {
         Some(nchar) => nchar,
     };
     if nchar == '(' {
-        let (inner_regex, new_start) = parse_regex(regex, start + 1)?; // Consume the lparen
+        let (inner_regex, new_start) = parse_alternation(regex, start + 1, allow_empty)?; // Consume the lparen
         let new_base = Base::Exp(Box::new(inner_regex));
         let new_start = new_start + 1; // Consume the rparen
         Ok((new_base, new_start))
+    } else if nchar == '[' && regex.chars().nth(start + 1) == Some('^') {
+        let (char_set, new_start) = match parse_char_class(regex, start + 2) {
+            Ok((char_set, new_start)) => (char_set, new_start),
+            Err(err) => {
+                let err = Report::new(err);
+                return Err(err);
+            }
+        };
+        let new_start = new_start + 1; // Consume the rparen
+        let new_base = Base::NegatedCharSet(char_set);
+        Ok((new_base, new_start))
     } else if nchar == '[' {
         let (char_set, new_start) = match parse_char_class(regex, start + 1) {
             Ok((char_set, new_start)) => (char_set, new_start),
@@ -202,16 +788,59 @@ fn parse_base(regex: &str, start: usize) -> Result<(Base, usize)> {
         let new_start = new_start + 1; // Consume the rparen
         let new_base = Base::CharSet(char_set);
         Ok((new_base, new_start))
+    } else if nchar == '.' {
+        let new_base = Base::AnyChar;
+        let new_start = start + 1;
+        Ok((new_base, new_start))
+    } else if let Some((name, new_start)) = parse_macro_reference(regex, start) {
+        let new_base = Base::NamedClass(name);
+        Ok((new_base, new_start))
+    } else if nchar == '^' {
+        let new_base = Base::StartAnchor;
+        let new_start = start + 1;
+        Ok((new_base, new_start))
+    } else if nchar == '$' {
+        let new_base = Base::EndAnchor;
+        let new_start = start + 1;
+        Ok((new_base, new_start))
+    } else if nchar == '\\' && regex.chars().nth(start + 1) == Some('b') {
+        let new_base = Base::WordBoundary;
+        let new_start = start + 2;
+        Ok((new_base, new_start))
+    } else if let Some((char_set, new_start)) = parse_class_shorthand(regex, start) {
+        let new_base = Base::CharSet(char_set);
+        Ok((new_base, new_start))
+    } else if nchar == '\\' && regex.chars().nth(start + 1) == Some('x') {
+        let ch = match parse_hex_escape(regex, start) {
+            Ok(ch) => ch,
+            Err(err) => {
+                let err = Report::new(err);
+                return Err(err);
+            }
+        };
+        let new_base = Base::Character(ch);
+        let new_start = start + 4;
+        Ok((new_base, new_start))
     } else if nchar == '\\' {
-        if !is_escape_char(regex.chars().nth(start + 1).unwrap()) {
-            let err = Report::new(RegExError::InvalidEscapeCharacter(
-                regex.chars().nth(start + 1).unwrap(),
-            ));
+        // A backslash at the very end of the regex has nothing to escape
+        let escape_ch = match regex.chars().nth(start + 1) {
+            Some(escape_ch) => escape_ch,
+            None => {
+                let err = Report::new(RegExError::InvalidRegexError(regex.to_string()));
+                return Err(err);
+            }
+        };
+        if !is_escape_char(escape_ch) {
+            let err = Report::new(RegExError::InvalidEscapeCharacter(escape_ch));
             return Err(err);
         }
-        let new_base = Base::EscapeCharacter(regex.chars().nth(start + 1).unwrap());
+        let new_base = Base::EscapeCharacter(escape_ch);
         let new_start = start + 2;
         Ok((new_base, new_start))
+    } else if nchar == '*' || nchar == '+' || nchar == '?' {
+        // A quantifier can only follow a base, so finding one here means it has nothing to repeat
+        let err = Report::new(RegExError::MissingQuantifierOperand(nchar, start));
+        return Err(err);
     } else if nchar_is_valid(nchar) {
         let new_base = Base::Character(nchar);
         let new_start = start + 1;
@@ -222,41 +851,130 @@ fn parse_base(regex: &str, start: usize) -> Result<(Base, usize)> {
     }
 }
 
-fn parse_factor(regex: &str, start: usize) -> Result<(Factor, usize)> {
-    let (base, new_start) = parse_base(regex, start)?;
+fn is_macro_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(ch) if ch.is_ascii_alphabetic() || ch == '_' => {
+            chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        }
+        _ => false,
+    }
+}
 
-    let mut new_start = new_start;
-    let quantifier = {
-        if new_start >= regex.len() {
-            None
-        } else if regex.chars().nth(new_start).unwrap() == '*' {
-            new_start += 1;
-            Some(Quantifier::Star)
-        } else if regex.chars().nth(new_start).unwrap() == '?' {
-            new_start += 1;
-            Some(Quantifier::Question)
-        } else if regex.chars().nth(new_start).unwrap() == '+' {
-            new_start += 1;
-            Some(Quantifier::Plus)
-        } else {
-            None
+// Parse a {NAME} macro reference starting at start, returning the name and the position after the
+// closing brace. A brace which does not enclose a macro name is a literal character.
+fn parse_macro_reference(regex: &str, start: usize) -> Option<(String, usize)> {
+    if regex.chars().nth(start) != Some('{') {
+        return None;
+    }
+
+    let name: String = regex
+        .chars()
+        .skip(start + 1)
+        .take_while(|ch| *ch != '}')
+        .collect();
+
+    let close = start + 1 + name.chars().count();
+    if regex.chars().nth(close) != Some('}') || !is_macro_name(&name) {
+        return None;
+    }
+
+    Some((name, close + 1))
+}
+
+/// Expand the {NAME} macro references in a regex with the regexes defined for them. Every
+/// expansion is wrapped in parentheses, so that {DIGIT}+ repeats the whole definition. Macros may
+/// reference other macros, but a reference to an undefined macro or a macro defined in terms of
+/// itself is an error. Escaped braces and braces inside character classes are left as they are.
+pub fn expand_macros(regex: &str, macros: &HashMap<String, String>) -> Result<String, RegExError> {
+    expand_macros_with_stack(regex, macros, &mut Vec::new())
+}
+
+fn expand_macros_with_stack(
+    regex: &str,
+    macros: &HashMap<String, String>,
+    stack: &mut Vec<String>,
+) -> Result<String, RegExError> {
+    let chars: Vec<char> = regex.chars().collect();
+    let mut expanded = String::new();
+    let mut position = 0;
+    let mut in_char_class = false;
+
+    while position < chars.len() {
+        let ch = chars[position];
+
+        if ch == '\\' && position + 1 < chars.len() {
+            expanded.push(ch);
+            expanded.push(chars[position + 1]);
+            position += 2;
+            continue;
         }
+
+        if in_char_class {
+            in_char_class = ch != ']';
+        } else if ch == '[' {
+            in_char_class = true;
+        } else if let Some((name, next)) = parse_macro_reference(regex, position) {
+            let definition = match macros.get(&name) {
+                Some(definition) => definition,
+                None => return Err(RegExError::UndefinedMacro(name)),
+            };
+            if stack.contains(&name) {
+                return Err(RegExError::MacroCycle(name));
+            }
+
+            stack.push(name);
+            let definition = expand_macros_with_stack(definition, macros, stack)?;
+            stack.pop();
+
+            expanded.push('(');
+            expanded.push_str(&definition);
+            expanded.push(')');
+            position = next;
+            continue;
+        }
+
+        expanded.push(ch);
+        position += 1;
+    }
+
+    Ok(expanded)
+}
+
+fn parse_factor(regex: &str, start: usize, allow_empty: bool) -> Result<(Factor, usize)> {
+    let (base, new_start) = parse_base(regex, start, allow_empty)?;
+
+    let mut new_start = new_start;
+    let quantifier = match regex.chars().nth(new_start) {
+        Some('*') => Some(Quantifier::Star),
+        Some('?') => Some(Quantifier::Question),
+        Some('+') => Some(Quantifier::Plus),
+        _ => None,
     };
+    if quantifier.is_some() {
+        new_start += 1;
+    }
     let term = Factor::SimpleFactor(base, quantifier);
     Ok((term, new_start))
 }
 
-fn parse_term(regex: &str, start: usize) -> Result<(Term, usize)> {
-    let (factor, mut new_start) = parse_factor(regex, start)?;
+fn parse_term(regex: &str, start: usize, allow_empty: bool) -> Result<(Term, usize)> {
+    // An alternation branch which ends right away matches the empty string when allowed
+    let next_char = regex.chars().nth(start);
+    if allow_empty && matches!(next_char, None | Some('|') | Some(')')) {
+        let empty = Term::SimpleTerm(Factor::SimpleFactor(Base::Empty, None));
+        return Ok((empty, start));
+    }
+
+    let (factor, mut new_start) = parse_factor(regex, start, allow_empty)?;
 
     let mut prev_term = Term::SimpleTerm(factor);
 
-    while new_start < regex.len() {
-        let nchar = regex.chars().nth(new_start).unwrap();
+    while let Some(nchar) = regex.chars().nth(new_start) {
         if nchar == '|' || nchar == ')' {
             break;
         } else {
-            let (next_factor, tmp_start) = parse_factor(regex, new_start)?;
+            let (next_factor, tmp_start) = parse_factor(regex, new_start, allow_empty)?;
             let next_term = Term::ConcatTerm(next_factor, Box::new(prev_term));
             prev_term = next_term;
             new_start = tmp_start;
@@ -265,9 +983,12 @@ fn parse_term(regex: &str, start: usize) -> Result<(Term, usize)> {
     Ok((prev_term, new_start))
 }
 
-fn parse_regex(regex: &str, start: usize) -> Result<(RegEx, usize)> {
-    if !balanced_brackets(regex) {
-        let err = Report::new(RegExError::UnbalancedParenthesisError(regex.to_string()));
+fn parse_alternation(regex: &str, start: usize, allow_empty: bool) -> Result<(RegEx, usize)> {
+    if let Err(bracket) = balanced_brackets(regex) {
+        let err = Report::new(RegExError::UnbalancedParenthesisError(
+            regex.to_string(),
+            bracket,
+        ));
         return Err(err);
     }
 
@@ -276,51 +997,421 @@ fn parse_regex(regex: &str, start: usize) -> Result<(RegEx, usize)> {
         return Err(err);
     }
 
-    let (term, new_start) = parse_term(regex, start)?;
-    if new_start >= regex.len() {
-        return Ok((RegEx::SimpleRegex(term), new_start));
-    } else if regex.chars().nth(new_start).unwrap() == '|' {
-        let (next_regex, new_start) = parse_regex(regex, new_start + 1)?;
+    let (term, new_start) = parse_term(regex, start, allow_empty)?;
+    if regex.chars().nth(new_start) == Some('|') {
+        let (next_regex, new_start) = parse_alternation(regex, new_start + 1, allow_empty)?;
         return Ok((RegEx::AlterRegex(term, Box::new(next_regex)), new_start));
     } else {
         return Ok((RegEx::SimpleRegex(term), new_start));
     }
 }
 
-fn build_syntax_tree(regex: &str) -> Result<RegEx> {
-    let (syntax_tree, _) = parse_regex(regex, 0)?;
+// Find metacharacters inside character classes, which are treated as literal members
+fn check_char_classes(regex: &str) -> Vec<RegExWarning> {
+    let mut warnings = Vec::new();
+    let mut inside_class = false;
+    let mut chars = regex.chars().enumerate();
+
+    while let Some((position, ch)) = chars.next() {
+        match ch {
+            '\\' => {
+                chars.next(); // Escaped characters are always intended as literals
+            }
+            '[' if !inside_class => inside_class = true,
+            ']' if inside_class => inside_class = false,
+            '|' | '*' | '+' | '?' | '(' | ')' | '.' if inside_class => {
+                warnings.push(RegExWarning::MetacharacterInClass(
+                    regex.to_string(),
+                    ch,
+                    position,
+                ));
+            }
+            _ => {}
+        }
+    }
+    warnings
+}
+
+/// Parse a regular expression with the provided options and return its syntax tree along with any
+/// warnings found while parsing.
+pub fn parse_with_options(
+    regex: &str,
+    options: &ParseOptions,
+) -> Result<(RegEx, Vec<RegExWarning>)> {
+    let mut syntax_tree =
+        build_syntax_tree_with(regex, options.allow_empty_alternation, options.extended)?;
+
+    if options.dotall {
+        syntax_tree = dotall_regex(syntax_tree);
+    }
+
+    if options.case_insensitive {
+        syntax_tree = case_insensitive_regex(syntax_tree);
+    }
+
+    let mut warnings = Vec::new();
+
+    if options.strict {
+        warnings.extend(check_char_classes(regex));
+    }
+
+    Ok((syntax_tree, warnings))
+}
+
+// The any character matches a newline as well, so it is rewritten into the alternation (.|\n)
+fn dotall_base(base: Base) -> Base {
+    match base {
+        Base::AnyChar => {
+            let any_char = Term::SimpleTerm(Factor::SimpleFactor(Base::AnyChar, None));
+            let newline = Term::SimpleTerm(Factor::SimpleFactor(Base::EscapeCharacter('n'), None));
+            let alternation = RegEx::AlterRegex(any_char, Box::new(RegEx::SimpleRegex(newline)));
+            Base::Exp(Box::new(alternation))
+        }
+        base => base,
+    }
+}
+
+fn dotall_regex(regex: RegEx) -> RegEx {
+    map_regex(regex, &dotall_base)
+}
+
+// Letters match their other case as well, so they are rewritten into character sets holding both
+fn case_insensitive_base(base: Base) -> Base {
+    match base {
+        Base::Character(ch) => {
+            let char_set = case_variants(HashSet::from([ch]));
+            if char_set.len() == 1 {
+                Base::Character(ch)
+            } else {
+                Base::CharSet(char_set)
+            }
+        }
+        Base::CharSet(char_set) => Base::CharSet(case_variants(char_set)),
+        Base::NegatedCharSet(char_set) => Base::NegatedCharSet(case_variants(char_set)),
+        base => base,
+    }
+}
+
+// Add the lowercase and uppercase forms of every character which has a single character form
+fn case_variants(char_set: HashSet<char>) -> HashSet<char> {
+    let mut variants = char_set.clone();
+
+    for ch in char_set {
+        for mut forms in [
+            ch.to_lowercase().collect::<Vec<char>>(),
+            ch.to_uppercase().collect(),
+        ] {
+            if forms.len() == 1 {
+                variants.insert(forms.pop().unwrap());
+            }
+        }
+    }
+    variants
+}
+
+fn case_insensitive_regex(regex: RegEx) -> RegEx {
+    map_regex(regex, &case_insensitive_base)
+}
+
+// Rewrite every base of the syntax tree, including the ones inside groups
+fn map_base(base: Base, rewrite: &dyn Fn(Base) -> Base) -> Base {
+    match base {
+        Base::Exp(regex) => Base::Exp(Box::new(map_regex(*regex, rewrite))),
+        base => rewrite(base),
+    }
+}
+
+fn map_factor(factor: Factor, rewrite: &dyn Fn(Base) -> Base) -> Factor {
+    match factor {
+        Factor::SimpleFactor(base, quantifier) => {
+            Factor::SimpleFactor(map_base(base, rewrite), quantifier)
+        }
+    }
+}
+
+fn map_term(term: Term, rewrite: &dyn Fn(Base) -> Base) -> Term {
+    match term {
+        Term::SimpleTerm(factor) => Term::SimpleTerm(map_factor(factor, rewrite)),
+        Term::ConcatTerm(factor, term) => Term::ConcatTerm(
+            map_factor(factor, rewrite),
+            Box::new(map_term(*term, rewrite)),
+        ),
+    }
+}
+
+fn map_regex(regex: RegEx, rewrite: &dyn Fn(Base) -> Base) -> RegEx {
+    match regex {
+        RegEx::SimpleRegex(term) => RegEx::SimpleRegex(map_term(term, rewrite)),
+        RegEx::AlterRegex(term, regex) => RegEx::AlterRegex(
+            map_term(term, rewrite),
+            Box::new(map_regex(*regex, rewrite)),
+        ),
+    }
+}
+
+/// A regex starting with this flag lets '.' match a newline as well
+pub const DOTALL_FLAG: &str = "(?s)";
+
+/// A regex starting with this flag is written in extended mode, see strip_extended
+pub const EXTENDED_FLAG: &str = "(?x)";
+
+/// A regex starting with this flag matches letters regardless of their case
+pub const CASE_INSENSITIVE_FLAG: &str = "(?i)";
+
+/// Strip the insignificant parts of a regex written in extended mode, where unescaped whitespace is
+/// ignored and # starts a comment running to the end of the line. This lets a complex pattern be
+/// spread over several lines and commented. Whitespace and # inside a character class are kept, and
+/// an escaped space or # (`\ ` or `\#`) stands for the literal character.
+pub fn strip_extended(regex: &str) -> String {
+    let mut result = String::new();
+    let mut chars = regex.chars();
+    let mut in_class = false;
+    let mut in_comment = false;
+
+    while let Some(ch) = chars.next() {
+        if in_comment {
+            in_comment = ch != '\n';
+            continue;
+        }
+
+        match ch {
+            '\\' => match chars.next() {
+                // The space and # are not metacharacters, so they are matched unescaped
+                Some(escaped @ (' ' | '#')) if !in_class => result.push(escaped),
+                Some(escaped) => {
+                    result.push('\\');
+                    result.push(escaped);
+                }
+                None => result.push('\\'),
+            },
+            '[' if !in_class => {
+                in_class = true;
+                result.push(ch);
+            }
+            ']' if in_class => {
+                in_class = false;
+                result.push(ch);
+            }
+            '#' if !in_class => in_comment = true,
+            ch if ch.is_whitespace() && !in_class => {}
+            ch => result.push(ch),
+        }
+    }
+
+    return result;
+}
+
+pub(crate) fn build_syntax_tree(regex: &str) -> Result<RegEx> {
+    build_syntax_tree_with(regex, false, false)
+}
+
+fn build_syntax_tree_with(
+    regex: &str,
+    allow_empty_alternation: bool,
+    extended: bool,
+) -> Result<RegEx> {
+    let (regex, extended) = match regex.strip_prefix(EXTENDED_FLAG) {
+        Some(regex) => (regex, true),
+        None => (regex, extended),
+    };
+
+    let stripped;
+    let regex = if extended {
+        stripped = strip_extended(regex);
+        stripped.as_str()
+    } else {
+        regex
+    };
+
+    // The remaining flags can be given in either order
+    let mut regex = regex;
+    let mut dotall = false;
+    let mut case_insensitive = false;
+
+    loop {
+        if let Some(rest) = regex.strip_prefix(DOTALL_FLAG) {
+            dotall = true;
+            regex = rest;
+        } else if let Some(rest) = regex.strip_prefix(CASE_INSENSITIVE_FLAG) {
+            case_insensitive = true;
+            regex = rest;
+        } else {
+            break;
+        }
+    }
+
+    let (mut syntax_tree, _) = parse_alternation(regex, 0, allow_empty_alternation)?;
+
+    if dotall {
+        syntax_tree = dotall_regex(syntax_tree);
+    }
+    if case_insensitive {
+        syntax_tree = case_insensitive_regex(syntax_tree);
+    }
     return Ok(syntax_tree);
 }
-/// Parse a list of microsyntaxes provided and return the parse trees
-pub fn parse_microsyntax_list(
-    regex_list: Vec<(String, String)>,
-) -> Result<VecDeque<(String, RegEx, String)>> {
+/// An entry of a microsyntax list, either a regex and category pair or a regex, category and
+/// flags triple as read by read_microsyntax_file
+pub trait MicrosyntaxEntry {
+    /// Split the entry into its regex, category and flags
+    fn into_parts(self) -> (String, String, RuleFlags);
+}
+
+impl MicrosyntaxEntry for (String, String) {
+    fn into_parts(self) -> (String, String, RuleFlags) {
+        (self.0, self.1, RuleFlags::default())
+    }
+}
+
+impl MicrosyntaxEntry for (String, String, RuleFlags) {
+    fn into_parts(self) -> (String, String, RuleFlags) {
+        self
+    }
+}
+
+/// Parse a list of microsyntaxes provided and return the parse trees. The i and x flags of an
+/// entry are applied to its regex, and the flags are kept with the parse tree so that the scanner
+/// skips the tokens of the entries with the s flag.
+pub fn parse_microsyntax_list<E: MicrosyntaxEntry>(
+    regex_list: Vec<E>,
+) -> Result<VecDeque<(String, RegEx, String, RuleFlags)>> {
     let mut syntax_tree_list = VecDeque::new();
 
     for regex_entry in regex_list {
-        let (regex, category) = regex_entry;
+        let (regex, category, flags) = regex_entry.into_parts();
+        let regex = flags.apply(regex);
 
         let syntax_tree = build_syntax_tree(&regex)?;
 
-        syntax_tree_list.push_back((regex, syntax_tree, category));
+        syntax_tree_list.push_back((regex, syntax_tree, category, flags));
     }
     return Ok(syntax_tree_list);
 }
-/// Parse a file containing microsyntaxes and return the parse trees
-pub fn read_microsyntax_file(file_path: String) -> Result<Vec<(String, String)>, RegExError> {
+/// Parse a file containing microsyntaxes and return the parse trees. Besides the microsyntax
+/// entries, the file may define macros on lines of the form `NAME = regex`, whose {NAME}
+/// references in the entries are expanded with expand_macros. An entry may end with a field of
+/// flags, see RuleFlags.
+pub fn read_microsyntax_file(
+    file_path: String,
+) -> Result<Vec<(String, String, RuleFlags)>, RegExError> {
+    let (regex_list, macros) = read_microsyntax_entries(file_path)?;
+
+    expand_macro_references(regex_list, &macros)
+}
+
+fn expand_macro_references(
+    regex_list: Vec<(String, String, RuleFlags)>,
+    macros: &HashMap<String, String>,
+) -> Result<Vec<(String, String, RuleFlags)>, RegExError> {
+    let mut expanded_list = Vec::new();
+
+    for (regex, category, flags) in regex_list {
+        expanded_list.push((expand_macros(&regex, macros)?, category, flags));
+    }
+
+    Ok(expanded_list)
+}
+
+// Split a "NAME = regex" macro definition, which is any line without "::" that starts with a
+// macro name followed by " = "
+fn split_macro_definition(line: &str) -> Option<(String, String)> {
+    if line.contains("::") {
+        return None;
+    }
+
+    let (name, regex) = line.split_once(" = ")?;
+    let name = name.trim();
+
+    if !is_macro_name(name) {
+        return None;
+    }
+
+    Some((name.to_string(), regex.to_string()))
+}
+
+/// The flags which can follow the category of a microsyntax entry, such as `if :: KEYWORD :: i`.
+/// Each flag is a single letter:
+/// - `i` matches the letters of the regex regardless of their case, like the `(?i)` flag
+/// - `s` skips the tokens of the category of the entry while scanning, like the skip list
+/// - `x` reads the regex in extended mode, like the `(?x)` flag
+///
+/// The flags are kept with the entry when the file is read. The i and x flags are applied by
+/// parse_microsyntax_list as the inline flags at the start of the regex, while the category of an
+/// entry with the s flag is kept and the scanner skips its tokens as if it were in the skip list.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RuleFlags {
+    pub case_insensitive: bool,
+    pub skip: bool,
+    pub extended: bool,
+}
+
+impl RuleFlags {
+    /// Parse the letters of a flags field. Whitespace between the letters is ignored, and any
+    /// other letter is an error.
+    pub fn parse(flags: &str) -> Result<RuleFlags, RegExError> {
+        let mut rule_flags = RuleFlags::default();
+
+        for flag in flags.chars().filter(|ch| !ch.is_whitespace()) {
+            match flag {
+                'i' => rule_flags.case_insensitive = true,
+                's' => rule_flags.skip = true,
+                'x' => rule_flags.extended = true,
+                _ => return Err(RegExError::UnknownRuleFlag(flag, flags.to_string())),
+            }
+        }
+        Ok(rule_flags)
+    }
+
+    /// Apply the i and x flags to the regex of a microsyntax entry and return the regex to use
+    /// instead
+    pub fn apply(&self, regex: String) -> String {
+        let mut prefix = String::new();
+
+        // The extended flag has to come first, as it changes how the rest of the regex is read
+        if self.extended {
+            prefix.push_str(EXTENDED_FLAG);
+        }
+        if self.case_insensitive {
+            prefix.push_str(CASE_INSENSITIVE_FLAG);
+        }
+        prefix + &regex
+    }
+}
+
+/// The prefix of a microsyntax whose pattern is a literal string rather than a regex, as in
+/// `lit:||::OR`. The rest of the pattern is escaped with escape_literal when the file is read.
+pub const LITERAL_RULE_PREFIX: &str = "lit:";
+
+// Escape the pattern of a literal rule, leaving any other pattern as it is
+fn literal_rule(pattern: &str) -> String {
+    match pattern.strip_prefix(LITERAL_RULE_PREFIX) {
+        Some(literal) => escape_literal(literal),
+        None => pattern.to_string(),
+    }
+}
+
+// Read the microsyntax entries and the macro definitions of a file without expanding the macros
+fn read_microsyntax_entries(
+    file_path: String,
+) -> Result<(Vec<(String, String, RuleFlags)>, HashMap<String, String>), RegExError> {
     let file_path = PathBuf::from(file_path);
 
-    let file = File::open(file_path);
+    let file = File::open(&file_path);
     let file = match file {
         Ok(file) => file,
         Err(error) => {
-            let err_line = format!("Error: Failed to open the microsyntax file {}", error);
+            let err_line = format!(
+                "Error: Failed to open the microsyntax file {}: {}",
+                file_path.display(),
+                error
+            );
             return Err(RegExError::FileOpenError(err_line));
         }
     };
     let reader = BufReader::new(file);
 
-    let mut regex_list: Vec<(String, String)> = Vec::new();
+    let mut regex_list: Vec<(String, String, RuleFlags)> = Vec::new();
+    let mut macros: HashMap<String, String> = HashMap::new();
 
     for (line_number, line) in reader.lines().enumerate() {
         let line = match line {
@@ -334,9 +1425,14 @@ pub fn read_microsyntax_file(file_path: String) -> Result<Vec<(String, String)>,
             }
         };
 
+        if let Some((name, regex)) = split_macro_definition(&line) {
+            macros.insert(name, regex);
+            continue;
+        }
+
         let content: Vec<&str> = line.split("::").collect();
 
-        if content.len() != 2 {
+        if content.len() != 2 && content.len() != 3 {
             return Err(RegExError::MalformedMicrosyntaxError(
                 content[0].to_string(),
             ));
@@ -346,16 +1442,104 @@ pub fn read_microsyntax_file(file_path: String) -> Result<Vec<(String, String)>,
         let lhs = lhs.replace("\\:\\:", "::"); // Escape the double colons itself
         let rhs = content[1];
 
-        let pair = (lhs.to_string(), rhs.to_string());
-        regex_list.push(pair);
+        // With a flags field the fields may be padded with spaces, as in "if :: KEYWORD :: i"
+        let entry = match content.get(2) {
+            Some(flags) => match RuleFlags::parse(flags) {
+                Ok(flags) => (literal_rule(lhs.trim()), rhs.trim().to_string(), flags),
+                Err(RegExError::UnknownRuleFlag(flag, _)) => {
+                    return Err(RegExError::UnknownRuleFlag(flag, line));
+                }
+                Err(err) => return Err(err),
+            },
+            None => (literal_rule(&lhs), rhs.to_string(), RuleFlags::default()),
+        };
+        regex_list.push(entry);
+    }
+
+    Ok((regex_list, macros))
+}
+/// Read several microsyntax files in order and concatenate their entries. Entries from earlier
+/// files have a higher priority than entries from later files. Macros defined in any of the files
+/// can be referenced from all of them.
+pub fn read_microsyntax_files(
+    file_paths: &[String],
+) -> Result<Vec<(String, String, RuleFlags)>, RegExError> {
+    let mut regex_list: Vec<(String, String, RuleFlags)> = Vec::new();
+    let mut macros: HashMap<String, String> = HashMap::new();
+
+    for file_path in file_paths {
+        let (mut file_regex_list, file_macros) = read_microsyntax_entries(file_path.clone())?;
+        regex_list.append(&mut file_regex_list);
+        macros.extend(file_macros);
+    }
+
+    expand_macro_references(regex_list, &macros)
+}
+
+/// Read a file defining the universe of characters that negated character sets and '.' are
+/// resolved against, to be passed to compile_with_universe or construct_nfa_with_universe. Every
+/// line lists members the same way as the inside of a character class, such as `a-z0-9` or
+/// `αβγ`, including ranges and escapes like `\n` or `\]`. Empty lines are ignored.
+pub fn read_alphabet_file(path: &str) -> Result<HashSet<char>, RegExError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) => {
+            let err_line = format!(
+                "Error: Failed to open the alphabet file {}: {}",
+                path, error
+            );
+            return Err(RegExError::FileOpenError(err_line));
+        }
+    };
+    let reader = BufReader::new(file);
+
+    let mut universe = HashSet::new();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(error) => {
+                let err_line = format!(
+                    "Error: Failed to read line number {} in alphabet file {}",
+                    line_number, error
+                );
+                return Err(RegExError::FileReadError(err_line));
+            }
+        };
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let (char_set, end) = parse_char_class(&line, 0)?;
+
+        // The class stops at an unescaped closing bracket, which has to be the end of the line
+        if end != line.chars().count() {
+            return Err(RegExError::InvalidRegexError(line));
+        }
+
+        universe.extend(char_set);
     }
 
-    Ok(regex_list)
+    return Ok(universe);
 }
 
 #[cfg(test)]
 mod regex_tests {
-    use crate::regex::{parse_regex, Base, Factor, Quantifier, RegEx, RegExError, Term};
+    use crate::fa::byte_symbol;
+    use crate::regex::{
+        escape_literal, expand_macros, parse_alternation, parse_char_class, parse_microsyntax_list,
+        parse_with_options, read_alphabet_file, read_microsyntax_file, read_microsyntax_files,
+        regex_to_dot, strip_extended, Base, Factor, ParseOptions, Quantifier, RegEx, RegExError,
+        RegExWarning, RuleFlags, Term, UnmatchedBracket,
+    };
+    use color_eyre::eyre::Result;
+    use std::collections::{HashMap, HashSet};
+
+    // Parse with empty alternation branches rejected, which is what most of the tests exercise
+    fn parse_regex(regex: &str, start: usize) -> Result<(RegEx, usize)> {
+        parse_alternation(regex, start, false)
+    }
 
     // Helper function to simplify match assertions
     fn assert_simple_char(regex: &RegEx, expected_char: char) {
@@ -562,6 +1746,34 @@ mod regex_tests {
         assert_concatenation(&base, 'a', '?');
     }
 
+    #[test]
+    fn test_consecutive_escape_concatenation() {
+        let cases = [
+            ("\\(\\)", "(cat (esc '(') (esc ')'))"),
+            ("\\(a\\)", "(cat (esc '(') (char a) (esc ')'))"),
+            ("\\\\\\n", "(cat (esc '\\\\') (esc n))"),
+            ("\\[\\]*", "(cat (esc [) (star (esc ])))"),
+            ("(\\(\\))+", "(plus (cat (esc '(') (esc ')')))"),
+            ("\\||\\*\\+", "(alt (esc |) (cat (esc *) (esc +)))"),
+        ];
+
+        for (regex, expected) in cases {
+            let (result, end) = parse_regex(regex, 0).unwrap();
+            assert_eq!(result.to_sexp(), expected, "{}", regex);
+            assert_eq!(end, regex.len(), "{}", regex);
+        }
+
+        // The escapes match their literal characters
+        let dfa = crate::dfa::compile("\\(a\\)").unwrap();
+        assert!(dfa.accepts("(a)"));
+        assert!(!dfa.accepts("a"));
+        assert!(!dfa.accepts("\\(a\\)"));
+
+        let dfa = crate::dfa::compile("\\(\\)\\\\").unwrap();
+        assert!(dfa.accepts("()\\"));
+        assert!(!dfa.accepts("()"));
+    }
+
     #[test]
     fn test_regex_alternation() {
         let regex = "a|b";
@@ -578,11 +1790,70 @@ mod regex_tests {
         let result = parse_regex(regex, 0);
         assert!(result.is_err());
         match result.unwrap_err().downcast_ref().unwrap() {
-            RegExError::UnbalancedParenthesisError(_) => assert!(true),
+            RegExError::UnbalancedParenthesisError(_, _) => assert!(true),
             err => assert!(false, "Expected UnbalancedParenthesisError, got {:?}", err),
         }
     }
 
+    #[test]
+    fn test_unmatched_bracket_position() {
+        let cases = [
+            ("(a", UnmatchedBracket::Unclosed('(', 0)),
+            ("a)", UnmatchedBracket::Unexpected(')', 1)),
+            ("[a", UnmatchedBracket::Unclosed('[', 0)),
+            ("((a)", UnmatchedBracket::Unclosed('(', 0)),
+            ("a(b[c", UnmatchedBracket::Unclosed('(', 1)),
+            ("(a))", UnmatchedBracket::Unexpected(')', 3)),
+        ];
+
+        for (regex, expected) in cases {
+            match parse_regex(regex, 0).unwrap_err().downcast_ref().unwrap() {
+                RegExError::UnbalancedParenthesisError(_, bracket) => {
+                    assert_eq!(*bracket, expected, "{}", regex)
+                }
+                err => assert!(false, "Expected UnbalancedParenthesisError, got {:?}", err),
+            }
+        }
+
+        let err = parse_regex("a)", 0).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error: a) has unbalanced parenthesis, unexpected ')' at position 1!"
+        );
+    }
+
+    // A closing bracket or brace outside of a class is a literal, only parentheses must balance
+    #[test]
+    fn test_stray_closing_brackets() {
+        let (result, _) = parse_regex("a}", 0).unwrap();
+        assert_concatenation(&result, 'a', '}');
+
+        let (result, _) = parse_regex("a]", 0).unwrap();
+        assert_concatenation(&result, 'a', ']');
+
+        let (result, _) = parse_regex("}a", 0).unwrap();
+        assert_concatenation(&result, '}', 'a');
+
+        // Parentheses inside a class are members and do not need to balance
+        let (result, _) = parse_regex("[(]", 0).unwrap();
+        match result {
+            RegEx::SimpleRegex(Term::SimpleTerm(Factor::SimpleFactor(
+                Base::CharSet(set),
+                None,
+            ))) => {
+                assert_eq!(set, HashSet::from(['(']))
+            }
+            _ => assert!(false, "Expected character set, got {:?}", result),
+        }
+
+        for regex in ["a)", "[ab", "(a]"] {
+            match parse_regex(regex, 0).unwrap_err().downcast_ref().unwrap() {
+                RegExError::UnbalancedParenthesisError(_, _) => {}
+                err => assert!(false, "Expected UnbalancedParenthesisError, got {:?}", err),
+            }
+        }
+    }
+
     #[test]
     fn test_invalid_escape() {
         let regex = "\\y"; // Assuming \y is not a valid escape
@@ -713,4 +1984,872 @@ mod regex_tests {
             _ => assert!(false),
         }
     }
+
+    // Test for quantified groups followed by concatenation
+    #[test]
+    fn test_quantified_group_concatenation() {
+        let cases = [
+            ("(a|b)+c", Quantifier::Plus),
+            ("(a|b)?c", Quantifier::Question),
+            ("(a|b)*c", Quantifier::Star),
+        ];
+
+        for (regex, expected_quantifier) in cases {
+            let result = parse_regex(regex, 0);
+            assert!(result.is_ok(), "Failed to parse {}", regex);
+            let (result, end) = result.unwrap();
+            assert_eq!(end, regex.len());
+
+            match result {
+                RegEx::SimpleRegex(Term::ConcatTerm(
+                    Factor::SimpleFactor(Base::Character('c'), None),
+                    box_term,
+                )) => match *box_term {
+                    Term::SimpleTerm(Factor::SimpleFactor(Base::Exp(inner_regex), Some(q))) => {
+                        match (&q, &expected_quantifier) {
+                            (Quantifier::Star, Quantifier::Star) => {}
+                            (Quantifier::Plus, Quantifier::Plus) => {}
+                            (Quantifier::Question, Quantifier::Question) => {}
+                            _ => assert!(
+                                false,
+                                "Expected quantifier {:?} on the group in {}, got {:?}",
+                                expected_quantifier, regex, q
+                            ),
+                        }
+                        assert_alternation(&inner_regex, 'a', 'b');
+                    }
+                    term => assert!(false, "Expected quantified group, got {:?}", term),
+                },
+                result => assert!(false, "Expected concatenation, got {:?}", result),
+            }
+        }
+    }
+
+    // Test for a quantified concatenation group followed by another character
+    #[test]
+    fn test_quantified_concat_group() {
+        let regex = "(ab)*d";
+        let result = parse_regex(regex, 0);
+        assert!(result.is_ok());
+        let result = result.unwrap().0;
+
+        match result {
+            RegEx::SimpleRegex(Term::ConcatTerm(
+                Factor::SimpleFactor(Base::Character('d'), None),
+                box_term,
+            )) => match *box_term {
+                Term::SimpleTerm(Factor::SimpleFactor(
+                    Base::Exp(inner_regex),
+                    Some(Quantifier::Star),
+                )) => assert_concatenation(&inner_regex, 'a', 'b'),
+                term => assert!(false, "Expected starred group, got {:?}", term),
+            },
+            result => assert!(false, "Expected concatenation, got {:?}", result),
+        }
+    }
+
+    // Test for a quantified group followed by alternation
+    #[test]
+    fn test_quantified_group_alternation() {
+        let regex = "(a|b)+|c";
+        let result = parse_regex(regex, 0);
+        assert!(result.is_ok());
+        let result = result.unwrap().0;
+
+        match result {
+            RegEx::AlterRegex(
+                Term::SimpleTerm(Factor::SimpleFactor(
+                    Base::Exp(inner_regex),
+                    Some(Quantifier::Plus),
+                )),
+                right,
+            ) => {
+                assert_alternation(&inner_regex, 'a', 'b');
+                assert_simple_char(&right, 'c');
+            }
+            result => assert!(false, "Expected alternation, got {:?}", result),
+        }
+    }
+
+    // Test for quantifiers without a preceding expression
+    #[test]
+    fn test_missing_quantifier_operand() {
+        let cases = [
+            ("*a", '*', 0),
+            ("+b", '+', 0),
+            ("?c", '?', 0),
+            ("a|*b", '*', 2),
+            ("(+a)", '+', 1),
+            ("a**", '*', 2),
+        ];
+
+        for (regex, expected_quantifier, expected_position) in cases {
+            let result = parse_regex(regex, 0);
+            assert!(result.is_err(), "Expected error for {}", regex);
+
+            let err = result.unwrap_err();
+            match err.downcast_ref().unwrap() {
+                RegExError::MissingQuantifierOperand(quantifier, position) => {
+                    assert_eq!(*quantifier, expected_quantifier);
+                    assert_eq!(*position, expected_position);
+                }
+                err => assert!(false, "Expected MissingQuantifierOperand, got {:?}", err),
+            }
+        }
+
+        let err = parse_regex("*a", 0).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error: Quantifier '*' has no preceding expression at position 0!"
+        );
+    }
+
+    // Test for strict mode flagging metacharacters in a character class
+    #[test]
+    fn test_strict_char_class() {
+        let strict = ParseOptions {
+            strict: true,
+            ..ParseOptions::default()
+        };
+
+        let result = parse_with_options("[a|b]", &strict);
+        assert!(result.is_ok());
+        let (_, warnings) = result.unwrap();
+        assert_eq!(
+            warnings,
+            vec![RegExWarning::MetacharacterInClass(
+                "[a|b]".to_string(),
+                '|',
+                2
+            )]
+        );
+
+        // Escaped metacharacters and metacharacters outside classes are fine
+        let (_, warnings) = parse_with_options("[a\\|b]+|(c)*", &strict).unwrap();
+        assert!(warnings.is_empty());
+
+        // The permissive default does not report anything
+        let (_, warnings) = parse_with_options("[a|b]", &ParseOptions::default()).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    // Test for empty alternation branches, which are only accepted when the flag is set
+    #[test]
+    fn test_allow_empty_alternation() {
+        let allow_empty = ParseOptions {
+            allow_empty_alternation: true,
+            ..ParseOptions::default()
+        };
+
+        for regex in ["a|", "(a|)"] {
+            let err = parse_with_options(regex, &ParseOptions::default()).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<RegExError>(),
+                Some(RegExError::InvalidRegexError(_))
+            ));
+
+            let (result, _) = parse_with_options(regex, &allow_empty).unwrap();
+            assert_eq!(result.to_sexp(), "(alt (char a) (empty))");
+        }
+
+        let (result, _) = parse_with_options("|a", &allow_empty).unwrap();
+        assert_eq!(result.to_sexp(), "(alt (empty) (char a))");
+
+        // Branches which are not empty parse the same as without the flag
+        let (result, _) = parse_with_options("(a|b)c", &allow_empty).unwrap();
+        let (expected, _) = parse_regex("(a|b)c", 0).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    // Test for hexadecimal escapes inside and outside character classes
+    #[test]
+    fn test_hex_escape() {
+        let regex = "\\x89";
+        let (result, _) = parse_regex(regex, 0).unwrap();
+        assert_simple_char(&result, byte_symbol(0x89));
+
+        // The bytes up to \x7f are the ASCII characters
+        let (result, _) = parse_regex("\\x41", 0).unwrap();
+        assert_simple_char(&result, 'A');
+
+        let regex = "[\\x00-\\x02\\xff]";
+        let (result, _) = parse_regex(regex, 0).unwrap();
+
+        match result {
+            RegEx::SimpleRegex(Term::SimpleTerm(Factor::SimpleFactor(
+                Base::CharSet(set),
+                None,
+            ))) => {
+                assert_eq!(set.len(), 4);
+                assert!(set.contains(&'\u{0}'));
+                assert!(set.contains(&'\u{1}'));
+                assert!(set.contains(&'\u{2}'));
+                assert!(set.contains(&byte_symbol(0xff)));
+            }
+            _ => assert!(false, "Expected character set, got {:?}", result),
+        }
+
+        // A range from the ASCII characters into the raw bytes skips the characters in between
+        let (result, _) = parse_regex("[\\x7e-\\x81]", 0).unwrap();
+        match result {
+            RegEx::SimpleRegex(Term::SimpleTerm(Factor::SimpleFactor(
+                Base::CharSet(set),
+                None,
+            ))) => {
+                let expected = HashSet::from(['~', '\x7f', byte_symbol(0x80), byte_symbol(0x81)]);
+                assert_eq!(set, expected);
+            }
+            _ => assert!(false, "Expected character set, got {:?}", result),
+        }
+
+        let result = parse_regex("\\xg1", 0);
+        match result.unwrap_err().downcast_ref().unwrap() {
+            RegExError::InvalidEscapeCharacter('x') => {}
+            err => assert!(false, "Expected InvalidEscapeCharacter, got {:?}", err),
+        }
+    }
+
+    // Test that reading several files keeps the priority order across files
+    #[test]
+    fn test_read_microsyntax_files() {
+        let dir = std::env::temp_dir().join(format!("lexviz_mst_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let keywords = dir.join("keywords.mst");
+        let identifiers = dir.join("identifiers.mst");
+        std::fs::write(&keywords, "if|else::KEYWORD\nlet::LET\n").unwrap();
+        std::fs::write(&identifiers, "[a-z]+::IDENTIFIER\n ::SPACE\n").unwrap();
+
+        let paths = vec![
+            keywords.to_string_lossy().to_string(),
+            identifiers.to_string_lossy().to_string(),
+        ];
+        let regex_list = read_microsyntax_files(&paths).unwrap();
+
+        let categories: Vec<&str> = regex_list
+            .iter()
+            .map(|(_, category, _)| category.as_str())
+            .collect();
+        assert_eq!(categories, vec!["KEYWORD", "LET", "IDENTIFIER", "SPACE"]);
+        assert_eq!(regex_list[0].0, "if|else");
+
+        // Without a flags field the fields are kept as they are
+        assert_eq!(regex_list[3].0, " ");
+
+        // A missing file is reported by its path
+        let missing = dir.join("missing.mst").to_string_lossy().to_string();
+        let paths = vec![paths[0].clone(), missing.clone()];
+        match read_microsyntax_files(&paths) {
+            Err(RegExError::FileOpenError(err_line)) => assert!(err_line.contains(&missing)),
+            other => assert!(false, "Expected FileOpenError, got {:?}", other),
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Test parsing of the any character and negated character set bases
+    #[test]
+    fn test_any_char_and_negated_class() {
+        let (result, _) = parse_regex(".", 0).unwrap();
+        match result {
+            RegEx::SimpleRegex(Term::SimpleTerm(Factor::SimpleFactor(Base::AnyChar, None))) => {}
+            _ => assert!(false, "Expected any character, got {:?}", result),
+        }
+
+        let (result, _) = parse_regex("[^a-c]", 0).unwrap();
+        match result {
+            RegEx::SimpleRegex(Term::SimpleTerm(Factor::SimpleFactor(
+                Base::NegatedCharSet(set),
+                None,
+            ))) => {
+                assert_eq!(set, HashSet::from(['a', 'b', 'c']));
+            }
+            _ => assert!(false, "Expected negated character set, got {:?}", result),
+        }
+
+        // An escaped dot is a literal
+        let (result, _) = parse_regex("\\.", 0).unwrap();
+        match result {
+            RegEx::SimpleRegex(Term::SimpleTerm(Factor::SimpleFactor(
+                Base::EscapeCharacter('.'),
+                None,
+            ))) => {}
+            _ => assert!(false, "Expected escaped dot, got {:?}", result),
+        }
+    }
+
+    // Test that syntax trees can be compared and hashed
+    #[test]
+    fn test_syntax_tree_equality() {
+        let (first, _) = parse_regex("(a|b)c*[xyz]", 0).unwrap();
+        let (second, _) = parse_regex("(a|b)c*[zyx]", 0).unwrap();
+        assert_eq!(first, second);
+
+        // Alternation is compared structurally, so the order of the alternatives matters
+        let (forward, _) = parse_regex("(a|b)", 0).unwrap();
+        let (backward, _) = parse_regex("(b|a)", 0).unwrap();
+        assert_ne!(forward, backward);
+
+        let trees: HashSet<RegEx> = [first, second, forward, backward].into_iter().collect();
+        assert_eq!(trees.len(), 3);
+    }
+
+    // Test that the dotall flag and option rewrite '.' to also match a newline
+    #[test]
+    fn test_dotall() {
+        let (any_char, _) = parse_regex(".", 0).unwrap();
+        let (any_char_or_newline, _) = parse_regex("(.|\\n)", 0).unwrap();
+
+        let (result, _) = parse_with_options(".", &ParseOptions::default()).unwrap();
+        assert_eq!(result, any_char);
+
+        let dotall = ParseOptions {
+            dotall: true,
+            ..ParseOptions::default()
+        };
+        let (result, _) = parse_with_options(".", &dotall).unwrap();
+        assert_eq!(result, any_char_or_newline);
+
+        let (result, _) = parse_with_options("(?s).", &ParseOptions::default()).unwrap();
+        assert_eq!(result, any_char_or_newline);
+    }
+
+    #[test]
+    fn test_extended_mode() {
+        let extended = ParseOptions {
+            extended: true,
+            ..ParseOptions::default()
+        };
+
+        let cases = [
+            ("a b  c", "abc"),
+            (
+                "[0-9]+ # the integer part\n (\\. [0-9]+)? # the fraction",
+                "[0-9]+(\\.[0-9]+)?",
+            ),
+            ("[ #]+ | \\ \\#", "[ #]+| #"),
+            ("\\\\ \\n \\]", "\\\\\\n\\]"),
+            ("(?s) a . b", "(?s)a.b"),
+        ];
+
+        for (verbose, compact) in cases {
+            assert_eq!(strip_extended(verbose), compact);
+            let (result, _) = parse_with_options(verbose, &extended).unwrap();
+            let (expected, _) = parse_with_options(compact, &ParseOptions::default()).unwrap();
+            assert_eq!(result, expected, "{:?}", verbose);
+        }
+
+        // The flag turns extended mode on without the option
+        let (result, _) =
+            parse_with_options("(?x) if | else  # keywords", &ParseOptions::default()).unwrap();
+        let (expected, _) = parse_regex("if|else", 0).unwrap();
+        assert_eq!(result, expected);
+
+        // Outside of extended mode whitespace is significant
+        let (result, _) = parse_with_options("a b", &ParseOptions::default()).unwrap();
+        assert_ne!(result, parse_regex("ab", 0).unwrap().0);
+    }
+
+    #[test]
+    fn test_read_alphabet_file() {
+        let universe = read_alphabet_file("test_data/greek.alphabet").unwrap();
+
+        let expected: HashSet<char> = ('a'..='c').chain("αβγ -]\n".chars()).collect();
+        assert_eq!(universe, expected);
+
+        let dfa = crate::dfa::compile_with_universe("[^a]", &universe).unwrap();
+        for ch in ['b', 'c', 'α', 'γ', ' ', ']', '\n'] {
+            assert!(dfa.accepts(&ch.to_string()), "{:?}", ch);
+        }
+        for rejected in ["a", "d", "δ", "bb"] {
+            assert!(!dfa.accepts(rejected), "{:?}", rejected);
+        }
+
+        assert!(matches!(
+            read_alphabet_file("test_data/missing.alphabet"),
+            Err(RegExError::FileOpenError(_))
+        ));
+    }
+
+    // Test escaping literal strings into regular expressions
+    #[test]
+    fn test_escape_literal() {
+        assert_eq!(escape_literal("a+b"), "a\\+b");
+        assert_eq!(escape_literal("+="), "\\+=");
+        assert_eq!(escape_literal("f(x)"), "f\\(x\\)");
+        assert_eq!(escape_literal("a\nb"), "a\\nb");
+        assert_eq!(escape_literal("plain"), "plain");
+
+        let regex = escape_literal("+*");
+        let (result, _) = parse_regex(&regex, 0).unwrap();
+        let expected = RegEx::SimpleRegex(Term::ConcatTerm(
+            Factor::SimpleFactor(Base::EscapeCharacter('*'), None),
+            Box::new(Term::SimpleTerm(Factor::SimpleFactor(
+                Base::EscapeCharacter('+'),
+                None,
+            ))),
+        ));
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_anchors() {
+        let (result, _) = parse_regex("^a$", 0).unwrap();
+        // Concatenations are built with the last factor outermost
+        let expected = RegEx::SimpleRegex(Term::ConcatTerm(
+            Factor::SimpleFactor(Base::EndAnchor, None),
+            Box::new(Term::ConcatTerm(
+                Factor::SimpleFactor(Base::Character('a'), None),
+                Box::new(Term::SimpleTerm(Factor::SimpleFactor(
+                    Base::StartAnchor,
+                    None,
+                ))),
+            )),
+        ));
+        assert_eq!(result, expected);
+
+        // Escaped anchors are literals
+        let (result, _) = parse_regex("\\$", 0).unwrap();
+        match result {
+            RegEx::SimpleRegex(Term::SimpleTerm(Factor::SimpleFactor(
+                Base::EscapeCharacter('$'),
+                None,
+            ))) => {}
+            _ => assert!(false, "Expected escaped dollar, got {:?}", result),
+        }
+        assert_eq!(escape_literal("^$"), "\\^\\$");
+
+        // A word boundary is an assertion, not the literal 'b'
+        let (result, _) = parse_regex("\\bcat\\b", 0).unwrap();
+        assert_eq!(
+            result.to_sexp(),
+            "(cat (boundary) (char c) (char a) (char t) (boundary))"
+        );
+    }
+
+    #[test]
+    fn test_macros() {
+        let mut macros = HashMap::new();
+        macros.insert("DIGIT".to_string(), "[0-9]".to_string());
+        macros.insert("NUMBER".to_string(), "{DIGIT}+".to_string());
+
+        assert_eq!(expand_macros("{DIGIT}+", &macros).unwrap(), "([0-9])+");
+        assert_eq!(
+            expand_macros("{NUMBER}\\.{DIGIT}", &macros).unwrap(),
+            "(([0-9])+)\\.([0-9])"
+        );
+
+        // Braces which do not reference a macro are left alone
+        assert_eq!(expand_macros("{", &macros).unwrap(), "{");
+        assert_eq!(expand_macros("{1}", &macros).unwrap(), "{1}");
+        assert_eq!(expand_macros("\\{DIGIT}", &macros).unwrap(), "\\{DIGIT}");
+        assert_eq!(expand_macros("[{DIGIT}]", &macros).unwrap(), "[{DIGIT}]");
+
+        match expand_macros("{LETTER}", &macros) {
+            Err(RegExError::UndefinedMacro(name)) => assert_eq!(name, "LETTER"),
+            other => assert!(false, "Expected UndefinedMacro, got {:?}", other),
+        }
+
+        macros.insert("A".to_string(), "a{B}".to_string());
+        macros.insert("B".to_string(), "b|{A}".to_string());
+        match expand_macros("{A}", &macros) {
+            Err(RegExError::MacroCycle(name)) => assert_eq!(name, "A"),
+            other => assert!(false, "Expected MacroCycle, got {:?}", other),
+        }
+
+        // An unexpanded reference is parsed as a named class
+        let (result, _) = parse_regex("{DIGIT}", 0).unwrap();
+        match result {
+            RegEx::SimpleRegex(Term::SimpleTerm(Factor::SimpleFactor(
+                Base::NamedClass(name),
+                None,
+            ))) => assert_eq!(name, "DIGIT"),
+            _ => assert!(false, "Expected named class, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_read_microsyntax_file_macros() {
+        let regex_list = read_microsyntax_file("test_data/macros.mst".to_string()).unwrap();
+
+        assert_eq!(
+            regex_list,
+            vec![
+                (
+                    "([a-z])(([a-z])|([0-9]))*".to_string(),
+                    "IDENTIFIER".to_string(),
+                    RuleFlags::default()
+                ),
+                (
+                    "([0-9])+".to_string(),
+                    "NUMBER".to_string(),
+                    RuleFlags::default()
+                ),
+                (
+                    "( |\\n)+".to_string(),
+                    "WHITESPACE".to_string(),
+                    RuleFlags::default()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_to_sexp() {
+        let (result, _) = parse_regex("(a|b)*c", 0).unwrap();
+        assert_eq!(
+            result.to_sexp(),
+            "(cat (star (alt (char a) (char b))) (char c))"
+        );
+
+        let cases = [
+            ("a|b|c", "(alt (char a) (char b) (char c))"),
+            ("a|(b|c)", "(alt (char a) (alt (char b) (char c)))"),
+            ("(a|b)|c", "(alt (alt (char a) (char b)) (char c))"),
+            ("ab+\\n?", "(cat (char a) (plus (char b)) (opt (esc n)))"),
+            ("[cab][^x ]", "(cat (set a b c) (not-set ' ' x))"),
+            ("^.\\($", "(cat (start) (any) (esc '(') (end))"),
+            ("{DIGIT}", "(macro DIGIT)"),
+        ];
+
+        for (regex, expected) in cases {
+            let (result, _) = parse_regex(regex, 0).unwrap();
+            assert_eq!(result.to_sexp(), expected, "{}", regex);
+        }
+    }
+
+    #[test]
+    fn test_regex_to_dot() {
+        let (result, _) = parse_regex("(a|b)*c", 0).unwrap();
+        let dot = regex_to_dot(&result);
+
+        assert!(dot.starts_with("digraph {"));
+        for label in ["cat", "star", "group", "alt", "char a", "char b", "char c"] {
+            assert!(
+                dot.contains(&format!("[ label = \"{}\" ]", label)),
+                "{}",
+                label
+            );
+        }
+        assert_eq!(dot.matches("label").count(), 7);
+        assert_eq!(dot.matches("->").count(), 6);
+        assert!(dot.contains("0 [ label = \"cat\" ]"));
+
+        // Alternation binds loosest, so it is the root without parentheses
+        let (result, _) = parse_regex("ab|c", 0).unwrap();
+        let dot = regex_to_dot(&result);
+        assert!(dot.contains("0 [ label = \"alt\" ]"));
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        // Alternation binds loosest, then concatenation, then the quantifiers
+        let cases = [
+            (
+                "ab|cd",
+                "(alt (cat (char a) (char b)) (cat (char c) (char d)))",
+            ),
+            ("a|bc*", "(alt (char a) (cat (char b) (star (char c))))"),
+            ("ab*|c", "(alt (cat (char a) (star (char b))) (char c))"),
+            ("a|b|cd", "(alt (char a) (char b) (cat (char c) (char d)))"),
+            ("(a|b)c+", "(cat (alt (char a) (char b)) (plus (char c)))"),
+            ("ab?c", "(cat (char a) (opt (char b)) (char c))"),
+            ("[ab]+[01]*", "(cat (plus (set a b)) (star (set 0 1)))"),
+            (
+                "[ab]?[01]+[^x]*",
+                "(cat (opt (set a b)) (plus (set 0 1)) (star (not-set x)))",
+            ),
+        ];
+
+        for (regex, expected) in cases {
+            let (result, _) = parse_regex(regex, 0).unwrap();
+            assert_eq!(result.to_sexp(), expected, "{}", regex);
+        }
+    }
+
+    #[test]
+    fn test_class_shorthands() {
+        let digits: HashSet<char> = ('0'..='9').collect();
+
+        let (set, end) = parse_char_class("[\\d_]", 1).unwrap();
+        let mut expected = digits.clone();
+        expected.insert('_');
+        assert_eq!(set, expected);
+        assert_eq!(end, 4);
+
+        // Shorthands union with ranges, literals and each other, and a dash after one is literal
+        let (set, _) = parse_char_class("[\\d\\s-]", 1).unwrap();
+        assert_eq!(set.len(), 10 + 6 + 1);
+        assert!(set.contains(&'-') && set.contains(&'\t') && set.contains(&'7'));
+        let (set, _) = parse_char_class("[a-c\\d]", 1).unwrap();
+        assert_eq!(set.len(), 13);
+        let (set, _) = parse_char_class("[\\w]", 1).unwrap();
+        assert_eq!(set.len(), 63);
+        assert!(parse_char_class("[a-\\d]", 1).is_err());
+
+        let dfa = crate::dfa::compile("[\\d_]").unwrap();
+        assert!(dfa.accepts("5"));
+        assert!(dfa.accepts("_"));
+        assert!(!dfa.accepts("a"));
+        assert!(!dfa.accepts("5_"));
+
+        // They can also be used on their own or negated
+        let (result, _) = parse_regex("\\d+", 0).unwrap();
+        let expected = RegEx::SimpleRegex(Term::SimpleTerm(Factor::SimpleFactor(
+            Base::CharSet(digits.clone()),
+            Some(Quantifier::Plus),
+        )));
+        assert_eq!(result, expected);
+        let dfa = crate::dfa::compile("[^\\s\\d]").unwrap();
+        assert!(dfa.accepts("a"));
+        assert!(!dfa.accepts(" "));
+        assert!(!dfa.accepts("4"));
+    }
+
+    #[test]
+    fn test_char_class_dash() {
+        // The class contents start after the opening bracket
+        let (set, end) = parse_char_class("[a-]", 1).unwrap();
+        assert_eq!(set, HashSet::from(['a', '-']));
+        assert_eq!(end, 3);
+
+        let (set, end) = parse_char_class("[-a]", 1).unwrap();
+        assert_eq!(set, HashSet::from(['-', 'a']));
+        assert_eq!(end, 3);
+
+        let (set, end) = parse_char_class("[a]", 1).unwrap();
+        assert_eq!(set, HashSet::from(['a']));
+        assert_eq!(end, 2);
+
+        // A dash at the end of an unterminated class is a literal too
+        let (set, _) = parse_char_class("[a-", 1).unwrap();
+        assert_eq!(set, HashSet::from(['a', '-']));
+
+        // A range or escape cut short by the end of the regex is an error, not a panic
+        match parse_char_class("[a-\\", 1) {
+            Err(RegExError::InvalidRegexError(_)) => {}
+            other => assert!(false, "Expected InvalidRegexError, got {:?}", other),
+        }
+
+        let (result, _) = parse_regex("[a-]", 0).unwrap();
+        assert_eq!(result.to_sexp(), "(set - a)");
+    }
+
+    // A small xorshift generator, so the random inputs are reproducible without extra dependencies
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    // Feed random strings built mostly from metacharacters to the parser, which must return a
+    // result for every one of them instead of panicking
+    #[test]
+    fn test_parse_never_panics() {
+        let pieces = [
+            "a", "b", "(", ")", "[", "]", "[^", "|", "*", "+", "?", ".", "\\", "\\x", "4", "f",
+            "g", "n", "-", "^", "$", "{", "}", "{D}", "é", "日", "(?s)", " ",
+        ];
+        let allow_empty = ParseOptions {
+            allow_empty_alternation: true,
+            strict: true,
+            dotall: false,
+            extended: false,
+            case_insensitive: false,
+        };
+        // Inputs which used to panic: a trailing backslash and characters longer than one byte
+        assert!(parse_regex("a\\", 0).is_err());
+        assert!(parse_regex("é|", 0).is_err());
+        let (result, _) = parse_regex("é日+", 0).unwrap();
+        assert_eq!(result.to_sexp(), "(cat (char é) (plus (char 日)))");
+
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+
+        for _ in 0..20000 {
+            let length = (rng.next() % 10) as usize;
+            let regex: String = (0..length)
+                .map(|_| pieces[(rng.next() % pieces.len() as u64) as usize])
+                .collect();
+
+            let result = std::panic::catch_unwind(|| {
+                let _ = parse_with_options(&regex, &ParseOptions::default());
+                let _ = parse_with_options(&regex, &allow_empty);
+                let _ = expand_macros(&regex, &HashMap::new());
+            });
+            assert!(result.is_ok(), "Parsing {:?} panicked", regex);
+        }
+    }
+
+    #[test]
+    fn test_rule_flags() {
+        let flags = RuleFlags::parse("i s").unwrap();
+        assert_eq!(
+            flags,
+            RuleFlags {
+                case_insensitive: true,
+                skip: true,
+                extended: false,
+            }
+        );
+        assert_eq!(flags.apply("if".to_string()), "(?i)if");
+        assert!(matches!(
+            RuleFlags::parse("iq"),
+            Err(RegExError::UnknownRuleFlag('q', _))
+        ));
+
+        let regex_list = read_microsyntax_file("test_data/flags.mst".to_string()).unwrap();
+        let case_insensitive = RuleFlags {
+            case_insensitive: true,
+            ..RuleFlags::default()
+        };
+        let extended = RuleFlags {
+            extended: true,
+            ..RuleFlags::default()
+        };
+        let skip = RuleFlags {
+            skip: true,
+            ..RuleFlags::default()
+        };
+        // The fields of the entries with flags are trimmed, and the s flag keeps the category
+        let expected = vec![
+            (
+                "if|else".to_string(),
+                "KEYWORD".to_string(),
+                case_insensitive,
+            ),
+            (
+                "[a-z]+".to_string(),
+                "IDENTIFIER".to_string(),
+                RuleFlags::default(),
+            ),
+            ("([0-9]) +".to_string(), "NUMBER".to_string(), extended),
+            ("[ \\n]+".to_string(), "WHITESPACE".to_string(), skip),
+        ];
+        assert_eq!(regex_list, expected);
+
+        let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+        let regexes: Vec<&str> = syntax_tree_list
+            .iter()
+            .map(|(regex, _, _, _)| regex.as_str())
+            .collect();
+        assert_eq!(
+            regexes,
+            vec!["(?i)if|else", "[a-z]+", "(?x)([0-9]) +", "[ \\n]+"]
+        );
+        assert_eq!(syntax_tree_list[3].2, "WHITESPACE");
+        assert!(syntax_tree_list[3].3.skip);
+
+        let regex_list = read_microsyntax_file("test_data/literals.mst".to_string()).unwrap();
+        let expected = vec![
+            ("\\|\\|".to_string(), "OR".to_string()),
+            ("&&".to_string(), "AND".to_string()),
+            ("\\+\\+".to_string(), "INCREMENT".to_string()),
+            ("\\{DIGIT\\}\\.\\*".to_string(), "BRACES".to_string()),
+            ("IF".to_string(), "KEYWORD".to_string()),
+            ("[a-z]+".to_string(), "IDENTIFIER".to_string()),
+        ];
+        let pairs: Vec<(String, String)> = regex_list
+            .iter()
+            .map(|(regex, category, _)| (regex.clone(), category.clone()))
+            .collect();
+        assert_eq!(pairs, expected);
+        assert_eq!(regex_list[4].2, case_insensitive);
+
+        match read_microsyntax_file("test_data/unknown_flag.mst".to_string()) {
+            Err(RegExError::UnknownRuleFlag(flag, line)) => {
+                assert_eq!(flag, 'q');
+                assert_eq!(line, "[0-9]+ :: NUMBER :: iq");
+            }
+            result => panic!("Expected an unknown flag error, got {:?}", result),
+        }
+
+        // Case insensitive letters match either case, inside and outside of character sets
+        let (result, _) = parse_regex("[a-b]x|[^c]", 0).unwrap();
+        let (expected, _) = parse_regex("[a-bA-B][xX]|[^cC]", 0).unwrap();
+        let case_insensitive = ParseOptions {
+            case_insensitive: true,
+            ..ParseOptions::default()
+        };
+        let (flagged, _) = parse_with_options("[a-b]x|[^c]", &case_insensitive).unwrap();
+        assert_eq!(flagged, expected);
+        assert_ne!(result, expected);
+
+        // The inline flags can be combined in either order
+        let (inline, _) = parse_with_options("(?i)(?s).x", &ParseOptions::default()).unwrap();
+        let (expected, _) = parse_with_options("(?s).[xX]", &ParseOptions::default()).unwrap();
+        assert_eq!(inline, expected);
+    }
+
+    #[test]
+    fn test_to_std_regex_string() {
+        let allow_empty = ParseOptions {
+            allow_empty_alternation: true,
+            ..ParseOptions::default()
+        };
+
+        let cases = [
+            (
+                "(a|b)*c",
+                "(a|b)*c",
+                vec!["c", "abbac"],
+                vec!["", "ab", "cc"],
+            ),
+            (
+                "[0-9]+\\.[0-9]*",
+                "[0-9]+\\.[0-9]*",
+                vec!["1.", "12.50"],
+                vec!["1", ".5", "1x5"],
+            ),
+            (
+                "[^a-y]z?",
+                "[^a-y]z?",
+                vec!["z", "zz", "!"],
+                vec!["a", "yz"],
+            ),
+            (
+                "\\(\\|\\*\\+\\)\\n",
+                "\\(\\|\\*\\+\\)\\n",
+                vec!["(|*+)\n"],
+                vec!["(|*+)n"],
+            ),
+            (
+                "[-#.&~]+ ",
+                "[\\#\\&\\-\\.\\~]+ ",
+                vec!["-. ", "#&~ "],
+                vec!["-.", "a "],
+            ),
+            ("a(b|)", "a(b|)", vec!["a", "ab"], vec!["abb"]),
+            (".\\b", ".(?-u:\\b)", vec!["a"], vec!["\n"]),
+        ];
+
+        for (pattern, expected, matching, rejected) in cases {
+            let (result, _) = parse_with_options(pattern, &allow_empty).unwrap();
+            let std_pattern = result.to_std_regex_string();
+            assert_eq!(std_pattern, expected, "{}", pattern);
+
+            // Anchoring both ends makes the leftmost match of the regex crate a whole match
+            let std_regex = regex::Regex::new(&format!("^(?:{})$", std_pattern)).unwrap();
+            for input in matching {
+                assert!(
+                    std_regex.is_match(input),
+                    "{} should match {:?}",
+                    pattern,
+                    input
+                );
+            }
+            for input in rejected {
+                assert!(
+                    !std_regex.is_match(input),
+                    "{} should reject {:?}",
+                    pattern,
+                    input
+                );
+            }
+        }
+    }
 }
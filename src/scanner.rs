@@ -4,8 +4,10 @@
 
 use bitvec::vec::BitVec;
 
-use crate::dfa::DFA;
-use crate::fa::{Symbol, FA};
+use crate::dfa::{compile, construct_dfa, construct_minimal_dfa, DFA};
+use crate::fa::{raw_byte, Symbol, FA};
+use crate::nfa::construct_nfa;
+use crate::regex::{parse_microsyntax_list, MicrosyntaxEntry, RuleFlags};
 use color_eyre::eyre::{Report, Result};
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
@@ -141,6 +143,10 @@ impl Buffer {
         ch.try_into().unwrap()
     }
 
+    fn peek_char(&self) -> char {
+        self.source_buffer[self.input_ptr].into()
+    }
+
     fn is_eof(&self) -> bool {
         let ch = self.source_buffer[self.input_ptr];
 
@@ -148,35 +154,244 @@ impl Buffer {
     }
 }
 
+// The input a scanner reads characters from. Rollbacks are counted in characters, so an input
+// which decodes multi byte characters has to step back over whole characters.
+trait ScanInput {
+    fn next_char(&mut self) -> Result<char, ScannerError>;
+    fn peek_char(&self) -> Result<char, ScannerError>;
+    fn is_eof(&self) -> bool;
+    fn rollback(&mut self, amount: usize) -> Result<()>;
+}
+
+impl ScanInput for Buffer {
+    fn next_char(&mut self) -> Result<char, ScannerError> {
+        Ok(Buffer::next_char(self))
+    }
+
+    fn peek_char(&self) -> Result<char, ScannerError> {
+        Ok(Buffer::peek_char(self))
+    }
+
+    fn is_eof(&self) -> bool {
+        Buffer::is_eof(self)
+    }
+
+    fn rollback(&mut self, amount: usize) -> Result<()> {
+        Buffer::rollback(self, amount)
+    }
+}
+
+// Reads the characters of a byte slice, such as a memory mapped file, in place. The bytes are
+// decoded as UTF-8 one character at a time instead of validating the whole input up front.
+struct ByteInput<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl ScanInput for ByteInput<'_> {
+    fn next_char(&mut self) -> Result<char, ScannerError> {
+        let ch = decode_char(self.input, self.position)?;
+        self.position += ch.len_utf8();
+        Ok(ch)
+    }
+
+    fn peek_char(&self) -> Result<char, ScannerError> {
+        decode_char(self.input, self.position)
+    }
+
+    fn is_eof(&self) -> bool {
+        self.position >= self.input.len()
+    }
+
+    fn rollback(&mut self, amount: usize) -> Result<()> {
+        for _ in 0..amount {
+            if self.position == 0 {
+                let err = Report::new(BufferError::RollbackError);
+                return Err(err);
+            }
+            self.position -= 1;
+            // Step back over the continuation bytes of a multi byte character
+            while self.position > 0 && self.input[self.position] & 0xC0 == 0x80 {
+                self.position -= 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Decode the UTF-8 character starting at the byte offset position. A character is at most 4 bytes
+// long, so a sequence which is cut short within that window can only be cut by the end of input.
+fn decode_char(input: &[u8], position: usize) -> Result<char, ScannerError> {
+    let window = &input[position..input.len().min(position + 4)];
+
+    let valid_len = match std::str::from_utf8(window) {
+        Ok(_) => window.len(),
+        Err(err) if err.valid_up_to() > 0 => err.valid_up_to(),
+        Err(err) => match err.error_len() {
+            None => return Err(ScannerError::IncompleteUtf8(position)),
+            Some(_) => return Err(ScannerError::InvalidUtf8(position)),
+        },
+    };
+
+    let decoded = std::str::from_utf8(&window[..valid_len]).unwrap();
+    Ok(decoded.chars().next().unwrap())
+}
+
 /// List of possible errors in the scanner
 #[derive(Debug)]
 pub enum ScannerError {
     /// Found an epsilon transition in a DFA
     EpsilonInDFA,
+    /// Found a ^ or $ anchor or a \b word boundary in a DFA, which the table driven scanner can not
+    /// check
+    AssertionInDFA,
     /// Found a bad token which cannot be categorized in the list of syntactic categories provided
     BadToken(String),
+    /// Found a BEGIN action for a start condition which no microsyntax is tagged with
+    UndefinedStartCondition(String),
+    /// Found two BEGIN actions switching to different conditions for the same (condition, category)
+    ConflictingAction(String, String),
+    /// Found a suffix rule whose pattern matches the empty string, so it would match every lexeme
+    EmptySuffixRule(String),
+    /// Reached the end of the input inside a nested comment
+    UnterminatedComment(String),
+    /// Reached the end of the input inside a string literal
+    UnterminatedString(String),
+    /// Found a byte sequence which is not valid UTF-8 at the given byte offset
+    InvalidUtf8(usize),
+    /// The input ends inside the UTF-8 sequence starting at the given byte offset
+    IncompleteUtf8(usize),
 }
 
 impl std::fmt::Display for ScannerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ScannerError::EpsilonInDFA => write!(f, "Error: Found an epsilon transition in a DFA!"),
+            ScannerError::AssertionInDFA => write!(
+                f,
+                "Error: Anchors and word boundaries are not supported by the scanner, escape ^ and $ as \\^ and \\$ to match the characters!"
+            ),
             ScannerError::BadToken(token) => {
                 write!(f, "Error: Bad token found! {} is not a valid token!", token)
             }
+            ScannerError::UndefinedStartCondition(condition) => {
+                write!(f, "Error: Start condition {} is not defined!", condition)
+            }
+            ScannerError::ConflictingAction(condition, category) => write!(
+                f,
+                "Error: Category {} has conflicting BEGIN actions in start condition {}!",
+                category, condition
+            ),
+            ScannerError::EmptySuffixRule(pattern) => write!(
+                f,
+                "Error: The suffix rule {} matches the empty string, so it would match every token!",
+                pattern
+            ),
+            ScannerError::UnterminatedComment(comment) => {
+                write!(f, "Error: Nested comment {} is never closed!", comment)
+            }
+            ScannerError::UnterminatedString(literal) => {
+                write!(f, "Error: String literal {} is never closed!", literal)
+            }
+            ScannerError::InvalidUtf8(position) => {
+                write!(f, "Error: Invalid UTF-8 sequence at byte {}!", position)
+            }
+            ScannerError::IncompleteUtf8(position) => write!(
+                f,
+                "Error: The input ends inside the UTF-8 sequence at byte {}!",
+                position
+            ),
         }
     }
 }
 
 impl std::error::Error for ScannerError {}
 
+/// Non fatal diagnostics found in the microsyntaxes a scanner is built from
+#[derive(Debug, PartialEq, Eq)]
+pub enum ScannerWarning {
+    /// A microsyntax of the category can match the empty string, which leads to zero length tokens
+    EmptyToken(String),
+}
+
+impl std::fmt::Display for ScannerWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScannerWarning::EmptyToken(category) => write!(
+                f,
+                "Warning: The syntactic category {} can match the empty string, which produces zero length tokens!",
+                category
+            ),
+        }
+    }
+}
+
+/// The policy deciding which of the accept states passed while reading a token ends it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchPolicy {
+    /// The token ends at the last accept state, so the longest lexeme wins. This is the maximal
+    /// munch of flex and the POSIX regex semantics.
+    #[default]
+    LongestMatch,
+    /// The token ends at the accept state of the microsyntax declared first, even if a later
+    /// microsyntax matches a longer lexeme. Among the lexemes of the same microsyntax the longest
+    /// one wins. This follows the rule order, like an ordered alternation in PCRE.
+    FirstMatch,
+}
+
+/// What the scanner does at a character outside the alphabet of its DFA, which can not start any
+/// token. A character of the alphabet without a transition from the start state is still a bad
+/// token under every policy.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum UnknownCharPolicy {
+    /// Stop scanning with a bad token error
+    #[default]
+    Error,
+    /// Drop the character and go on scanning after it
+    SkipOne,
+    /// Report the character as a single character token of the given category, usually
+    /// UNKNOWN_CATEGORY
+    Unknown(String),
+}
+
+/// Mapping of category and the number of its tokens along with the total bytes they consumed, see
+/// Scanner::scan_with_stats
+pub type CategoryStats = HashMap<String, (usize, usize)>;
+
 pub struct Scanner {
     transition_table: Vec<Vec<usize>>, // Matrix of input characters and dfa states
     classifier_table: HashMap<Option<char>, usize>, // Mapping from alphabet to its class id
     token_type_table: HashMap<usize, String>, // Mapping of accept state number and token type
+    // Mapping of accept state number and the closing delimiter of the nested comment it opens
+    nested_comment_table: HashMap<usize, String>,
+    // Mapping of accept state number and the quote and escape characters of the string literal it
+    // opens
+    string_literal_table: HashMap<usize, (char, char)>,
     error_state: usize,
     accept_states: BitVec<u8>,
     start_state: usize,
+    // Tokens never span a newline, which only separates lines
+    line_mode: bool,
+    // Mapping of category and the action applied to the lexemes of its tokens
+    actions: HashMap<String, fn(&str) -> String>,
+    // Mapping of reserved word and the category its identifier tokens are retagged with
+    keyword_table: HashMap<String, String>,
+    // Categories whose runs of adjacent tokens are merged into one
+    coalesce: HashSet<String>,
+    match_policy: MatchPolicy,
+    // Append an EOF token after the last token of the input
+    eof_token: bool,
+    unknown_char_policy: UnknownCharPolicy,
+    // Reverse DFA of every suffix rule, the category of the tokens it applies to and the category
+    // it retags the matching tokens with
+    suffix_rules: Vec<(DFA, String, String)>,
+    // Mapping of accept state number and the priority of its category, 0 being the category
+    // declared first
+    rule_priority_table: HashMap<usize, usize>,
+    // Diagnostics found in the DFA while constructing the scanner
+    warnings: Vec<ScannerWarning>,
+    // Categories of the microsyntaxes with the s flag, skipped as if they were in the skip list
+    skip_categories: HashSet<String>,
 }
 
 impl Scanner {
@@ -185,9 +400,171 @@ impl Scanner {
             transition_table: vec![],
             classifier_table: HashMap::new(),
             token_type_table: HashMap::new(),
+            nested_comment_table: HashMap::new(),
+            string_literal_table: HashMap::new(),
             error_state: 0,
             accept_states: BitVec::new(),
             start_state: 0,
+            line_mode: false,
+            actions: HashMap::new(),
+            keyword_table: HashMap::new(),
+            coalesce: HashSet::new(),
+            match_policy: MatchPolicy::LongestMatch,
+            eof_token: false,
+            unknown_char_policy: UnknownCharPolicy::Error,
+            suffix_rules: Vec::new(),
+            rule_priority_table: HashMap::new(),
+            warnings: Vec::new(),
+            skip_categories: HashSet::new(),
+        }
+    }
+
+    /// Scan the input line by line. In line mode the longest match runs independently on every
+    /// line, so no token can span a newline, and the newlines themselves only separate lines and
+    /// are not reported as tokens.
+    pub fn set_line_mode(&mut self, line_mode: bool) {
+        self.line_mode = line_mode;
+    }
+
+    /// Register an action which transforms the lexeme of every token of the given category before
+    /// it is stored in the Token, such as stripping the quotes of a string or lowercasing a
+    /// keyword. The action only changes the stored text, the input consumed by the token stays the
+    /// same. Registering another action for the same category replaces the previous one.
+    pub fn register_action(&mut self, category: &str, action: fn(&str) -> String) {
+        self.actions.insert(category.to_string(), action);
+    }
+
+    /// Set the table of reserved words, mapping every keyword to its syntactic category. A token
+    /// matched as an IDENTIFIER whose lexeme is in the table gets the keyword's category instead,
+    /// so keywords need no states of their own in the DFA and only the identifier microsyntax has
+    /// to be provided.
+    pub fn set_keyword_table(&mut self, keyword_table: HashMap<String, String>) {
+        self.keyword_table = keyword_table;
+    }
+
+    /// Set the categories whose adjacent tokens are merged. A token directly following a token of
+    /// the same category in the set is appended to it, so a run of whitespace or of error tokens
+    /// is reported as a single token whose lexeme is the concatenation of the run. Tokens skipped
+    /// through the skip list or the newlines of line mode end a run.
+    pub fn set_coalesce(&mut self, categories: HashSet<String>) {
+        self.coalesce = categories;
+    }
+
+    /// Set the policy deciding where a token ends when several microsyntaxes match lexemes of
+    /// different lengths. The default is the longest match. With the first match, the priorities
+    /// come from the order the microsyntaxes were declared in, see DFA::category_priority.
+    pub fn set_match_policy(&mut self, match_policy: MatchPolicy) {
+        self.match_policy = match_policy;
+    }
+
+    /// Append a token of the EOF category with an empty lexeme after the last token of the input,
+    /// as the end of input sentinel many parsers expect. It is never skipped or merged with other
+    /// tokens. Off by default, so the scanner only reports the tokens of the input.
+    pub fn set_eof_token(&mut self, eof_token: bool) {
+        self.eof_token = eof_token;
+    }
+
+    /// Set what happens at a character outside the alphabet of the DFA, such as a stray byte in a
+    /// real world file. By default it is a bad token error. The token of the Unknown policy goes
+    /// through the skip list, the actions and coalescing like any other token.
+    pub fn set_unknown_char_policy(&mut self, unknown_char_policy: UnknownCharPolicy) {
+        self.unknown_char_policy = unknown_char_policy;
+    }
+
+    /// Get the diagnostics found in the DFA the scanner was constructed from, such as a category
+    /// matching the empty string
+    pub fn get_warnings(&self) -> &[ScannerWarning] {
+        &self.warnings
+    }
+
+    /// Add a rule retagging every token of the base category whose lexeme ends with a match of the
+    /// pattern with the given category, such as the extension of a file name. The pattern is
+    /// compiled to a DFA and reversed, and the reverse DFA runs over the lexeme read backwards, so
+    /// the suffix is found without trying every starting position. The rules are tried in the order
+    /// they were added, on the tokens which are not skipped, and the category of a token retagged
+    /// through the keyword table is its base category. Returns an error if the pattern does not
+    /// compile or matches the empty string.
+    pub fn add_suffix_rule(
+        &mut self,
+        pattern: &str,
+        base_category: &str,
+        category: &str,
+    ) -> Result<()> {
+        let dfa = compile(pattern)?;
+        if dfa.accepts("") {
+            let err = Report::new(ScannerError::EmptySuffixRule(pattern.to_string()));
+            return Err(err);
+        }
+        self.suffix_rules.push((
+            dfa.reverse_dfa(),
+            base_category.to_string(),
+            category.to_string(),
+        ));
+        Ok(())
+    }
+
+    // Get the category of the first suffix rule for the base category matching the end of the
+    // lexeme
+    fn suffix_category(&self, lexeme: &str, base_category: &str) -> Option<&String> {
+        if self.suffix_rules.is_empty() {
+            return None;
+        }
+
+        let reversed: String = lexeme.chars().rev().collect();
+        self.suffix_rules
+            .iter()
+            .filter(|(_, base, _)| base == base_category)
+            .find(|(reverse_dfa, _, _)| reverse_dfa.match_span(&reversed).is_some())
+            .map(|(_, _, category)| category)
+    }
+
+    // Consume the next character if it is outside the alphabet and the policy does not make it an
+    // error. When whitespace is skipped, it is consumed first as next_word would skip it anyway.
+    fn read_unknown_char<I: ScanInput>(
+        &self,
+        buffer: &mut I,
+        skip_whitespace: bool,
+    ) -> Result<Option<char>, ScannerError> {
+        if self.unknown_char_policy == UnknownCharPolicy::Error {
+            return Ok(None);
+        }
+
+        while skip_whitespace && !buffer.is_eof() && buffer.peek_char()?.is_whitespace() {
+            if self.line_mode && buffer.peek_char()? == '\n' {
+                return Ok(None);
+            }
+            buffer.next_char()?;
+        }
+
+        if buffer.is_eof()
+            || self
+                .classifier_table
+                .contains_key(&Some(buffer.peek_char()?))
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(buffer.next_char()?))
+    }
+
+    // Check whether the accept state reached while reading a token becomes the new end of the
+    // token, given the accept state it ended at so far
+    fn replaces_accept(&self, accept_state: usize, last_accept_state: i64) -> bool {
+        match self.match_policy {
+            MatchPolicy::LongestMatch => true,
+            MatchPolicy::FirstMatch => {
+                if last_accept_state == -1 {
+                    return true;
+                }
+                // Categories without a priority come after all the others
+                let priority = |state: usize| {
+                    self.rule_priority_table
+                        .get(&state)
+                        .copied()
+                        .unwrap_or(usize::MAX)
+                };
+                priority(accept_state) <= priority(last_accept_state as usize)
+            }
         }
     }
 
@@ -244,6 +621,10 @@ impl Scanner {
     }
 
     fn init_transition_table(&mut self, dfa: &DFA) -> Result<(), ScannerError> {
+        if dfa.has_assertions() {
+            return Err(ScannerError::AssertionInDFA);
+        }
+
         // Add a column for every character in the alphabet and a row for every state in the DFA
 
         let mut alphabet: Vec<char> = dfa.get_alphabet().iter().cloned().collect();
@@ -275,6 +656,8 @@ impl Scanner {
 
                 let symbol = match symbol {
                     Symbol::Epsilon => return Err(ScannerError::EpsilonInDFA),
+                    // Only a ByteDFA matches the raw bytes, a character of the input never does
+                    Symbol::Char(ch) if raw_byte(*ch).is_some() => continue,
                     Symbol::Char(ch) => ch,
                 };
 
@@ -315,14 +698,35 @@ impl Scanner {
 
         for accept_state in accept_states.iter_ones() {
             let category = dfa.get_state(accept_state).get_category();
+
+            if let Some(priority) = dfa.category_priority(category) {
+                self.rule_priority_table.insert(accept_state, priority);
+            }
+
+            if let Some(close) = split_nested_comment(category) {
+                self.nested_comment_table
+                    .insert(accept_state, close.to_string());
+                self.token_type_table
+                    .insert(accept_state, NESTED_COMMENT_CATEGORY.to_string());
+                continue;
+            }
+
+            if let Some((quote, escape)) = split_string_literal(category) {
+                self.string_literal_table
+                    .insert(accept_state, (quote, escape));
+                self.token_type_table
+                    .insert(accept_state, STRING_LITERAL_CATEGORY.to_string());
+                continue;
+            }
+
             self.token_type_table
                 .insert(accept_state, category.to_string());
         }
     }
 
-    fn next_word(
+    fn next_word<I: ScanInput>(
         &self,
-        buffer: &mut Buffer,
+        buffer: &mut I,
         skip_whitespace: bool,
     ) -> Result<(String, String), ScannerError> {
         let mut state = self.start_state; // Keeps track of the current state in the DFA
@@ -353,7 +757,11 @@ impl Scanner {
                 break;
             }
 
-            let ch = buffer.next_char();
+            if self.line_mode && buffer.peek_char()? == '\n' {
+                break; // The token ends with the line
+            }
+
+            let ch = buffer.next_char()?;
 
             if ch == '"' {
                 // If you encounter a double quote, toggle the fact that we are inside a
@@ -379,7 +787,7 @@ impl Scanner {
 
             let is_accept = self.accept_states.get(next_state).unwrap();
 
-            if *is_accept {
+            if *is_accept && self.replaces_accept(next_state, last_accept_state) {
                 last_accept_pos = cur_pos.try_into().unwrap();
                 last_accept_state = next_state.try_into().unwrap();
                 stack.clear();
@@ -404,7 +812,9 @@ impl Scanner {
             let rollback_amount = cur_pos - last_accept;
 
             buffer.rollback(rollback_amount).unwrap();
-            lexeme.truncate(lexeme.len() - rollback_amount);
+            for _ in 0..rollback_amount {
+                lexeme.pop(); // The rollback is counted in characters, not bytes
+            }
 
             while !stack.is_empty() {
                 let (state, pos) = stack.pop_front().unwrap();
@@ -418,6 +828,20 @@ impl Scanner {
 
             let category = self.token_type_table.get(&final_accept_state).unwrap();
 
+            if let Some(close) = self.nested_comment_table.get(&final_accept_state) {
+                read_nested_comment(buffer, &mut lexeme, close)?;
+            }
+
+            if let Some((quote, escape)) = self.string_literal_table.get(&final_accept_state) {
+                read_string_literal(buffer, &mut lexeme, *quote, *escape)?;
+            }
+
+            if category == IDENTIFIER_CATEGORY {
+                if let Some(keyword_category) = self.keyword_table.get(&lexeme) {
+                    return Ok((lexeme, keyword_category.to_string()));
+                }
+            }
+
             Ok((lexeme, category.to_string()))
         }
     }
@@ -426,7 +850,7 @@ impl Scanner {
     /// is true, whitespace tokens are skipped (except within double quotes) without throwing an error.
     /// The skip list is an optional list of syntactic categories that can be skipped without writing
     /// them out to the out_file. By default, any tokens belonging to the SKIP category are not written
-    /// to the outfile.
+    /// to the outfile, and neither are the tokens of the microsyntaxes declared with the s flag.
     pub fn scan(
         &self,
         source_file: String,
@@ -434,48 +858,103 @@ impl Scanner {
         skip_whitespace: bool,
         skip_list: Option<Vec<String>>,
     ) -> Result<Vec<Token>> {
+        let (token_list, _) =
+            self.scan_file(source_file, out_file, skip_whitespace, skip_list, false)?;
+        Ok(token_list)
+    }
+
+    /// Scan the source file like scan, and also return statistics of the tokens produced, mapping
+    /// every category to the number of its tokens and the total number of bytes they consumed. The
+    /// bytes are those of the lexemes as matched, before any action changed them. Skipped tokens
+    /// are not counted, a coalesced run counts as one token and the EOF token consumes no bytes.
+    pub fn scan_with_stats(
+        &self,
+        source_file: String,
+        out_file: Option<String>,
+        skip_whitespace: bool,
+        skip_list: Option<Vec<String>>,
+    ) -> Result<(Vec<Token>, CategoryStats)> {
+        self.scan_file(source_file, out_file, skip_whitespace, skip_list, true)
+    }
+
+    // Scan the source file, collecting the statistics of the tokens only if collect_stats is set
+    fn scan_file(
+        &self,
+        source_file: String,
+        out_file: Option<String>,
+        skip_whitespace: bool,
+        skip_list: Option<Vec<String>>,
+        collect_stats: bool,
+    ) -> Result<(Vec<Token>, CategoryStats)> {
         let source_file = PathBuf::from(source_file);
 
         let write_to_file = out_file.is_some();
 
-        let mut token_list: Vec<Token> = Vec::new();
+        let buffer = Buffer::new(source_file).unwrap();
 
-        let mut buffer = Buffer::new(source_file).unwrap();
+        let mut stream = TokenStream::new(self, buffer, skip_whitespace, skip_list);
+        stream.collect_stats = collect_stats;
 
-        let mut skip_set = HashSet::new();
-        skip_set.insert("SKIP".to_string());
-
-        if skip_list.is_some() {
-            for elem in skip_list.unwrap() {
-                skip_set.insert(elem);
+        let token_list = match stream.by_ref().collect() {
+            Ok(token_list) => token_list,
+            Err(err) => {
+                let err = Report::new(err);
+                return Err(err);
             }
+        };
+
+        if write_to_file {
+            write_tokens(out_file.unwrap(), &token_list);
         }
+        Ok((token_list, stream.stats))
+    }
 
-        while !buffer.is_eof() {
-            let next_word = match self.next_word(&mut buffer, skip_whitespace) {
-                Ok(word) => word,
-                Err(err) => {
-                    let err = Report::new(err);
-                    return Err(err);
-                }
-            };
+    /// Scan a byte slice for tokens, such as the contents of a memory mapped source file, without
+    /// copying it into a buffer. The bytes are decoded as UTF-8 while scanning, and an invalid
+    /// sequence or a sequence cut short by the end of the input is reported as an error. The
+    /// remaining arguments behave the same as in scan.
+    pub fn scan_bytes(
+        &self,
+        input: &[u8],
+        skip_whitespace: bool,
+        skip_list: Option<Vec<String>>,
+    ) -> Result<Vec<Token>> {
+        let input = ByteInput { input, position: 0 };
 
-            if skip_set.contains(&next_word.1) {
-                continue;
-            }
+        self.scan_input(input, skip_whitespace, skip_list)
+    }
 
-            token_list.push(Token::new(next_word.0, next_word.1));
+    /// Scan the string lazily, producing one token at a time instead of collecting them all, so
+    /// large inputs can be processed as they are read and the tokens composed with iterator
+    /// adapters. Whitespace is not skipped and only the SKIP category is left out, the same as
+    /// scan_bytes without a skip list, which collecting the iterator is equal to. The iterator
+    /// ends after the first error.
+    pub fn tokens<'a>(&'a self, input: &'a str) -> Tokens<'a> {
+        let input = ByteInput {
+            input: input.as_bytes(),
+            position: 0,
+        };
+
+        Tokens {
+            stream: TokenStream::new(self, input, false, None),
         }
-        if write_to_file {
-            let mut out_file = File::create(out_file.unwrap()).unwrap();
+    }
 
-            for token in token_list.iter() {
-                let output_line = format!("({}, {})", token.token, token.category);
+    fn scan_input<I: ScanInput>(
+        &self,
+        buffer: I,
+        skip_whitespace: bool,
+        skip_list: Option<Vec<String>>,
+    ) -> Result<Vec<Token>> {
+        let stream = TokenStream::new(self, buffer, skip_whitespace, skip_list);
 
-                let _ = writeln!(out_file, "{}", output_line).unwrap();
+        match stream.collect() {
+            Ok(token_list) => Ok(token_list),
+            Err(err) => {
+                let err = Report::new(err);
+                Err(err)
             }
         }
-        Ok(token_list)
     }
 
     #[allow(dead_code)]
@@ -484,15 +963,549 @@ impl Scanner {
         println!("{:?}", self.classifier_table);
     }
 }
+
+/// The tokens of a string scanned lazily, see Scanner::tokens
+pub struct Tokens<'a> {
+    stream: TokenStream<'a, ByteInput<'a>>,
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = Result<Token, ScannerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.stream.next()
+    }
+}
+
+// Reads the tokens of any input one at a time. A token of a category which is coalesced is held
+// back until the next token shows it can not be merged any further, the other tokens are ready as
+// soon as they are read.
+struct TokenStream<'s, I: ScanInput> {
+    scanner: &'s Scanner,
+    buffer: I,
+    skip_whitespace: bool,
+    skip_set: HashSet<String>,
+    adjacent: bool,         // Whether the last token read ends where the next starts
+    pending: Option<Token>, // The last token read, if it may still be merged with the next one
+    ready: VecDeque<Token>,
+    error: Option<ScannerError>,
+    finished: bool,
+    collect_stats: bool, // Whether the statistics of the tokens are collected
+    stats: CategoryStats,
+    conditions: Option<&'s ConditionalScanner>, // The scanners to switch between on BEGIN actions
+    condition: String,                          // The active start condition
+}
+
+impl<'s, I: ScanInput> TokenStream<'s, I> {
+    fn new(
+        scanner: &'s Scanner,
+        buffer: I,
+        skip_whitespace: bool,
+        skip_list: Option<Vec<String>>,
+    ) -> Self {
+        TokenStream {
+            scanner,
+            buffer,
+            skip_whitespace,
+            skip_set: build_skip_set(skip_list),
+            adjacent: false,
+            pending: None,
+            ready: VecDeque::new(),
+            error: None,
+            finished: false,
+            collect_stats: false,
+            stats: HashMap::new(),
+            conditions: None,
+            condition: INITIAL_CONDITION.to_string(),
+        }
+    }
+
+    // Switch to the scanner of the next start condition if the category has a BEGIN action in the
+    // active one
+    fn begin_condition(&mut self, category: &str) -> Result<(), ScannerError> {
+        let Some(conditions) = self.conditions else {
+            return Ok(());
+        };
+
+        let key = (self.condition.clone(), category.to_string());
+        if let Some(next_condition) = conditions.actions.get(&key) {
+            self.scanner = match conditions.scanners.get(next_condition) {
+                Some(scanner) => scanner,
+                None => {
+                    let condition = next_condition.clone();
+                    return Err(ScannerError::UndefinedStartCondition(condition));
+                }
+            };
+            self.condition = next_condition.clone();
+        }
+
+        Ok(())
+    }
+
+    // Read the next token of the input, or skip over the input which produces none
+    fn advance(&mut self) -> Result<(), ScannerError> {
+        let scanner = self.scanner;
+        let buffer = &mut self.buffer;
+
+        if buffer.is_eof() {
+            self.ready.extend(self.pending.take());
+            if scanner.eof_token {
+                self.ready
+                    .push_back(Token::new(String::new(), EOF_CATEGORY.to_string()));
+                if self.collect_stats {
+                    self.stats.entry(EOF_CATEGORY.to_string()).or_default().0 += 1;
+                }
+            }
+            self.finished = true;
+            return Ok(());
+        }
+
+        if scanner.line_mode && buffer.peek_char()? == '\n' {
+            buffer.next_char()?; // Move on to the next line
+            self.adjacent = false;
+            return Ok(());
+        }
+
+        let (lexeme, category) = match scanner.read_unknown_char(buffer, self.skip_whitespace)? {
+            Some(ch) => match &scanner.unknown_char_policy {
+                UnknownCharPolicy::Unknown(category) => (ch.to_string(), category.clone()),
+                _ => {
+                    self.adjacent = false;
+                    return Ok(());
+                }
+            },
+            None if buffer.is_eof() => return Ok(()),
+            None => scanner.next_word(buffer, self.skip_whitespace)?,
+        };
+
+        self.begin_condition(&category)?;
+
+        if self.skip_set.contains(&category) || scanner.skip_categories.contains(&category) {
+            self.adjacent = false;
+            return Ok(());
+        }
+
+        let category = match scanner.suffix_category(&lexeme, &category) {
+            Some(suffix_category) => suffix_category.clone(),
+            None => category,
+        };
+
+        let consumed = lexeme.len();
+        let lexeme = match scanner.actions.get(&category) {
+            Some(action) => action(&lexeme),
+            None => lexeme,
+        };
+
+        if self.adjacent && scanner.coalesce.contains(&category) {
+            if let Some(last) = self.pending.as_mut() {
+                if last.category == category {
+                    last.token.push_str(&lexeme);
+                    if self.collect_stats {
+                        self.stats.entry(category).or_default().1 += consumed;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+
+        if self.collect_stats {
+            let stats = self.stats.entry(category.clone()).or_default();
+            stats.0 += 1;
+            stats.1 += consumed;
+        }
+
+        self.ready.extend(self.pending.take());
+        let token = Token::new(lexeme, category);
+        if scanner.coalesce.contains(&token.category) {
+            self.pending = Some(token);
+        } else {
+            self.ready.push_back(token);
+        }
+        self.adjacent = true;
+
+        Ok(())
+    }
+}
+
+impl<I: ScanInput> Iterator for TokenStream<'_, I> {
+    type Item = Result<Token, ScannerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(token) = self.ready.pop_front() {
+                return Some(Ok(token));
+            }
+            if let Some(err) = self.error.take() {
+                return Some(Err(err));
+            }
+            if self.finished {
+                return None;
+            }
+
+            if let Err(err) = self.advance() {
+                // The tokens read before the error are still produced first
+                self.ready.extend(self.pending.take());
+                self.error = Some(err);
+                self.finished = true;
+            }
+        }
+    }
+}
+
+/// The category of tokens spanning a whole nested comment. A microsyntax whose category is
+/// `NESTED_COMMENT(close)` matches the opening delimiter of the comment, and the scanner then reads
+/// up to the matching closing delimiter, keeping count of nested opening delimiters.
+pub const NESTED_COMMENT_CATEGORY: &str = "NESTED_COMMENT";
+
+/// The category of tokens spanning a whole string literal. A microsyntax whose category is
+/// `STRING_LITERAL(qe)`, with q the quote character and e the escape character, matches the opening
+/// quote, and the scanner then reads up to the first quote which is not escaped. The escape
+/// character makes the character after it part of the literal, so `"a\"b"` is a single token with
+/// `STRING_LITERAL("\)`.
+pub const STRING_LITERAL_CATEGORY: &str = "STRING_LITERAL";
+
+/// The category of tokens which are looked up in the keyword table of a scanner and retagged with
+/// the keyword's category when their lexeme is a reserved word
+pub const IDENTIFIER_CATEGORY: &str = "IDENTIFIER";
+
+/// The category of the token appended after the last token of the input, see Scanner::set_eof_token
+pub const EOF_CATEGORY: &str = "EOF";
+
+/// The category suggested for the tokens of UnknownCharPolicy::Unknown, see
+/// Scanner::set_unknown_char_policy
+pub const UNKNOWN_CATEGORY: &str = "UNKNOWN";
+
+/// The category of tokens which are always skipped while scanning, as if it were in the skip list
+pub const SKIP_CATEGORY: &str = "SKIP";
+
+// Get the closing delimiter from a "NESTED_COMMENT(close)" category
+fn split_nested_comment(category: &str) -> Option<&str> {
+    category
+        .strip_prefix(NESTED_COMMENT_CATEGORY)
+        .and_then(|rest| rest.strip_prefix('('))
+        .and_then(|rest| rest.strip_suffix(')'))
+        .filter(|close| !close.is_empty())
+}
+
+// Nested comments are not regular, so they are read outside the DFA with a depth counter. The
+// lexeme holds the opening delimiter, and the rest of the comment is appended to it. Delimiters
+// are only looked for after the previous one so that e.g. "/*/" does not also close the comment.
+fn read_nested_comment<I: ScanInput>(
+    buffer: &mut I,
+    lexeme: &mut String,
+    close: &str,
+) -> Result<(), ScannerError> {
+    let open = lexeme.clone();
+    let mut depth = 1;
+    let mut delimiter_end = lexeme.len();
+
+    while depth > 0 {
+        if buffer.is_eof() {
+            return Err(ScannerError::UnterminatedComment(lexeme.clone()));
+        }
+
+        lexeme.push(buffer.next_char()?);
+        let unmatched = &lexeme[delimiter_end..];
+
+        if unmatched.ends_with(close) {
+            depth -= 1;
+            delimiter_end = lexeme.len();
+        } else if !open.is_empty() && unmatched.ends_with(&open) {
+            depth += 1;
+            delimiter_end = lexeme.len();
+        }
+    }
+
+    Ok(())
+}
+
+// Get the quote and escape characters from a "STRING_LITERAL(qe)" category
+fn split_string_literal(category: &str) -> Option<(char, char)> {
+    let delimiters = category
+        .strip_prefix(STRING_LITERAL_CATEGORY)
+        .and_then(|rest| rest.strip_prefix('('))
+        .and_then(|rest| rest.strip_suffix(')'))?;
+
+    let mut chars = delimiters.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(quote), Some(escape), None) if quote != escape => Some((quote, escape)),
+        _ => None,
+    }
+}
+
+// Read the rest of a string literal whose opening quote is the lexeme, up to and including the
+// first closing quote which is not escaped. The escapes are kept in the lexeme as they are.
+fn read_string_literal<I: ScanInput>(
+    buffer: &mut I,
+    lexeme: &mut String,
+    quote: char,
+    escape: char,
+) -> Result<(), ScannerError> {
+    loop {
+        if buffer.is_eof() {
+            return Err(ScannerError::UnterminatedString(lexeme.clone()));
+        }
+
+        let ch = buffer.next_char()?;
+        lexeme.push(ch);
+
+        if ch == quote {
+            return Ok(());
+        }
+
+        if ch == escape {
+            if buffer.is_eof() {
+                return Err(ScannerError::UnterminatedString(lexeme.clone()));
+            }
+            lexeme.push(buffer.next_char()?); // The escaped character never closes the literal
+        }
+    }
+}
+
+fn build_skip_set(skip_list: Option<Vec<String>>) -> HashSet<String> {
+    let mut skip_set = HashSet::new();
+    skip_set.insert(SKIP_CATEGORY.to_string());
+
+    if skip_list.is_some() {
+        for elem in skip_list.unwrap() {
+            skip_set.insert(elem);
+        }
+    }
+    skip_set
+}
+
+fn write_tokens(out_file: String, token_list: &Vec<Token>) {
+    let mut out_file = File::create(out_file).unwrap();
+
+    for token in token_list.iter() {
+        let output_line = format!("({}, {})", token.token, token.category);
+
+        let _ = writeln!(out_file, "{}", output_line).unwrap();
+    }
+}
+
 /// Construct a scanner for the provided DFA. For best performance, always provide the minized DFA.
-pub fn construct_scanner(dfa: &DFA) -> Scanner {
+/// Returns an error if the DFA has anchors or word boundaries, which only the matchers of the DFA
+/// can check.
+///
+/// If the start state of the DFA accepts, the empty lexeme is a token and the scanner reports a
+/// ScannerWarning::EmptyToken for every category whose microsyntax matches the empty string, see
+/// Scanner::get_warnings. Such a microsyntax is almost always a mistake, as `a*` should usually be
+/// `a+` when it is a token.
+pub fn construct_scanner(dfa: &DFA) -> Result<Scanner> {
     let mut scanner = Scanner::new();
 
-    scanner.init_transition_table(dfa).unwrap();
+    if let Err(err) = scanner.init_transition_table(dfa) {
+        let err = Report::new(err);
+        return Err(err);
+    }
 
     scanner.init_token_type_table(dfa);
+    scanner.skip_categories = dfa.get_skip_categories().clone();
+
+    let start_category = dfa.category_of(dfa.get_start_state());
+
+    // The start state is tagged with the category which wins by priority only, so the microsyntax
+    // of every category is checked on its own
+    if start_category.is_some() {
+        let mut categories: Vec<String> = dfa.categories().into_iter().collect();
+        categories.sort_by_key(|category| dfa.category_priority(category).unwrap_or(usize::MAX));
+
+        for category in categories {
+            let matches_empty = match dfa.regex_for_category(&category) {
+                Some(regex) => compile(regex).is_ok_and(|category_dfa| category_dfa.accepts("")),
+                None => start_category == Some(category.as_str()),
+            };
+            if matches_empty {
+                scanner.warnings.push(ScannerWarning::EmptyToken(category));
+            }
+        }
+    }
+
+    return Ok(scanner);
+}
+
+/// The start condition which is active when scanning begins and which untagged microsyntaxes
+/// belong to.
+pub const INITIAL_CONDITION: &str = "INITIAL";
+
+/// A scanner which switches between several DFAs depending on the currently active start
+/// condition, similar to the start conditions in flex. Every start condition has its own scanner
+/// built only from the microsyntaxes which are active in that condition.
+pub struct ConditionalScanner {
+    // Mapping of start condition and its scanner
+    scanners: HashMap<String, Scanner>,
+    // Mapping of (condition, category) to the condition its BEGIN action switches to
+    actions: HashMap<(String, String), String>,
+}
+
+impl ConditionalScanner {
+    /// Scan the source file for tokens, starting in the INITIAL condition. Whenever a token whose
+    /// microsyntax has a BEGIN action is accepted, the scanner switches to the named condition.
+    /// The remaining arguments behave the same as in Scanner::scan, and every token is read with
+    /// the settings of the scanner of the condition active when the token starts.
+    pub fn scan(
+        &self,
+        source_file: String,
+        out_file: Option<String>,
+        skip_whitespace: bool,
+        skip_list: Option<Vec<String>>,
+    ) -> Result<Vec<Token>> {
+        let source_file = PathBuf::from(source_file);
+
+        let buffer = Buffer::new(source_file)?;
+
+        let scanner = match self.scanners.get(INITIAL_CONDITION) {
+            Some(scanner) => scanner,
+            None => {
+                let condition = INITIAL_CONDITION.to_string();
+                let err = Report::new(ScannerError::UndefinedStartCondition(condition));
+                return Err(err);
+            }
+        };
+
+        let mut stream = TokenStream::new(scanner, buffer, skip_whitespace, skip_list);
+        stream.conditions = Some(self);
+
+        let token_list = match stream.collect() {
+            Ok(token_list) => token_list,
+            Err(err) => {
+                let err = Report::new(err);
+                return Err(err);
+            }
+        };
+
+        if let Some(out_file) = out_file {
+            write_tokens(out_file, &token_list);
+        }
+        Ok(token_list)
+    }
+
+    /// Change the settings of the scanner of every start condition, such as the line mode or the
+    /// EOF token, for example `scanner.configure(|scanner| scanner.set_line_mode(true))`.
+    pub fn configure(&mut self, settings: impl Fn(&mut Scanner)) {
+        for scanner in self.scanners.values_mut() {
+            settings(scanner);
+        }
+    }
+
+    /// Get the list of start conditions known to the scanner
+    pub fn get_conditions(&self) -> Vec<&String> {
+        let mut conditions: Vec<&String> = self.scanners.keys().collect();
+        conditions.sort();
+        conditions
+    }
+}
+
+// Split a "<COND1,COND2>regex" entry into its start conditions and the regex itself. Entries
+// without a condition prefix are only active in the INITIAL condition.
+fn split_start_conditions(regex: &str) -> (Vec<String>, String) {
+    let is_condition_name =
+        |name: &str| !name.is_empty() && name.chars().all(|ch| ch.is_alphanumeric() || ch == '_');
+
+    if regex.starts_with('<') {
+        if let Some(end) = regex.find('>') {
+            let names: Vec<&str> = regex[1..end].split(',').map(|name| name.trim()).collect();
+            let rest = &regex[end + 1..];
+
+            if !rest.is_empty() && names.iter().all(|name| is_condition_name(name)) {
+                let names = names.iter().map(|name| name.to_string()).collect();
+                return (names, rest.to_string());
+            }
+        }
+    }
+
+    (vec![INITIAL_CONDITION.to_string()], regex.to_string())
+}
+
+// Split a "CATEGORY BEGIN(COND)" entry into the category and the condition to switch to
+fn split_begin_action(category: &str) -> (String, Option<String>) {
+    let category = category.trim();
+
+    if let Some((name, action)) = category.rsplit_once(char::is_whitespace) {
+        if let Some(condition) = action
+            .strip_prefix("BEGIN(")
+            .and_then(|action| action.strip_suffix(')'))
+        {
+            return (name.trim().to_string(), Some(condition.trim().to_string()));
+        }
+    }
+
+    (category.to_string(), None)
+}
+
+/// Construct a scanner with start conditions from a list of microsyntaxes. A microsyntax can be
+/// restricted to a set of start conditions by prefixing its regex with the condition names, for
+/// example `<STRING>[a-z]+`. Microsyntaxes without a prefix are active in the INITIAL condition.
+/// A category can be followed by a `BEGIN(NAME)` action, for example `QUOTE BEGIN(STRING)`, which
+/// switches the scanner to the named condition after a token of that category is accepted.
+/// Returns an error if no microsyntax is active in the INITIAL condition or if a category has
+/// BEGIN actions to different conditions within the same start condition.
+pub fn construct_conditional_scanner<E: MicrosyntaxEntry>(
+    regex_list: Vec<E>,
+) -> Result<ConditionalScanner> {
+    let mut condition_rules: HashMap<String, Vec<(String, String, RuleFlags)>> = HashMap::new();
+    let mut actions: HashMap<(String, String), String> = HashMap::new();
+
+    condition_rules.insert(INITIAL_CONDITION.to_string(), Vec::new());
+
+    for regex_entry in regex_list {
+        let (regex, category, flags) = regex_entry.into_parts();
+        let (conditions, regex) = split_start_conditions(&regex);
+        let (category, next_condition) = split_begin_action(&category);
+
+        for condition in conditions {
+            if let Some(next_condition) = &next_condition {
+                let key = (condition.clone(), category.clone());
+                match actions.get(&key) {
+                    Some(existing) if existing != next_condition => {
+                        let err = Report::new(ScannerError::ConflictingAction(condition, category));
+                        return Err(err);
+                    }
+                    _ => {
+                        actions.insert(key, next_condition.clone());
+                    }
+                }
+            }
+            condition_rules.entry(condition).or_default().push((
+                regex.clone(),
+                category.clone(),
+                flags,
+            ));
+        }
+    }
+
+    if condition_rules[INITIAL_CONDITION].is_empty() {
+        let err = Report::new(ScannerError::UndefinedStartCondition(
+            INITIAL_CONDITION.to_string(),
+        ));
+        return Err(err);
+    }
+
+    for next_condition in actions.values() {
+        if !condition_rules.contains_key(next_condition) {
+            let err = Report::new(ScannerError::UndefinedStartCondition(
+                next_condition.to_string(),
+            ));
+            return Err(err);
+        }
+    }
+
+    let mut scanners = HashMap::new();
+
+    for (condition, rules) in condition_rules {
+        if rules.is_empty() {
+            continue;
+        }
+        let syntax_tree_list = parse_microsyntax_list(rules)?;
+        let nfa = construct_nfa(syntax_tree_list, false)?;
+        let dfa = construct_dfa(&nfa, false)?;
+        let minimal_dfa = construct_minimal_dfa(&dfa, false);
+
+        scanners.insert(condition, construct_scanner(&minimal_dfa)?);
+    }
 
-    return scanner;
+    Ok(ConditionalScanner { scanners, actions })
 }
 
 #[cfg(test)]
@@ -33,11 +33,11 @@ mod integration_tests_helper {
 
         let nfa = nfa.unwrap();
 
-        let dfa = construct_dfa(&nfa, false);
+        let dfa = construct_dfa(&nfa, false).unwrap();
 
         let dfa = construct_minimal_dfa(&dfa, false);
 
-        let scanner = construct_scanner(&dfa);
+        let scanner = construct_scanner(&dfa).unwrap();
         scanner
     }
 }
@@ -45,7 +45,16 @@ mod integration_tests_helper {
 mod integration_tests {
     use crate::integration_tests_helper::{get_scanner, get_token};
 
-    use lexviz::scanner::{ScannerError, Token};
+    use lexviz::{
+        construct_conditional_scanner, construct_dfa, construct_minimal_dfa, construct_nfa,
+        construct_scanner, parse_microsyntax_list, read_microsyntax_file,
+    };
+
+    use lexviz::scanner::{
+        MatchPolicy, ScannerError, ScannerWarning, Token, UnknownCharPolicy, EOF_CATEGORY,
+        UNKNOWN_CATEGORY,
+    };
+    use std::collections::{HashMap, HashSet};
 
     #[test]
     fn test_valid_invalid_lex() {
@@ -145,4 +154,669 @@ mod integration_tests {
 
         assert_eq!(token_list, expected_list);
     }
+
+    #[test]
+    fn test_start_conditions() {
+        let regex_list = read_microsyntax_file("test_data/conditions.mst".to_string());
+        assert!(regex_list.is_ok());
+
+        let scanner = construct_conditional_scanner(regex_list.unwrap());
+        assert!(scanner.is_ok());
+
+        let mut scanner = scanner.unwrap();
+        assert_eq!(scanner.get_conditions(), vec!["INITIAL", "STRING"]);
+
+        let src_file_path = "test_data/conditions.snek".to_string();
+
+        let mut skip_list: Vec<String> = Vec::new();
+        skip_list.push("WHITESPACE".to_string());
+
+        let token_list = scanner.scan(src_file_path, None, false, Some(skip_list));
+        assert!(token_list.is_ok());
+
+        let token_list = token_list.unwrap();
+
+        // Digits and spaces are only valid tokens inside the STRING condition
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("say", "IDENTIFIER"));
+        expected_list.push(get_token("\"", "QUOTE"));
+        expected_list.push(get_token("hello 42 world", "STRING_BODY"));
+        expected_list.push(get_token("\"", "QUOTE"));
+        expected_list.push(get_token("now", "IDENTIFIER"));
+
+        assert_eq!(token_list, expected_list);
+
+        // The settings of the scanners apply in every start condition
+        scanner.configure(|scanner| {
+            scanner.set_line_mode(true);
+            scanner.set_eof_token(true);
+        });
+
+        let src_file_path = "test_data/conditions.snek".to_string();
+        let skip_list = vec!["WHITESPACE".to_string()];
+
+        let token_list = scanner.scan(src_file_path, None, false, Some(skip_list));
+        assert!(token_list.is_ok());
+
+        expected_list.push(get_token("", "EOF"));
+        assert_eq!(token_list.unwrap(), expected_list);
+    }
+
+    #[test]
+    fn test_undefined_start_condition() {
+        let mut regex_list: Vec<(String, String)> = Vec::new();
+        regex_list.push(("\"".to_string(), "QUOTE BEGIN(COMMENT)".to_string()));
+
+        let scanner = construct_conditional_scanner(regex_list);
+        assert!(scanner.is_err());
+
+        match scanner.err().unwrap().downcast_ref() {
+            Some(ScannerError::UndefinedStartCondition(condition)) => {
+                assert_eq!(condition, "COMMENT")
+            }
+            _ => assert!(false),
+        }
+
+        // Every microsyntax is tagged, so nothing is active in the INITIAL condition
+        let mut regex_list: Vec<(String, String)> = Vec::new();
+        regex_list.push(("<STRING>[a-z]+".to_string(), "WORD".to_string()));
+
+        let scanner = construct_conditional_scanner(regex_list);
+        match scanner.err().unwrap().downcast_ref() {
+            Some(ScannerError::UndefinedStartCondition(condition)) => {
+                assert_eq!(condition, "INITIAL")
+            }
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn test_conflicting_begin_actions() {
+        let mut regex_list: Vec<(String, String)> = Vec::new();
+        regex_list.push(("\"".to_string(), "QUOTE BEGIN(STRING)".to_string()));
+        regex_list.push(("'".to_string(), "QUOTE BEGIN(CHAR)".to_string()));
+        regex_list.push(("<STRING,CHAR>[a-z]+".to_string(), "WORD".to_string()));
+
+        let scanner = construct_conditional_scanner(regex_list);
+        match scanner.err().unwrap().downcast_ref() {
+            Some(ScannerError::ConflictingAction(condition, category)) => {
+                assert_eq!(condition, "INITIAL");
+                assert_eq!(category, "QUOTE");
+            }
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn test_nested_comments() {
+        let scanner = get_scanner("test_data/nested_comments.mst");
+
+        let src_file_path = "test_data/nested_comments.snek".to_string();
+
+        let mut skip_list: Vec<String> = Vec::new();
+        skip_list.push("WHITESPACE".to_string());
+
+        let token_list = scanner.scan(src_file_path, None, false, Some(skip_list));
+        assert!(token_list.is_ok());
+
+        let token_list = token_list.unwrap();
+
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("start", "IDENTIFIER"));
+        expected_list.push(get_token(
+            "/* outer /* inner */ still outer */",
+            "NESTED_COMMENT",
+        ));
+        expected_list.push(get_token("end", "IDENTIFIER"));
+
+        assert_eq!(token_list, expected_list);
+    }
+
+    #[test]
+    fn test_string_literals() {
+        let scanner = get_scanner("test_data/string_literals.mst");
+
+        let src_file_path = "test_data/string_literals.snek".to_string();
+
+        let mut skip_list: Vec<String> = Vec::new();
+        skip_list.push("WHITESPACE".to_string());
+
+        let token_list = scanner.scan(src_file_path, None, false, Some(skip_list.clone()));
+        assert!(token_list.is_ok());
+
+        let token_list = token_list.unwrap();
+
+        // Escaped quotes do not close the literal, and neither does a quote of the other kind
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("say", "IDENTIFIER"));
+        expected_list.push(get_token("\"he said \\\"hi\\\"\"", "STRING_LITERAL"));
+        expected_list.push(get_token("then", "IDENTIFIER"));
+        expected_list.push(get_token("'it\\'s'", "STRING_LITERAL"));
+        expected_list.push(get_token("\"a\\\\\"", "STRING_LITERAL"));
+        expected_list.push(get_token("end", "IDENTIFIER"));
+
+        assert_eq!(token_list, expected_list);
+
+        let result = scanner.scan_bytes(b"say \"unterminated \\\"", false, Some(skip_list));
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<ScannerError>(),
+            Some(ScannerError::UnterminatedString(literal)) if literal == "\"unterminated \\\""
+        ));
+    }
+
+    #[test]
+    fn test_line_mode() {
+        let mut scanner = get_scanner("test_data/lines.mst");
+        scanner.set_line_mode(true);
+
+        let src_file_path = "test_data/lines.snek".to_string();
+
+        let mut skip_list: Vec<String> = Vec::new();
+        skip_list.push("WHITESPACE".to_string());
+
+        let token_list = scanner.scan(src_file_path, None, false, Some(skip_list));
+        assert!(token_list.is_ok());
+
+        let token_list = token_list.unwrap();
+
+        // Every comment stops at the end of its line
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("alpha", "WORD"));
+        expected_list.push(get_token("# first comment", "COMMENT"));
+        expected_list.push(get_token("beta", "WORD"));
+        expected_list.push(get_token("# second", "COMMENT"));
+        expected_list.push(get_token("gamma", "WORD"));
+
+        assert_eq!(token_list, expected_list);
+    }
+
+    #[test]
+    fn test_keyword_table() {
+        let mut scanner = get_scanner("test_data/keywords.mst");
+
+        let mut keyword_table = HashMap::new();
+        for keyword in ["if", "then", "else"] {
+            keyword_table.insert(keyword.to_string(), "KEYWORD".to_string());
+        }
+        scanner.set_keyword_table(keyword_table);
+
+        let src_file_path = "test_data/keywords.snek".to_string();
+
+        let mut skip_list: Vec<String> = Vec::new();
+        skip_list.push("WHITESPACE".to_string());
+
+        let token_list = scanner.scan(src_file_path, None, false, Some(skip_list));
+        assert!(token_list.is_ok());
+
+        let token_list = token_list.unwrap();
+
+        // Only whole identifiers are retagged, so iffy stays an identifier
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("if", "KEYWORD"));
+        expected_list.push(get_token("x1", "IDENTIFIER"));
+        expected_list.push(get_token("then", "KEYWORD"));
+        expected_list.push(get_token("42", "NUMBER"));
+        expected_list.push(get_token("else", "KEYWORD"));
+        expected_list.push(get_token("iffy", "IDENTIFIER"));
+
+        assert_eq!(token_list, expected_list);
+    }
+
+    fn strip_quotes(lexeme: &str) -> String {
+        lexeme.trim_matches('"').to_string()
+    }
+
+    fn to_uppercase(lexeme: &str) -> String {
+        lexeme.to_uppercase()
+    }
+
+    #[test]
+    fn test_token_actions() {
+        let mut scanner = get_scanner("test_data/strings.mst");
+        scanner.register_action("STRING", strip_quotes);
+        scanner.register_action("IDENTIFIER", to_uppercase);
+
+        let src_file_path = "test_data/strings.snek".to_string();
+
+        let mut skip_list: Vec<String> = Vec::new();
+        skip_list.push("WHITESPACE".to_string());
+
+        let token_list = scanner.scan(src_file_path, None, false, Some(skip_list));
+        assert!(token_list.is_ok());
+
+        let token_list = token_list.unwrap();
+
+        // The quotes are still consumed by the string tokens, so scanning carries on after them
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("SAY", "IDENTIFIER"));
+        expected_list.push(get_token("hello world", "STRING"));
+        expected_list.push(get_token("TWICE", "IDENTIFIER"));
+        expected_list.push(get_token("ok", "STRING"));
+
+        assert_eq!(token_list, expected_list);
+    }
+
+    #[test]
+    fn test_scan_bytes() {
+        let scanner = get_scanner("test_data/utf8.mst");
+
+        // Scanning the contents of a file in place, as with a memory mapped file
+        let path =
+            std::env::temp_dir().join(format!("lexviz_scan_bytes_{}.txt", std::process::id()));
+        std::fs::write(&path, "caf\u{e9} \u{e9}\u{e9}\nabc").unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut skip_list: Vec<String> = Vec::new();
+        skip_list.push("WHITESPACE".to_string());
+
+        let token_list = scanner.scan_bytes(&bytes, false, Some(skip_list.clone()));
+        assert!(token_list.is_ok());
+
+        let token_list = token_list.unwrap();
+
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("caf", "WORD"));
+        expected_list.push(get_token("\u{e9}", "ACCENT"));
+        expected_list.push(get_token("\u{e9}\u{e9}", "ACCENT"));
+        expected_list.push(get_token("abc", "WORD"));
+
+        assert_eq!(token_list, expected_list);
+
+        // The input ends in the middle of a two byte sequence
+        let token_list = scanner.scan_bytes(b"abc \xc3", false, Some(skip_list.clone()));
+        match token_list.err().unwrap().downcast_ref() {
+            Some(ScannerError::IncompleteUtf8(position)) => assert_eq!(*position, 4),
+            _ => assert!(false),
+        }
+
+        let token_list = scanner.scan_bytes(b"abc \xff", false, Some(skip_list));
+        match token_list.err().unwrap().downcast_ref() {
+            Some(ScannerError::InvalidUtf8(position)) => assert_eq!(*position, 4),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn test_macros() {
+        let scanner = get_scanner("test_data/macros.mst");
+
+        let src_file_path = "test_data/macros.snek".to_string();
+
+        let mut skip_list: Vec<String> = Vec::new();
+        skip_list.push("WHITESPACE".to_string());
+
+        let token_list = scanner.scan(src_file_path, None, false, Some(skip_list));
+        assert!(token_list.is_ok());
+
+        let token_list = token_list.unwrap();
+
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("x1", "IDENTIFIER"));
+        expected_list.push(get_token("42", "NUMBER"));
+        expected_list.push(get_token("y", "IDENTIFIER"));
+
+        assert_eq!(token_list, expected_list);
+    }
+
+    #[test]
+    fn test_empty_token_warnings() {
+        let build_scanner = |regex_list: Vec<(&str, &str)>| {
+            let regex_list = regex_list
+                .iter()
+                .map(|(regex, category)| (regex.to_string(), category.to_string()))
+                .collect();
+            let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+            let nfa = construct_nfa(syntax_tree_list, false).unwrap();
+            let dfa = construct_dfa(&nfa, false).unwrap();
+            construct_scanner(&construct_minimal_dfa(&dfa, false)).unwrap()
+        };
+
+        // Every category matching the empty string is reported, not only the one the empty lexeme
+        // gets by priority
+        let scanner = build_scanner(vec![("b+", "BS"), ("a*", "AS"), ("c?d?", "CD")]);
+        assert_eq!(
+            scanner.get_warnings(),
+            [
+                ScannerWarning::EmptyToken("AS".to_string()),
+                ScannerWarning::EmptyToken("CD".to_string()),
+            ]
+        );
+        assert_eq!(
+            scanner.get_warnings()[0].to_string(),
+            "Warning: The syntactic category AS can match the empty string, which produces zero length tokens!"
+        );
+
+        // None of the sample microsyntaxes match the empty string
+        let scanner = get_scanner("test_data/sample.mst");
+        assert!(scanner.get_warnings().is_empty());
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let mut scanner = get_scanner("test_data/coalesce.mst");
+
+        let mut coalesce = HashSet::new();
+        coalesce.insert("ERROR".to_string());
+        coalesce.insert("WHITESPACE".to_string());
+        scanner.set_coalesce(coalesce);
+
+        let src_file_path = "test_data/coalesce.snek".to_string();
+
+        let token_list = scanner.scan(src_file_path, None, false, None);
+        assert!(token_list.is_ok());
+
+        let token_list = token_list.unwrap();
+
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("abc", "WORD"));
+        expected_list.push(get_token("  ", "WHITESPACE"));
+        expected_list.push(get_token("@#!", "ERROR"));
+        expected_list.push(get_token("def", "WORD"));
+        expected_list.push(get_token(" ", "WHITESPACE"));
+        expected_list.push(get_token("%%", "ERROR"));
+        expected_list.push(get_token("\n", "WHITESPACE"));
+
+        assert_eq!(token_list, expected_list);
+
+        // A skipped token ends the run, so the errors around it stay apart
+        let mut scanner = get_scanner("test_data/coalesce.mst");
+        let mut coalesce = HashSet::new();
+        coalesce.insert("ERROR".to_string());
+        scanner.set_coalesce(coalesce);
+
+        let token_list = scanner
+            .scan_bytes(b"@ #!", false, Some(vec!["WHITESPACE".to_string()]))
+            .unwrap();
+
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("@", "ERROR"));
+        expected_list.push(get_token("#!", "ERROR"));
+
+        assert_eq!(token_list, expected_list);
+    }
+
+    #[test]
+    fn test_rule_flags() {
+        let scanner = get_scanner("test_data/flags.mst");
+
+        let src_file_path = "test_data/flags.snek".to_string();
+
+        // The whitespace is skipped through its flag without a skip list
+        let token_list = scanner
+            .scan(src_file_path.clone(), None, false, None)
+            .unwrap();
+
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("IF", "KEYWORD"));
+        expected_list.push(get_token("x", "IDENTIFIER"));
+        expected_list.push(get_token("Else", "KEYWORD"));
+        expected_list.push(get_token("42", "NUMBER"));
+        expected_list.push(get_token("else", "KEYWORD"));
+
+        assert_eq!(token_list, expected_list);
+
+        // The scanners of the start conditions honor the flag as well
+        let regex_list = read_microsyntax_file("test_data/flags.mst".to_string()).unwrap();
+        let scanner = construct_conditional_scanner(regex_list).unwrap();
+        let token_list = scanner.scan(src_file_path, None, false, None).unwrap();
+        assert_eq!(token_list, expected_list);
+    }
+
+    #[test]
+    fn test_match_policy() {
+        let mut scanner = get_scanner("test_data/match_policy.mst");
+        let skip_list = Some(vec!["WHITESPACE".to_string()]);
+
+        // The longest match prefers the longer lexemes of the later microsyntaxes
+        let token_list = scanner
+            .scan_bytes(b"== 3.14", false, skip_list.clone())
+            .unwrap();
+
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("==", "EQUALS"));
+        expected_list.push(get_token("3.14", "DECIMAL"));
+
+        assert_eq!(token_list, expected_list);
+
+        // The first match stops at the microsyntax declared first, even though it is shorter
+        scanner.set_match_policy(MatchPolicy::FirstMatch);
+        let token_list = scanner.scan_bytes(b"== 3.14", false, skip_list).unwrap();
+
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("=", "ASSIGN"));
+        expected_list.push(get_token("=", "ASSIGN"));
+        expected_list.push(get_token("3", "INTEGER"));
+        expected_list.push(get_token(".", "DOT"));
+        expected_list.push(get_token("14", "INTEGER"));
+
+        assert_eq!(token_list, expected_list);
+    }
+
+    #[test]
+    fn test_eof_token() {
+        let mut scanner = get_scanner("test_data/sample.mst");
+        let input = "(add1 5)";
+
+        // No EOF token unless it is asked for
+        let token_list = scanner.scan_bytes(input.as_bytes(), false, None).unwrap();
+        assert!(token_list
+            .iter()
+            .all(|token| token.get_category() != EOF_CATEGORY));
+
+        scanner.set_eof_token(true);
+        let token_list = scanner.scan_bytes(input.as_bytes(), false, None).unwrap();
+
+        // The EOF token is last and starts where the lexemes before it end, at the input length
+        let eof = token_list.last().unwrap();
+        assert_eq!(eof, &get_token("", "EOF"));
+        let eof_start: usize = token_list[..token_list.len() - 1]
+            .iter()
+            .map(|token| token.get_token().len())
+            .sum();
+        assert_eq!(eof_start, input.len());
+
+        // Skipped tokens do not drop the EOF token, and empty input still gets one
+        let token_list = scanner
+            .scan_bytes(
+                input.as_bytes(),
+                false,
+                Some(vec!["WHITESPACE".to_string()]),
+            )
+            .unwrap();
+        assert_eq!(token_list.last().unwrap(), &get_token("", "EOF"));
+        let token_list = scanner.scan_bytes(b"", false, None).unwrap();
+        assert_eq!(token_list, vec![get_token("", "EOF")]);
+    }
+
+    #[test]
+    fn test_unknown_char_policy() {
+        let mut scanner = get_scanner("test_data/sample.mst");
+        // Neither $ nor é is in the alphabet of the sample microsyntaxes
+        let input = "(add1 $5)é";
+
+        let err = scanner
+            .scan_bytes(input.as_bytes(), false, None)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScannerError>(),
+            Some(ScannerError::BadToken(_))
+        ));
+
+        scanner.set_unknown_char_policy(UnknownCharPolicy::SkipOne);
+        let token_list = scanner.scan_bytes(input.as_bytes(), false, None).unwrap();
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("(", "LPAREN"));
+        expected_list.push(get_token("add1", "KEYWORD"));
+        expected_list.push(get_token(" ", "WHITESPACE"));
+        expected_list.push(get_token("5", "NUMBER"));
+        expected_list.push(get_token(")", "RPAREN"));
+        assert_eq!(token_list, expected_list);
+
+        scanner.set_unknown_char_policy(UnknownCharPolicy::Unknown(UNKNOWN_CATEGORY.to_string()));
+        let token_list = scanner.scan_bytes(input.as_bytes(), false, None).unwrap();
+        expected_list.insert(3, get_token("$", "UNKNOWN"));
+        expected_list.push(get_token("é", "UNKNOWN"));
+        assert_eq!(token_list, expected_list);
+
+        // The unknown characters are found after skipped whitespace as well
+        let token_list = scanner.scan_bytes(" $ 5".as_bytes(), true, None).unwrap();
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("$", "UNKNOWN"));
+        expected_list.push(get_token("5", "NUMBER"));
+        assert_eq!(token_list, expected_list);
+    }
+
+    #[test]
+    fn test_quantified_char_sets() {
+        let scanner = get_scanner("test_data/quantified_sets.mst");
+
+        let token_list = scanner
+            .scan_bytes(
+                b"abc123 abc x9",
+                false,
+                Some(vec!["WHITESPACE".to_string()]),
+            )
+            .unwrap();
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("abc123", "NAME"));
+        expected_list.push(get_token("abc", "NAME"));
+        expected_list.push(get_token("x9", "NAME"));
+        assert_eq!(token_list, expected_list);
+
+        // A token can not start with the digits of the second set
+        assert!(scanner.scan_bytes(b"123", false, None).is_err());
+    }
+
+    #[test]
+    fn test_lazy_tokens() {
+        let mut scanner = get_scanner("test_data/sample.mst");
+        let input = std::fs::read_to_string("test_data/valid.snek").unwrap();
+
+        let token_list: Result<Vec<Token>, ScannerError> = scanner.tokens(&input).collect();
+        let expected_list = scanner.scan_bytes(input.as_bytes(), false, None).unwrap();
+        assert_eq!(token_list.unwrap(), expected_list);
+
+        // Tokens come one at a time, so the first ones are read without scanning the rest
+        let mut tokens = scanner.tokens("(add1 5) @");
+        assert_eq!(tokens.next().unwrap().unwrap(), get_token("(", "LPAREN"));
+        let rest: Vec<Result<Token, ScannerError>> = tokens.collect();
+        assert!(matches!(rest.last(), Some(Err(ScannerError::BadToken(_)))));
+        assert!(rest[..rest.len() - 1].iter().all(|token| token.is_ok()));
+
+        // Coalesced runs and the EOF token come out the same as when scanning eagerly
+        let mut coalesce = HashSet::new();
+        coalesce.insert("WHITESPACE".to_string());
+        scanner.set_coalesce(coalesce);
+        scanner.set_eof_token(true);
+        let input = "main  \n\t(add1 5)\n\n";
+        let token_list: Result<Vec<Token>, ScannerError> = scanner.tokens(input).collect();
+        let expected_list = scanner.scan_bytes(input.as_bytes(), false, None).unwrap();
+        assert_eq!(token_list.unwrap(), expected_list);
+        assert_eq!(expected_list[1], get_token("  \n\t", "WHITESPACE"));
+    }
+
+    #[test]
+    fn test_scan_with_stats() {
+        let mut scanner = get_scanner("test_data/sample.mst");
+        scanner.register_action("NUMBER", |_| "0".to_string());
+        let src_file_path = "test_data/stats.snek".to_string();
+
+        let (token_list, stats) = scanner
+            .scan_with_stats(src_file_path.clone(), None, false, None)
+            .unwrap();
+        assert_eq!(
+            token_list,
+            scanner
+                .scan(src_file_path.clone(), None, false, None)
+                .unwrap()
+        );
+
+        // The bytes are counted before the action replaces the number
+        let mut expected: HashMap<String, (usize, usize)> = HashMap::new();
+        expected.insert("LPAREN".to_string(), (2, 2));
+        expected.insert("RPAREN".to_string(), (2, 2));
+        expected.insert("KEYWORD".to_string(), (2, 8));
+        expected.insert("NUMBER".to_string(), (1, 2));
+        expected.insert("WHITESPACE".to_string(), (3, 3));
+        assert_eq!(stats, expected);
+
+        // Skipped tokens are not counted
+        let (_, stats) = scanner
+            .scan_with_stats(
+                src_file_path,
+                None,
+                false,
+                Some(vec!["WHITESPACE".to_string()]),
+            )
+            .unwrap();
+        expected.remove("WHITESPACE");
+        assert_eq!(stats, expected);
+    }
+
+    #[test]
+    fn test_literal_rules() {
+        let scanner = get_scanner("test_data/literals.mst");
+
+        let token_list = scanner
+            .scan_bytes(b"||&&++if{DIGIT}.*x", false, None)
+            .unwrap();
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("||", "OR"));
+        expected_list.push(get_token("&&", "AND"));
+        expected_list.push(get_token("++", "INCREMENT"));
+        expected_list.push(get_token("if", "KEYWORD"));
+        expected_list.push(get_token("{DIGIT}.*", "BRACES"));
+        expected_list.push(get_token("x", "IDENTIFIER"));
+        assert_eq!(token_list, expected_list);
+
+        // The literal matches exactly "||", with the pipes not read as an alternation
+        assert!(scanner.scan_bytes(b"|", false, None).is_err());
+        assert!(scanner.scan_bytes(b"|||", false, None).is_err());
+    }
+
+    #[test]
+    fn test_suffix_rules() {
+        let mut scanner = get_scanner("test_data/sample.mst");
+        scanner
+            .add_suffix_rule("_t", "IDENTIFIER", "TYPE_NAME")
+            .unwrap();
+        scanner
+            .add_suffix_rule("_[0-9]+", "IDENTIFIER", "VERSIONED")
+            .unwrap();
+        scanner.add_suffix_rule("1", "IDENTIFIER", "ONE").unwrap();
+        scanner.add_suffix_rule(" ", "WHITESPACE", "SPACE").unwrap();
+
+        let input = "size_t t_x x_12 x12 add1";
+        let token_list = scanner
+            .scan_bytes(
+                input.as_bytes(),
+                false,
+                Some(vec!["WHITESPACE".to_string()]),
+            )
+            .unwrap();
+
+        // Only the end of the lexeme has to match, the rules only apply to tokens of their base
+        // category and the skipped tokens stay skipped
+        let mut expected_list: Vec<Token> = Vec::new();
+        expected_list.push(get_token("size_t", "TYPE_NAME"));
+        expected_list.push(get_token("t_x", "IDENTIFIER"));
+        expected_list.push(get_token("x_12", "VERSIONED"));
+        expected_list.push(get_token("x12", "IDENTIFIER"));
+        expected_list.push(get_token("add1", "KEYWORD"));
+        assert_eq!(token_list, expected_list);
+
+        assert!(scanner
+            .add_suffix_rule("(a", "IDENTIFIER", "BROKEN")
+            .is_err());
+
+        match scanner
+            .add_suffix_rule("_*", "IDENTIFIER", "EMPTY")
+            .unwrap_err()
+            .downcast_ref()
+        {
+            Some(ScannerError::EmptySuffixRule(pattern)) => assert_eq!(pattern, "_*"),
+            _ => assert!(false),
+        }
+    }
 }
@@ -0,0 +1,30 @@
+mod prelude_tests {
+    use lexviz::prelude::*;
+
+    // Compile the microsyntaxes and scan with nothing but the prelude in scope
+    #[test]
+    fn test_prelude_scans() {
+        let regex_list = vec![
+            ("[a-z]+".to_string(), "WORD".to_string()),
+            ("[0-9]+".to_string(), "NUMBER".to_string()),
+            (" ".to_string(), "SKIP".to_string()),
+        ];
+
+        let syntax_tree_list = parse_microsyntax_list(regex_list).unwrap();
+        let nfa: NFA = construct_nfa(syntax_tree_list, false).unwrap();
+        let dfa: DFA = construct_minimal_dfa(&construct_dfa(&nfa, false).unwrap(), false);
+        assert!(dfa.get_num_states() <= nfa.get_num_states());
+
+        let scanner = construct_scanner(&dfa).unwrap();
+        let tokens = scanner.scan_bytes(b"abc 42", false, None).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new("abc".to_string(), "WORD".to_string()),
+                Token::new("42".to_string(), "NUMBER".to_string()),
+            ]
+        );
+
+        assert!(compile("[0-9]+").unwrap().accepts("42"));
+    }
+}